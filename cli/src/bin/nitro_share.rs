@@ -1,2 +1,112 @@
+use std::{io::Write, path::PathBuf};
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use nitro_share_cli::{
+    client::{parse_paste_url, NitroShareClient, UploadFile},
+    encryption::PasteKey,
+};
+use reqwest::Url;
+
+#[derive(Debug, Parser)]
+#[command(name = "nitro_share", about = "Share files with a NitroShare server")]
+struct Cli {
+    /// The address of the server. Such as `https://example.com`
+    #[arg(long, env = "NITRO_SHARE_SERVER")]
+    server: Option<Url>,
+    /// An API token. Required to create pastes
+    #[arg(long, env = "NITRO_SHARE_TOKEN")]
+    token: Option<String>,
+    #[command(subcommand)]
+    command: Command,
+}
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Creates a paste from files. Prints the URL of the paste
+    Create {
+        files: Vec<PathBuf>,
+        #[arg(long, default_value = "Untitled")]
+        name: String,
+        /// The id of the paste. Such as `deploy-runbook`. Requires the custom slug permission
+        #[arg(long)]
+        slug: Option<String>,
+        /// Encrypt the files before they are sent. The key is added to the URL after `#`
+        #[arg(long)]
+        encrypt: bool,
+    },
+    /// Downloads the files of a paste. Encrypted files are decrypted with the key in the URL
+    Get {
+        /// The URL printed by `create`
+        url: String,
+        /// Only download this file
+        #[arg(long)]
+        file: Option<String>,
+        /// Write the files into this directory instead of printing them
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
 #[tokio::main]
-async fn main() {}
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Create {
+            files,
+            name,
+            slug,
+            encrypt,
+        } => {
+            let server = cli
+                .server
+                .context("--server is required to create a paste")?;
+            let client = NitroShareClient::new(server, cli.token);
+            let mut uploads = Vec::with_capacity(files.len());
+            for path in files {
+                let content = tokio::fs::read(&path)
+                    .await
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                uploads.push(UploadFile {
+                    name: path.to_string_lossy().into_owned(),
+                    content,
+                });
+            }
+            let key = encrypt.then(PasteKey::generate);
+            let created = client
+                .create_paste(&name, slug.as_deref(), uploads, key.as_ref())
+                .await?;
+            for error in &created.errors {
+                eprintln!("{}: {}", error.file_name, error.error.message);
+            }
+            println!("{}", created.url);
+        }
+        Command::Get { url, file, output } => {
+            let (server, paste_id, key) = parse_paste_url(&url)?;
+            let client = NitroShareClient::new(server, cli.token);
+            let files = match file {
+                Some(file) => vec![file],
+                None => {
+                    let paste = client.get_paste(&paste_id).await?;
+                    if paste.encrypted && key.is_none() {
+                        bail!("The paste is encrypted. The URL must include the key");
+                    }
+                    paste.files
+                }
+            };
+            for file_name in files {
+                let content = client.get_file(&paste_id, &file_name, key.as_ref()).await?;
+                match &output {
+                    Some(directory) => {
+                        // File names can not escape the directory
+                        let path = directory.join(file_name.replace(['/', '\\'], "_"));
+                        tokio::fs::write(&path, content)
+                            .await
+                            .with_context(|| format!("Failed to write {}", path.display()))?;
+                    }
+                    None => std::io::stdout().write_all(&content)?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
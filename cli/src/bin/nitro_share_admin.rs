@@ -1,2 +1,79 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use nitro_share::{
+    config::ServerConfig,
+    storage::{StorageChecker, StorageProblem},
+};
+use sea_orm::{ConnectOptions, SqlxPostgresConnector};
+
+#[derive(Debug, Parser)]
+#[command(name = "nitro_share_admin", about = "Maintain a NitroShare server")]
+struct Cli {
+    /// The config file of the server
+    #[arg(short, long, env = "NITRO_SHARE_CONFIG")]
+    config: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Finds files that nothing references, rows whose file is missing and incorrect sizes
+    CheckStorage {
+        /// Delete the files that nothing references and correct the sizes
+        #[arg(long)]
+        repair: bool,
+    },
+}
+
 #[tokio::main]
-async fn main() {}
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let config = std::fs::read_to_string(&cli.config)
+        .with_context(|| format!("Failed to read {:?}", cli.config))?;
+    let config: ServerConfig = toml::from_str(&config).context("Invalid config")?;
+    let options: ConnectOptions = config.database.into();
+    let database = SqlxPostgresConnector::connect(options)
+        .await
+        .context("Failed to connect to the database")?;
+    match cli.command {
+        Command::CheckStorage { repair } => {
+            let checker = StorageChecker::new(
+                &database,
+                config.paste_rules.as_ref(),
+                config.image_rules.as_ref(),
+                config.blob_store.as_ref(),
+                &config.storage_check,
+            );
+            let report = checker.check().await?;
+            for problem in &report.problems {
+                println!("{problem}");
+            }
+            let unrepairable = report
+                .problems
+                .iter()
+                .filter(|problem| {
+                    matches!(
+                        problem,
+                        StorageProblem::MissingFile { .. } | StorageProblem::Unreadable { .. }
+                    )
+                })
+                .count();
+            println!(
+                "Checked {} files. Found {} problems. Orphans use {} bytes",
+                report.files_checked,
+                report.problems.len(),
+                report.orphaned_bytes()
+            );
+            if repair {
+                let repaired = checker.repair(&report).await;
+                println!(
+                    "Repaired {} problems. {} failed. {unrepairable} missing or unreadable files can not be repaired",
+                    repaired.repaired, repaired.failed
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,241 @@
+//! A client for the paste API of a NitroShare server
+use std::collections::HashMap;
+
+use anyhow::{bail, Context};
+use common::paste::{encryption::EncryptionEnvelope, file_name::normalize_file_name};
+use reqwest::{
+    header::AUTHORIZATION,
+    multipart::{Form, Part},
+    Client, RequestBuilder, Response, Url,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::encryption::PasteKey;
+
+/// The parts of a paste the client needs
+#[derive(Debug, Deserialize)]
+pub struct Paste {
+    pub encrypted: bool,
+    #[serde(default)]
+    pub files: Vec<String>,
+}
+#[derive(Debug, Deserialize)]
+pub struct NewPasteResponse {
+    pub paste_id: String,
+    pub errors: Vec<FileUploadError>,
+}
+#[derive(Debug, Deserialize)]
+pub struct FileUploadError {
+    pub file_name: String,
+    pub error: ErrorMessage,
+}
+#[derive(Debug, Deserialize)]
+pub struct ErrorMessage {
+    pub message: String,
+}
+#[derive(Debug, Default, Serialize)]
+struct NewFile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encryption: Option<EncryptionEnvelope>,
+}
+#[derive(Debug, Serialize)]
+struct NewPaste<'a> {
+    name: &'a str,
+    encrypted: bool,
+    file_details: HashMap<String, NewFile>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slug: Option<&'a str>,
+}
+/// A file to upload
+pub struct UploadFile {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+/// A paste that was created
+pub struct CreatedPaste {
+    pub paste_id: String,
+    /// The address of the paste. Includes the key if the paste is encrypted
+    pub url: String,
+    pub errors: Vec<FileUploadError>,
+}
+
+pub struct NitroShareClient {
+    client: Client,
+    server: Url,
+    token: Option<String>,
+}
+impl NitroShareClient {
+    pub fn new(server: Url, token: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            server,
+            token,
+        }
+    }
+    /// The address of a path on the server. Each segment is percent encoded
+    fn url(&self, segments: &[&str]) -> anyhow::Result<Url> {
+        let mut url = self.server.clone();
+        url.path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("{} can not be a base URL", self.server))?
+            .pop_if_empty()
+            .extend(segments);
+        Ok(url)
+    }
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.header(AUTHORIZATION, format!("Bearer {token}")),
+            None => request,
+        }
+    }
+    async fn send(&self, request: RequestBuilder) -> anyhow::Result<Response> {
+        let response = self.authorize(request).send().await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            bail!("The server responded with {status}: {body}");
+        }
+        Ok(response)
+    }
+    /// Creates a paste. If `key` is set every file is encrypted before it is sent
+    ///
+    /// `slug` is used as the id of the paste instead of a generated one
+    pub async fn create_paste(
+        &self,
+        name: &str,
+        slug: Option<&str>,
+        files: Vec<UploadFile>,
+        key: Option<&PasteKey>,
+    ) -> anyhow::Result<CreatedPaste> {
+        let mut file_details = HashMap::new();
+        let mut parts = Vec::with_capacity(files.len());
+        for file in files {
+            // The server looks up the details by the normalized name
+            let file_name = normalize_file_name(&file.name)
+                .with_context(|| format!("Invalid file name {:?}", file.name))?;
+            let (content, encryption) = match key {
+                Some(key) => {
+                    let (ciphertext, envelope) = key.encrypt(&file.content)?;
+                    (ciphertext, Some(envelope))
+                }
+                None => (file.content, None),
+            };
+            file_details.insert(file_name.clone(), NewFile { encryption });
+            parts.push(Part::bytes(content).file_name(file_name));
+        }
+        let details = NewPaste {
+            name,
+            encrypted: key.is_some(),
+            file_details,
+            slug,
+        };
+        // The details must be sent before the files
+        let mut form = Form::new().text("details", serde_json::to_string(&details)?);
+        for part in parts {
+            form = form.part("files", part);
+        }
+        let response: NewPasteResponse = self
+            .send(
+                self.client
+                    .post(self.url(&["api", "paste", "new"])?)
+                    .multipart(form),
+            )
+            .await?
+            .json()
+            .await?;
+        let mut url = self.url(&["api", "paste", &response.paste_id])?;
+        url.set_fragment(key.map(PasteKey::to_fragment).as_deref());
+        Ok(CreatedPaste {
+            paste_id: response.paste_id,
+            url: url.to_string(),
+            errors: response.errors,
+        })
+    }
+    pub async fn get_paste(&self, paste_id: &str) -> anyhow::Result<Paste> {
+        Ok(self
+            .send(self.client.get(self.url(&["api", "paste", paste_id])?))
+            .await?
+            .json()
+            .await?)
+    }
+    /// Downloads a file. Encrypted files are decrypted with `key`
+    pub async fn get_file(
+        &self,
+        paste_id: &str,
+        file_name: &str,
+        key: Option<&PasteKey>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let response = self
+            .send(
+                self.client
+                    .get(self.url(&["raw", "paste", paste_id, "file", file_name])?),
+            )
+            .await?;
+        let envelope = EncryptionEnvelope::from_headers(response.headers());
+        let content = response.bytes().await?;
+        match (envelope, key) {
+            (Some(envelope), Some(key)) => key.decrypt(&content, &envelope),
+            (Some(_), None) => bail!("{file_name:?} is encrypted. The URL must include the key"),
+            (None, _) => Ok(content.to_vec()),
+        }
+    }
+}
+
+/// Splits a paste URL printed by [NitroShareClient::create_paste] into the server, the paste id and the key
+pub fn parse_paste_url(url: &str) -> anyhow::Result<(Url, String, Option<PasteKey>)> {
+    let mut url = Url::parse(url)?;
+    let key = url.fragment().map(PasteKey::from_fragment).transpose()?;
+    url.set_fragment(None);
+    let segments: Vec<String> = url
+        .path_segments()
+        .map(|segments| segments.map(str::to_owned).collect())
+        .unwrap_or_default();
+    let Some(position) = segments
+        .windows(2)
+        .position(|window| window[0] == "api" && window[1] == "paste")
+    else {
+        bail!("{url} is not the URL of a paste");
+    };
+    let Some(paste_id) = segments.get(position + 2) else {
+        bail!("{url} is missing the id of the paste");
+    };
+    let paste_id = paste_id.clone();
+    let mut server = url;
+    server.set_path(&segments[..position].join("/"));
+    Ok((server, paste_id, key))
+}
+#[cfg(test)]
+mod tests {
+    use super::parse_paste_url;
+    use crate::encryption::PasteKey;
+
+    #[test]
+    fn paste_url() {
+        let (server, id, key) =
+            parse_paste_url("https://share.example.com/api/paste/abc123").unwrap();
+        assert_eq!(server.as_str(), "https://share.example.com/");
+        assert_eq!(id, "abc123");
+        assert!(key.is_none());
+    }
+    #[test]
+    fn server_in_a_sub_directory() {
+        let (server, id, _) =
+            parse_paste_url("https://example.com/share/api/paste/abc123/file/main.rs").unwrap();
+        assert_eq!(server.as_str(), "https://example.com/share");
+        assert_eq!(id, "abc123");
+    }
+    #[test]
+    fn key_in_fragment() {
+        let key = PasteKey::generate();
+        let url = format!("https://example.com/api/paste/abc123#{}", key.to_fragment());
+        let (server, _, parsed) = parse_paste_url(&url).unwrap();
+        assert_eq!(server.fragment(), None);
+        assert_eq!(parsed.unwrap().to_fragment(), key.to_fragment());
+    }
+    #[test]
+    fn invalid_urls() {
+        assert!(parse_paste_url("not a url").is_err());
+        assert!(parse_paste_url("https://example.com/raw/abc123").is_err());
+        assert!(parse_paste_url("https://example.com/api/paste").is_err());
+        assert!(parse_paste_url("https://example.com/api/paste/abc#short").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Client side encryption of paste files.
+//!
+//! The key is sent to no one. It is placed in the fragment of the paste URL which browsers and HTTP clients do not send
+use aes_gcm::{
+    aead::{Aead, KeyInit, OsRng},
+    AeadCore, Aes256Gcm, Key, Nonce,
+};
+use anyhow::{anyhow, bail, Context};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
+use common::paste::encryption::{EncryptionAlgorithm, EncryptionEnvelope, NONCE_LENGTH};
+
+/// The key shared by every file of an encrypted paste
+pub struct PasteKey(Key<Aes256Gcm>);
+impl PasteKey {
+    pub fn generate() -> Self {
+        Self(Aes256Gcm::generate_key(OsRng))
+    }
+    /// Reads the key from the fragment of a paste URL
+    pub fn from_fragment(fragment: &str) -> anyhow::Result<Self> {
+        let key = URL_SAFE_NO_PAD
+            .decode(fragment)
+            .context("The key is not valid base64")?;
+        if key.len() != 32 {
+            bail!("The key must be 32 bytes");
+        }
+        Ok(Self(*Key::<Aes256Gcm>::from_slice(&key)))
+    }
+    /// The key as it is placed in the URL fragment
+    pub fn to_fragment(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.0)
+    }
+    /// Encrypts a file with a new nonce
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<(Vec<u8>, EncryptionEnvelope)> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = Aes256Gcm::new(&self.0)
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt the file"))?;
+        let envelope = EncryptionEnvelope {
+            algorithm: EncryptionAlgorithm::Aes256Gcm,
+            nonce: STANDARD.encode(nonce),
+        };
+        Ok((ciphertext, envelope))
+    }
+    /// Fails if the key is wrong or the ciphertext has been changed
+    pub fn decrypt(
+        &self,
+        ciphertext: &[u8],
+        envelope: &EncryptionEnvelope,
+    ) -> anyhow::Result<Vec<u8>> {
+        let cipher = match envelope.algorithm {
+            EncryptionAlgorithm::Aes256Gcm => Aes256Gcm::new(&self.0),
+        };
+        let nonce = STANDARD
+            .decode(&envelope.nonce)
+            .context("The nonce is not valid base64")?;
+        if nonce.len() != NONCE_LENGTH {
+            bail!("The nonce must be {NONCE_LENGTH} bytes");
+        }
+        cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext)
+            .map_err(|_| {
+                anyhow!(
+                    "Failed to decrypt the file. The key is wrong or the file has been modified"
+                )
+            })
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::PasteKey;
+
+    #[test]
+    fn round_trip() {
+        let key = PasteKey::generate();
+        let (ciphertext, envelope) = key.encrypt(b"secret paste").unwrap();
+        assert_ne!(ciphertext.as_slice(), b"secret paste");
+        assert_eq!(
+            key.decrypt(&ciphertext, &envelope).unwrap(),
+            b"secret paste"
+        );
+    }
+    #[test]
+    fn every_file_gets_a_new_nonce() {
+        let key = PasteKey::generate();
+        let (_, first) = key.encrypt(b"same").unwrap();
+        let (_, second) = key.encrypt(b"same").unwrap();
+        assert_ne!(first.nonce, second.nonce);
+    }
+    #[test]
+    fn wrong_key() {
+        let (ciphertext, envelope) = PasteKey::generate().encrypt(b"secret").unwrap();
+        assert!(PasteKey::generate()
+            .decrypt(&ciphertext, &envelope)
+            .is_err());
+    }
+    #[test]
+    fn modified_ciphertext() {
+        let key = PasteKey::generate();
+        let (mut ciphertext, envelope) = key.encrypt(b"secret").unwrap();
+        ciphertext[0] ^= 1;
+        assert!(key.decrypt(&ciphertext, &envelope).is_err());
+    }
+    #[test]
+    fn fragment_round_trip() {
+        let key = PasteKey::generate();
+        let (ciphertext, envelope) = key.encrypt(b"secret").unwrap();
+        let parsed = PasteKey::from_fragment(&key.to_fragment()).unwrap();
+        assert_eq!(parsed.decrypt(&ciphertext, &envelope).unwrap(), b"secret");
+    }
+    #[test]
+    fn invalid_fragment() {
+        assert!(PasteKey::from_fragment("not base64!").is_err());
+        // Valid base64 but only 3 bytes
+        assert!(PasteKey::from_fragment("AAAA").is_err());
+    }
+}
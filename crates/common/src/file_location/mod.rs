@@ -1,4 +1,7 @@
-use std::{io, path::PathBuf};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
@@ -22,28 +25,74 @@ pub enum FileLocation {
         ///
         /// If you do decide to modify the file directly.
         ///
-        /// Either run
-        ///
-        /// `nitro-share-admin update-file-size --type <paste|image> --id <id>`.
-        ///
-        /// `nitro-share-admin update-file-size --type <paste|image> --all`
+        /// Run `nitro_share_admin check-storage --repair`
+        size: usize,
+        /// SHA-256 of the content as lowercase hex.
+        /// None if the file could not be read when it was hashed or the size was corrected since
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+    /// A file in the blob store. Shared by every file with the same content
+    ///
+    /// The blob is deleted once nothing references it
+    Blob {
+        /// SHA-256 of the content as lowercase hex
+        hash: String,
+        /// Path to the blob
+        location: PathBuf,
         size: usize,
     },
 }
 
 impl FileLocation {
     pub fn new_local(location: PathBuf, size: usize) -> Self {
-        Self::Local { location, size }
+        Self::Local {
+            location,
+            size,
+            hash: None,
+        }
     }
     pub fn file_size(&self) -> usize {
         match self {
-            Self::Local { size, .. } => *size,
+            Self::Local { size, .. } | Self::Blob { size, .. } => *size,
+        }
+    }
+    /// Replaces the recorded size. For when the file on disk no longer matches it
+    ///
+    /// The hash of a local file is removed because the content has changed
+    pub fn set_file_size(&mut self, file_size: usize) {
+        match self {
+            Self::Local { size, hash, .. } => {
+                *size = file_size;
+                *hash = None;
+            }
+            Self::Blob { size, .. } => *size = file_size,
+        }
+    }
+    /// Where the content is stored on disk
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Local { location, .. } | Self::Blob { location, .. } => location,
+        }
+    }
+    /// The hash of the blob. None if the file is not in the blob store
+    pub fn blob_hash(&self) -> Option<&str> {
+        match self {
+            Self::Blob { hash, .. } => Some(hash),
+            Self::Local { .. } => None,
+        }
+    }
+    /// The SHA-256 of the content. None if it is not known
+    pub fn content_hash(&self) -> Option<&str> {
+        match self {
+            Self::Blob { hash, .. } => Some(hash),
+            Self::Local { hash, .. } => hash.as_deref(),
         }
     }
 
     pub fn get_hash_files(&self) -> Vec<HashFile> {
         match self {
-            Self::Local { .. } => {
+            Self::Local { .. } | Self::Blob { .. } => {
                 let hash_files = Vec::new();
                 hash_files
             }
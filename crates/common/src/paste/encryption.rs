@@ -0,0 +1,69 @@
+//! Pastes encrypted by the client.
+//!
+//! The key never reaches the server. It is kept in the fragment of the paste URL (`#key`).
+//! The server stores the ciphertext as is and returns the [EncryptionEnvelope] needed to decrypt it
+use digestible::Digestible;
+use http::{header::InvalidHeaderValue, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+use typeshare::typeshare;
+use utoipa::ToSchema;
+
+pub const ENCRYPTION_ALGORITHM: HeaderName = HeaderName::from_static("encryption-algorithm");
+pub const ENCRYPTION_NONCE: HeaderName = HeaderName::from_static("encryption-nonce");
+
+/// The length of a nonce for every supported algorithm. In bytes
+pub const NONCE_LENGTH: usize = 12;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    Display,
+    EnumString,
+    ToSchema,
+    Digestible,
+)]
+#[typeshare]
+pub enum EncryptionAlgorithm {
+    /// AES-256 in Galois/Counter Mode. The authentication tag is appended to the ciphertext
+    #[serde(rename = "AES-256-GCM")]
+    #[strum(serialize = "AES-256-GCM")]
+    Aes256Gcm,
+}
+
+/// What a client needs besides the key to decrypt a file
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema, Digestible)]
+#[cfg_attr(feature = "sea-orm", derive(sea_orm::FromJsonQueryResult))]
+#[typeshare]
+pub struct EncryptionEnvelope {
+    pub algorithm: EncryptionAlgorithm,
+    /// Base64 encoded. A new nonce must be used for every revision
+    pub nonce: String,
+}
+impl EncryptionEnvelope {
+    /// Reads the envelope from [ENCRYPTION_ALGORITHM] and [ENCRYPTION_NONCE]
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let algorithm = headers.get(ENCRYPTION_ALGORITHM)?.to_str().ok()?;
+        let nonce = headers.get(ENCRYPTION_NONCE)?.to_str().ok()?;
+        Some(Self {
+            algorithm: algorithm.parse().ok()?,
+            nonce: nonce.to_owned(),
+        })
+    }
+    /// [ENCRYPTION_ALGORITHM] and [ENCRYPTION_NONCE]. Sent with the ciphertext
+    pub fn headers(&self) -> Result<HeaderMap, InvalidHeaderValue> {
+        let mut headers = HeaderMap::with_capacity(2);
+        headers.insert(
+            ENCRYPTION_ALGORITHM,
+            HeaderValue::from_str(&self.algorithm.to_string())?,
+        );
+        headers.insert(ENCRYPTION_NONCE, HeaderValue::from_str(&self.nonce)?);
+        Ok(headers)
+    }
+}
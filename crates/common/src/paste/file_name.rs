@@ -0,0 +1,85 @@
+//! Validation of the names of paste files.
+//!
+//! Names are only displayed. They are never used as a path on disk
+use thiserror::Error;
+
+/// The longest file name in bytes
+pub const MAX_FILE_NAME_LENGTH: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum InvalidFileName {
+    #[error("File name is empty")]
+    Empty,
+    #[error("File name can not be `.` or `..`")]
+    Reserved,
+    #[error("File name contains a control character")]
+    ControlCharacter,
+    #[error("File name is longer than {MAX_FILE_NAME_LENGTH} bytes")]
+    TooLong,
+}
+
+/// Normalizes a file name sent by a client.
+///
+/// Any directories are removed because some clients send the full path of the file.
+/// Then surrounding whitespace is removed
+pub fn normalize_file_name(name: &str) -> Result<String, InvalidFileName> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name).trim();
+    if name.is_empty() {
+        return Err(InvalidFileName::Empty);
+    }
+    if name == "." || name == ".." {
+        return Err(InvalidFileName::Reserved);
+    }
+    if name.chars().any(char::is_control) {
+        return Err(InvalidFileName::ControlCharacter);
+    }
+    if name.len() > MAX_FILE_NAME_LENGTH {
+        return Err(InvalidFileName::TooLong);
+    }
+    Ok(name.to_owned())
+}
+#[cfg(test)]
+mod tests {
+    use super::{normalize_file_name, InvalidFileName, MAX_FILE_NAME_LENGTH};
+
+    #[test]
+    fn directories_are_removed() {
+        assert_eq!(normalize_file_name("main.rs").unwrap(), "main.rs");
+        assert_eq!(normalize_file_name("src/main.rs").unwrap(), "main.rs");
+        assert_eq!(
+            normalize_file_name("C:\\Users\\me\\notes.txt").unwrap(),
+            "notes.txt"
+        );
+        assert_eq!(normalize_file_name("../../etc/passwd").unwrap(), "passwd");
+        assert_eq!(
+            normalize_file_name("  spaced.txt \t").unwrap(),
+            "spaced.txt"
+        );
+    }
+    #[test]
+    fn invalid_names() {
+        assert_eq!(normalize_file_name(""), Err(InvalidFileName::Empty));
+        assert_eq!(normalize_file_name("src/"), Err(InvalidFileName::Empty));
+        assert_eq!(normalize_file_name("   "), Err(InvalidFileName::Empty));
+        assert_eq!(normalize_file_name("."), Err(InvalidFileName::Reserved));
+        assert_eq!(normalize_file_name("a/.."), Err(InvalidFileName::Reserved));
+        assert_eq!(
+            normalize_file_name("new\nline"),
+            Err(InvalidFileName::ControlCharacter)
+        );
+        assert_eq!(
+            normalize_file_name("nul\0"),
+            Err(InvalidFileName::ControlCharacter)
+        );
+    }
+    #[test]
+    fn length_is_counted_in_bytes() {
+        let longest = "a".repeat(MAX_FILE_NAME_LENGTH);
+        assert_eq!(normalize_file_name(&longest).unwrap(), longest);
+        let too_long = "é".repeat(MAX_FILE_NAME_LENGTH / 2 + 1);
+        assert_eq!(
+            normalize_file_name(&too_long),
+            Err(InvalidFileName::TooLong)
+        );
+    }
+}
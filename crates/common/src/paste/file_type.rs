@@ -4,11 +4,13 @@ use http::{
     header::{InvalidHeaderValue, CONTENT_LANGUAGE, CONTENT_TYPE},
     HeaderMap, HeaderName, HeaderValue,
 };
-use mime::{MediaType, JSON};
+use mime::MediaType;
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 use utoipa::ToSchema;
 
+use super::language::Language;
+
 #[inline(always)]
 pub(crate) fn default_mime_type() -> String {
     "text/plain".to_string()
@@ -36,20 +38,21 @@ pub struct FileType {
     pub content_language: Option<String>,
 }
 impl FileType {
-    pub fn new_from_header(content_type: String) -> Self {
-        let mime: MediaType = content_type.parse().unwrap();
+    /// Parses a `Content-Type` header. None if the header is not a valid media type
+    pub fn new_from_header(content_type: &str) -> Option<Self> {
+        let mime: MediaType = content_type.parse().ok()?;
         let charset = mime
             .param("charset")
             .map(|v| v.to_string())
             .unwrap_or(default_charset());
         let mime_type = mime.without_params().to_string();
 
-        Self {
+        Some(Self {
             mime_type,
             charset,
             programming_language: None,
             content_language: None,
-        }
+        })
     }
     pub fn new(mime_type: String, charset: String) -> Self {
         Self {
@@ -59,21 +62,45 @@ impl FileType {
             content_language: None,
         }
     }
-    /// Tries to fill in the missing information about the file type
+    /// Fills in the programming language and mime type from the file name if they were not provided
     pub fn process_file(&mut self, file_name: impl AsRef<str>) {
-        if self.mime_type == JSON {
-            return;
-        }
-        if self.mime_type == "text/plain" && self.programming_language.is_none() {
-            if let Some(lang) = file_name.as_ref().split('.').last() {
-                if lang != "txt" {
-                    // TODO guess programming language
-                } else if lang == "json" {
-                    self.mime_type = JSON.to_string();
+        match &self.programming_language {
+            Some(language) => {
+                if let Some(language) = Language::find(language) {
+                    self.use_mime_type_of(language);
+                }
+            }
+            None => {
+                if let Some(language) = Language::from_file_name(file_name.as_ref()) {
+                    self.set_language(language);
                 }
             }
         }
     }
+    /// Fills in the programming language from a shebang line or modeline if it is still unknown
+    ///
+    /// See [Language::from_content]
+    pub fn process_content(&mut self, content: &str) {
+        if self.programming_language.is_some() {
+            return;
+        }
+        if let Some(language) = Language::from_content(content) {
+            self.set_language(language);
+        }
+    }
+    fn set_language(&mut self, language: &Language) {
+        self.programming_language = Some(language.id.to_owned());
+        self.use_mime_type_of(language);
+    }
+    /// Replaces the mime type if it does not describe the content
+    fn use_mime_type_of(&mut self, language: &Language) {
+        if matches!(
+            self.mime_type.as_str(),
+            "" | "text/plain" | "application/octet-stream"
+        ) {
+            self.mime_type = language.mime_type.to_owned();
+        }
+    }
     pub fn check(&self) -> bool {
         if HeaderValue::from_str(&self.mime_type_string()).is_err() {
             return false;
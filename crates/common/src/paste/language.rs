@@ -0,0 +1,385 @@
+//! Detects the programming language of a paste file.
+//!
+//! Detection is attempted in the following order
+//! 1. Well known file names. Such as `Dockerfile` or `Cargo.lock`
+//! 2. The file extension
+//! 3. The interpreter in a shebang line. `#!/usr/bin/env python3`
+//! 4. Vim and Emacs modelines. `vim: set ft=rust:` or `-*- mode: python -*-`
+
+/// How many lines at the start and end of a file are checked for a modeline
+pub const MODELINE_LINES: usize = 5;
+
+/// A programming language that can be detected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Language {
+    /// The value stored in [FileType::programming_language](super::file_type::FileType::programming_language)
+    pub id: &'static str,
+    /// The name displayed to users
+    pub name: &'static str,
+    /// The mime type used when the client does not provide one
+    pub mime_type: &'static str,
+    /// Without the leading `.`
+    pub extensions: &'static [&'static str],
+    pub file_names: &'static [&'static str],
+    /// Interpreters found in shebang lines
+    pub interpreters: &'static [&'static str],
+    /// Other names used by editors for the language
+    pub aliases: &'static [&'static str],
+}
+impl Language {
+    const fn new(
+        id: &'static str,
+        name: &'static str,
+        extensions: &'static [&'static str],
+    ) -> Self {
+        Self {
+            id,
+            name,
+            mime_type: "text/plain",
+            extensions,
+            file_names: &[],
+            interpreters: &[],
+            aliases: &[],
+        }
+    }
+    const fn mime_type(mut self, mime_type: &'static str) -> Self {
+        self.mime_type = mime_type;
+        self
+    }
+    const fn file_names(mut self, file_names: &'static [&'static str]) -> Self {
+        self.file_names = file_names;
+        self
+    }
+    const fn interpreters(mut self, interpreters: &'static [&'static str]) -> Self {
+        self.interpreters = interpreters;
+        self
+    }
+    const fn aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Finds a language by its id, name or one of its aliases. Ignoring case
+    pub fn find(name: &str) -> Option<&'static Language> {
+        LANGUAGES.iter().find(|language| {
+            language.id.eq_ignore_ascii_case(name)
+                || language.name.eq_ignore_ascii_case(name)
+                || language
+                    .aliases
+                    .iter()
+                    .any(|alias| alias.eq_ignore_ascii_case(name))
+        })
+    }
+    /// Detects the language from a file name. Any directories in the name are ignored
+    pub fn from_file_name(file_name: &str) -> Option<&'static Language> {
+        let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or(file_name);
+        if let Some(language) = Self::find_by_file_name(file_name) {
+            return Some(language);
+        }
+        // `.bashrc` has no extension. It is only a hidden file
+        if let Some((stem, extension)) = file_name
+            .rsplit_once('.')
+            .filter(|(stem, _)| !stem.is_empty())
+        {
+            let language = LANGUAGES.iter().find(|language| {
+                language
+                    .extensions
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(extension))
+            });
+            if language.is_some() {
+                return language;
+            }
+            // Names such as `Dockerfile.prod`
+            let stem = stem.split_once('.').map_or(stem, |(stem, _)| stem);
+            return Self::find_by_file_name(stem);
+        }
+        None
+    }
+    fn find_by_file_name(file_name: &str) -> Option<&'static Language> {
+        LANGUAGES.iter().find(|language| {
+            language
+                .file_names
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(file_name))
+        })
+    }
+    /// Detects the language from the interpreter of a shebang line.
+    ///
+    /// Version suffixes such as `python3.11` are ignored
+    pub fn from_shebang(line: &str) -> Option<&'static Language> {
+        let command = line.strip_prefix("#!")?.trim();
+        let mut parts = command.split_whitespace();
+        let mut interpreter = parts.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            // Skip the options of env. `#!/usr/bin/env -S node --harmony`
+            interpreter = parts.find(|part| !part.starts_with('-') && !part.contains('='))?;
+        }
+        let interpreter = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        LANGUAGES.iter().find(|language| {
+            language
+                .interpreters
+                .iter()
+                .any(|known| *known == interpreter)
+        })
+    }
+    /// Detects the language from a Vim or Emacs modeline
+    pub fn from_modeline(line: &str) -> Option<&'static Language> {
+        emacs_mode(line)
+            .or_else(|| vim_file_type(line))
+            .and_then(Self::find)
+    }
+    /// Detects the language from the content of a file.
+    ///
+    /// Checks the first line for a shebang then the first and last [MODELINE_LINES] lines for a modeline
+    pub fn from_content(content: &str) -> Option<&'static Language> {
+        let first_line = content.lines().next()?;
+        if let Some(language) = Self::from_shebang(first_line) {
+            return Some(language);
+        }
+        let head = content.lines().take(MODELINE_LINES);
+        let tail = content.lines().rev().take(MODELINE_LINES);
+        head.chain(tail).find_map(Self::from_modeline)
+    }
+}
+/// `-*- mode: python -*-` or `-*- python -*-`
+fn emacs_mode(line: &str) -> Option<&str> {
+    let (_, rest) = line.split_once("-*-")?;
+    let (variables, _) = rest.split_once("-*-")?;
+    if !variables.contains(':') {
+        return Some(variables.trim()).filter(|mode| !mode.is_empty());
+    }
+    variables.split(';').find_map(|variable| {
+        let (key, value) = variable.split_once(':')?;
+        key.trim()
+            .eq_ignore_ascii_case("mode")
+            .then_some(value.trim())
+    })
+}
+/// `vim: set ft=rust:` or `vim: filetype=rust`
+fn vim_file_type(line: &str) -> Option<&str> {
+    let start = ["vim:", "vi:", "ex:"].into_iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| {
+                // The marker must start the line or follow whitespace
+                line[..*index]
+                    .chars()
+                    .next_back()
+                    .map_or(true, char::is_whitespace)
+            })
+            .map(|(index, _)| index + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (key, value) = option.split_once('=')?;
+            matches!(key, "ft" | "filetype" | "syntax")
+                .then_some(value)
+                .filter(|value| !value.is_empty())
+        })
+}
+
+/// Every language that can be detected
+pub static LANGUAGES: &[Language] = &[
+    Language::new("bash", "Shell", &["sh", "bash", "zsh", "ksh"])
+        .file_names(&[
+            ".bashrc",
+            ".bash_profile",
+            ".bash_aliases",
+            ".zshrc",
+            ".zprofile",
+            ".profile",
+            "PKGBUILD",
+        ])
+        .interpreters(&["sh", "bash", "zsh", "ksh", "dash", "ash"])
+        .aliases(&["sh", "shell", "zsh", "shell-script"]),
+    Language::new("bat", "Batch", &["bat", "cmd"]).aliases(&["batch", "dosbatch"]),
+    Language::new("c", "C", &["c", "h"]),
+    Language::new(
+        "cpp",
+        "C++",
+        &["cpp", "cc", "cxx", "c++", "hpp", "hh", "hxx"],
+    )
+    .aliases(&["c++"]),
+    Language::new("cs", "C#", &["cs", "csx"]).aliases(&["csharp", "c#"]),
+    Language::new("clojure", "Clojure", &["clj", "cljs", "cljc", "edn"]),
+    Language::new("cmake", "CMake", &["cmake"]).file_names(&["CMakeLists.txt"]),
+    Language::new("css", "CSS", &["css"]).mime_type("text/css"),
+    Language::new("dart", "Dart", &["dart"]),
+    Language::new("diff", "Diff", &["diff", "patch"])
+        .mime_type("text/x-diff")
+        .aliases(&["patch"]),
+    Language::new("dockerfile", "Dockerfile", &["dockerfile"])
+        .file_names(&["Dockerfile", "Containerfile"])
+        .aliases(&["docker"]),
+    Language::new("elixir", "Elixir", &["ex", "exs"]).interpreters(&["elixir"]),
+    Language::new("erlang", "Erlang", &["erl", "hrl"])
+        .file_names(&["rebar.config"])
+        .interpreters(&["escript"]),
+    Language::new("go", "Go", &["go"]).aliases(&["golang"]),
+    Language::new("graphql", "GraphQL", &["graphql", "gql"]),
+    Language::new("groovy", "Groovy", &["groovy", "gradle"])
+        .file_names(&["Jenkinsfile"])
+        .interpreters(&["groovy"]),
+    Language::new("haskell", "Haskell", &["hs", "lhs"]).interpreters(&["runhaskell"]),
+    Language::new("html", "HTML", &["html", "htm", "xhtml"]).mime_type("text/html"),
+    Language::new("ini", "INI", &["ini", "cfg", "conf"])
+        .file_names(&[".editorconfig", ".gitconfig", ".npmrc"])
+        .aliases(&["dosini", "conf"]),
+    Language::new("java", "Java", &["java"]),
+    Language::new("javascript", "JavaScript", &["js", "mjs", "cjs", "jsx"])
+        .mime_type("text/javascript")
+        .interpreters(&["node", "nodejs", "deno", "bun"])
+        .aliases(&["js", "jsx", "js2"]),
+    Language::new("json", "JSON", &["json", "jsonc", "json5"])
+        .mime_type("application/json")
+        .file_names(&[".babelrc", ".eslintrc", "composer.lock", "flake.lock"]),
+    Language::new("julia", "Julia", &["jl"]).interpreters(&["julia"]),
+    Language::new("kotlin", "Kotlin", &["kt", "kts"]),
+    Language::new("latex", "LaTeX", &["tex", "sty", "cls"]).aliases(&["tex"]),
+    Language::new("lua", "Lua", &["lua"]).interpreters(&["lua", "luajit"]),
+    Language::new("makefile", "Makefile", &["mk", "mak"])
+        .file_names(&["Makefile", "GNUmakefile", "makefile"])
+        .interpreters(&["make"])
+        .aliases(&["make"]),
+    Language::new("markdown", "Markdown", &["md", "markdown", "mdown", "mkd"])
+        .mime_type("text/markdown")
+        .aliases(&["md", "gfm"]),
+    Language::new("nix", "Nix", &["nix"]),
+    Language::new("objc", "Objective-C", &["m", "mm"]).aliases(&["objective-c", "objectivec"]),
+    Language::new("perl", "Perl", &["pl", "pm"]).interpreters(&["perl"]),
+    Language::new("php", "PHP", &["php", "phtml"]).interpreters(&["php"]),
+    Language::new("powershell", "PowerShell", &["ps1", "psm1", "psd1"])
+        .interpreters(&["pwsh", "powershell"])
+        .aliases(&["ps1", "pwsh"]),
+    Language::new("protobuf", "Protocol Buffers", &["proto"]).aliases(&["proto"]),
+    Language::new("python", "Python", &["py", "pyw", "pyi"])
+        .file_names(&["SConstruct", "SConscript", "BUILD.bazel", "WORKSPACE"])
+        .interpreters(&["python", "pypy"])
+        .aliases(&["py", "python3"]),
+    Language::new("r", "R", &["r", "rmd"]).interpreters(&["Rscript"]),
+    Language::new("ruby", "Ruby", &["rb", "rake", "gemspec"])
+        .file_names(&["Gemfile", "Rakefile", "Vagrantfile", "Podfile"])
+        .interpreters(&["ruby", "jruby"])
+        .aliases(&["rb"]),
+    Language::new("rust", "Rust", &["rs"])
+        .interpreters(&["run-cargo-script"])
+        .aliases(&["rs"]),
+    Language::new("scala", "Scala", &["scala", "sc", "sbt"]).interpreters(&["scala"]),
+    Language::new("scss", "SCSS", &["scss", "sass"]).aliases(&["sass"]),
+    Language::new("sql", "SQL", &["sql", "psql", "pgsql"]).aliases(&["pgsql", "mysql", "plsql"]),
+    Language::new("svelte", "Svelte", &["svelte"]),
+    Language::new("swift", "Swift", &["swift"]).interpreters(&["swift"]),
+    Language::new("terraform", "Terraform", &["tf", "tfvars", "hcl"]).aliases(&["hcl"]),
+    Language::new("toml", "TOML", &["toml"]).file_names(&["Cargo.lock", "Pipfile", "poetry.lock"]),
+    Language::new("typescript", "TypeScript", &["ts", "mts", "cts", "tsx"])
+        .interpreters(&["ts-node", "tsx"])
+        .aliases(&["ts", "tsx"]),
+    Language::new("vue", "Vue", &["vue"]),
+    Language::new(
+        "xml",
+        "XML",
+        &["xml", "xsd", "xsl", "xslt", "svg", "plist", "csproj"],
+    )
+    .mime_type("application/xml"),
+    Language::new("yaml", "YAML", &["yml", "yaml"])
+        .mime_type("application/yaml")
+        .file_names(&[".clang-format"])
+        .aliases(&["yml"]),
+    Language::new("zig", "Zig", &["zig"]),
+];
+#[cfg(test)]
+mod tests {
+    use super::{Language, LANGUAGES};
+
+    fn id(language: Option<&Language>) -> Option<&'static str> {
+        language.map(|language| language.id)
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        for (index, language) in LANGUAGES.iter().enumerate() {
+            assert!(
+                LANGUAGES[index + 1..]
+                    .iter()
+                    .all(|other| other.id != language.id),
+                "{} is listed twice",
+                language.id
+            );
+        }
+    }
+    #[test]
+    fn find_by_id_name_or_alias() {
+        assert_eq!(id(Language::find("rust")), Some("rust"));
+        assert_eq!(id(Language::find("C++")), Some("cpp"));
+        assert_eq!(id(Language::find("Golang")), Some("go"));
+        assert_eq!(id(Language::find("not a language")), None);
+    }
+    #[test]
+    fn from_file_name() {
+        assert_eq!(id(Language::from_file_name("main.rs")), Some("rust"));
+        assert_eq!(id(Language::from_file_name("README.MD")), Some("markdown"));
+        assert_eq!(
+            id(Language::from_file_name("Dockerfile")),
+            Some("dockerfile")
+        );
+        assert_eq!(
+            id(Language::from_file_name("Dockerfile.prod")),
+            Some("dockerfile")
+        );
+        assert_eq!(
+            id(Language::from_file_name("src/lib/Cargo.lock")),
+            Some("toml")
+        );
+        assert_eq!(id(Language::from_file_name(".bashrc")), Some("bash"));
+        // A hidden file is not an extension
+        assert_eq!(id(Language::from_file_name(".rs")), None);
+        assert_eq!(id(Language::from_file_name("notes")), None);
+    }
+    #[test]
+    fn from_shebang() {
+        assert_eq!(id(Language::from_shebang("#!/bin/bash")), Some("bash"));
+        assert_eq!(
+            id(Language::from_shebang("#!/usr/bin/env python3.11")),
+            Some("python")
+        );
+        assert_eq!(
+            id(Language::from_shebang("#!/usr/bin/env -S node --harmony")),
+            Some("javascript")
+        );
+        assert_eq!(id(Language::from_shebang("# not a shebang")), None);
+        assert_eq!(id(Language::from_shebang("#!/usr/bin/unknown")), None);
+    }
+    #[test]
+    fn from_modeline() {
+        assert_eq!(
+            id(Language::from_modeline("// vim: set ft=rust:")),
+            Some("rust")
+        );
+        assert_eq!(
+            id(Language::from_modeline("# vi: filetype=yaml")),
+            Some("yaml")
+        );
+        assert_eq!(
+            id(Language::from_modeline(
+                "# -*- mode: python; coding: utf-8 -*-"
+            )),
+            Some("python")
+        );
+        assert_eq!(id(Language::from_modeline(";; -*- lua -*-")), Some("lua"));
+        // The marker must not be part of another word
+        assert_eq!(id(Language::from_modeline("envim: ft=rust")), None);
+    }
+    #[test]
+    fn from_content() {
+        assert_eq!(
+            id(Language::from_content("#!/usr/bin/env ruby\nputs 1\n")),
+            Some("ruby")
+        );
+        let modeline_at_end = "fn main() {}\n\n\n\n\n\n\n// vim: ft=rust\n";
+        assert_eq!(id(Language::from_content(modeline_at_end)), Some("rust"));
+        let modeline_in_middle = "a\nb\nc\nd\ne\n// vim: ft=rust\nf\ng\nh\ni\nj\n";
+        assert_eq!(id(Language::from_content(modeline_in_middle)), None);
+        assert_eq!(id(Language::from_content("")), None);
+    }
+}
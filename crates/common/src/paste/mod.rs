@@ -1 +1,4 @@
+pub mod encryption;
+pub mod file_name;
 pub mod file_type;
+pub mod language;
@@ -11,6 +11,11 @@ pub enum Visibility {
     Private {
         visible_to: Vec<i64>,
     },
+    /// Visible to anyone with the password. Always visible to the owner.
+    Password {
+        /// Clients send the password. The server replaces it with its argon2 hash before it is stored
+        password: String,
+    },
 }
 impl Default for Visibility {
     fn default() -> Self {
@@ -37,6 +42,14 @@ pub trait HasVisibility {
             Visibility::Public => false,
             Visibility::Unlisted => false,
             Visibility::Private { .. } => true,
+            Visibility::Password { .. } => true,
+        }
+    }
+    /// The hash of the password that unlocks the item. None if the item is not password protected
+    fn password_hash(&self) -> Option<&str> {
+        match self.visibility() {
+            Visibility::Password { password } => Some(password),
+            _ => None,
         }
     }
     fn is_visible_to(&self, user_id: i64) -> bool {
@@ -52,6 +65,8 @@ pub trait HasVisibility {
                 }
                 visible_to.contains(&user_id)
             }
+            // Accounts do not matter. The password must be sent
+            Visibility::Password { .. } => false,
         }
     }
 }
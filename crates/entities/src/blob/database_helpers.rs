@@ -0,0 +1,36 @@
+use sea_orm::{prelude::*, ConnectionTrait, DbBackend, Statement};
+
+/// Locks the rows so a new reference waits until the blob is gone. Then it is created again
+static DELETE_UNREFERENCED: &str = r#"
+DELETE
+FROM blobs
+WHERE hash IN (SELECT hash
+               FROM blobs
+               WHERE reference_count <= 0
+                 AND updated < $1
+               LIMIT $2 FOR UPDATE SKIP LOCKED)
+RETURNING hash;
+"#;
+
+/// Deletes the rows of blobs that have not been referenced since `released_before`
+///
+/// Call inside a transaction and delete the content of the blobs before committing it.
+///
+/// # Returns
+/// The hashes of the deleted blobs
+pub async fn delete_unreferenced_blobs(
+    connections: &impl ConnectionTrait,
+    released_before: DateTimeWithTimeZone,
+    limit: u64,
+) -> Result<Vec<String>, DbErr> {
+    let rows = connections
+        .query_all(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            DELETE_UNREFERENCED,
+            [released_before.into(), (limit as i64).into()],
+        ))
+        .await?;
+    rows.into_iter()
+        .map(|row| row.try_get::<String>("", "hash"))
+        .collect()
+}
@@ -0,0 +1,27 @@
+use sea_orm::entity::prelude::*;
+
+pub mod database_helpers;
+
+/// Content that is stored once no matter how many files contain it.
+///
+/// `reference_count` is kept up to date by database triggers on the tables that reference blobs
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "blobs")]
+pub struct Model {
+    /// SHA-256 of the content as lowercase hex
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub hash: String,
+    pub size: i64,
+    /// The number of paste file revisions and images that use this blob
+    pub reference_count: i64,
+    /// The last time the reference count changed
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub updated: DateTimeWithTimeZone,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,115 @@
+use sea_orm::{prelude::*, ActiveValue::Set, ConnectionTrait, QueryOrder};
+
+use crate::{
+    image::{ImageColumn, PostColumn, VariantColumn},
+    ImageFileEntity, ImageFileModel, ImagePostActiveModel, ImagePostEntity, ImagePostModel,
+    ImageVariantEntity, ImageVariantModel,
+};
+
+/// Finds a post that is not in the trash
+#[inline(always)]
+pub async fn find_post_by_id(
+    connections: &impl ConnectionTrait,
+    id: i64,
+) -> Result<Option<ImagePostModel>, DbErr> {
+    ImagePostEntity::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(connections)
+        .await
+}
+/// Finds a post that is not in the trash
+#[inline(always)]
+pub async fn find_post_by_str_id(
+    connections: &impl ConnectionTrait,
+    id: String,
+) -> Result<Option<ImagePostModel>, DbErr> {
+    ImagePostEntity::find()
+        .filter(PostColumn::IdStr.eq(id))
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(connections)
+        .await
+}
+/// Returns the images of a post in the order they were uploaded
+#[inline(always)]
+pub async fn get_images(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+) -> Result<Vec<ImageFileModel>, DbErr> {
+    ImageFileEntity::find()
+        .filter(ImageColumn::PostId.eq(post_id))
+        .order_by_asc(ImageColumn::Id)
+        .all(connections)
+        .await
+}
+/// Finds an image that belongs to the post
+#[inline(always)]
+pub async fn find_image(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    image_id: i64,
+) -> Result<Option<ImageFileModel>, DbErr> {
+    ImageFileEntity::find_by_id(image_id)
+        .filter(ImageColumn::PostId.eq(post_id))
+        .one(connections)
+        .await
+}
+/// Finds a post that is in the trash
+pub async fn find_deleted_post(
+    connections: &impl ConnectionTrait,
+    id: i64,
+) -> Result<Option<ImagePostModel>, DbErr> {
+    ImagePostEntity::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_not_null())
+        .one(connections)
+        .await
+}
+/// The posts of a user that are in the trash. Most recently deleted first
+pub async fn get_trash(
+    connections: &impl ConnectionTrait,
+    user_id: i64,
+) -> Result<Vec<ImagePostModel>, DbErr> {
+    ImagePostEntity::find()
+        .filter(PostColumn::UserId.eq(user_id))
+        .filter(PostColumn::DeletedAt.is_not_null())
+        .order_by_desc(PostColumn::DeletedAt)
+        .all(connections)
+        .await
+}
+/// Moves the post to the trash. Or restores it if `deleted_at` is None
+pub async fn set_post_deleted(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    deleted_at: Option<DateTimeWithTimeZone>,
+) -> Result<(), DbErr> {
+    let post = ImagePostActiveModel {
+        id: Set(post_id),
+        deleted_at: Set(deleted_at),
+        ..Default::default()
+    };
+    ImagePostEntity::update(post).exec(connections).await?;
+    Ok(())
+}
+/// Finds an image whose post is not in the trash
+pub async fn find_image_by_id(
+    connections: &impl ConnectionTrait,
+    image_id: i64,
+) -> Result<Option<(ImageFileModel, ImagePostModel)>, DbErr> {
+    let result = ImageFileEntity::find_by_id(image_id)
+        .find_also_related(ImagePostEntity)
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(connections)
+        .await?;
+    Ok(result.and_then(|(image, post)| Some((image, post?))))
+}
+/// Finds the variant of an image if it has been created
+pub async fn find_variant(
+    connections: &impl ConnectionTrait,
+    image_id: i64,
+    variant: &str,
+) -> Result<Option<ImageVariantModel>, DbErr> {
+    ImageVariantEntity::find()
+        .filter(VariantColumn::ImageId.eq(image_id))
+        .filter(VariantColumn::Variant.eq(variant))
+        .one(connections)
+        .await
+}
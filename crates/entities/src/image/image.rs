@@ -8,8 +8,16 @@ pub struct Model {
     #[sea_orm(primary_key, auto_increment = true)]
     pub id: i64,
     pub post_id: i64,
+    /// The file name of the image
     pub image: String,
     pub file: FileLocation,
+    /// Detected by decoding the image. Not the type claimed by the client
+    #[sea_orm(default_value = "application/octet-stream")]
+    pub content_type: String,
+    #[sea_orm(default_value = "0")]
+    pub width: i32,
+    #[sea_orm(default_value = "0")]
+    pub height: i32,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub last_updated: DateTimeWithTimeZone,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
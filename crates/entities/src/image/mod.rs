@@ -1,10 +1,21 @@
 use digestible::Digestible;
+use helper_macros::Response;
+use sea_orm::prelude::*;
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 use utoipa::ToSchema;
 
+pub use crate::image::{
+    image::{Column as ImageColumn, Relation as ImageRelation},
+    post::{Column as PostColumn, Relation as PostRelation},
+    variant::{Column as VariantColumn, Relation as VariantRelation},
+};
+use crate::{ImageFileModel, ImagePostModel};
+
+pub mod database_helpers;
 pub mod image;
 pub mod post;
+pub mod variant;
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Digestible, ToSchema)]
 #[serde(default)]
 #[typeshare]
@@ -24,3 +35,75 @@ impl Default for ImagePermissions {
         }
     }
 }
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+#[typeshare]
+pub struct ImagePost {
+    #[typeshare(typescript(type = "bigint"))]
+    pub id: i64,
+    pub id_str: String,
+    #[typeshare(typescript(type = "bigint"))]
+    pub user_id: i64,
+    pub name: String,
+    pub tags: Vec<String>,
+    pub description: String,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    #[typeshare(typescript(type = "Date"))]
+    pub last_updated: DateTimeWithTimeZone,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    #[typeshare(typescript(type = "Date"))]
+    pub created: DateTimeWithTimeZone,
+}
+impl From<ImagePostModel> for ImagePost {
+    fn from(post: ImagePostModel) -> Self {
+        Self {
+            id: post.id,
+            id_str: post.id_str,
+            user_id: post.user_id,
+            name: post.name,
+            tags: post.tags,
+            description: post.description,
+            last_updated: post.last_updated,
+            created: post.created,
+        }
+    }
+}
+/// The details of an image. The content is served separately
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible)]
+#[typeshare]
+pub struct ImageFile {
+    #[typeshare(typescript(type = "bigint"))]
+    pub id: i64,
+    #[typeshare(typescript(type = "bigint"))]
+    pub post_id: i64,
+    pub name: String,
+    /// Detected by decoding the image
+    pub content_type: String,
+    pub width: u32,
+    pub height: u32,
+    #[typeshare(typescript(type = "bigint"))]
+    pub size: u64,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    #[typeshare(typescript(type = "Date"))]
+    pub created: DateTimeWithTimeZone,
+}
+impl From<ImageFileModel> for ImageFile {
+    fn from(image: ImageFileModel) -> Self {
+        Self {
+            id: image.id,
+            post_id: image.post_id,
+            name: image.image,
+            content_type: image.content_type,
+            width: image.width as u32,
+            height: image.height as u32,
+            size: image.file.file_size() as u64,
+            created: image.created,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use common::visibility::{HasVisibility, Visibility};
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -6,6 +7,7 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = true)]
     pub id: i64,
+    #[sea_orm(unique)]
     pub id_str: String,
     pub user_id: i64,
     pub name: String,
@@ -13,11 +15,24 @@ pub struct Model {
     pub tags: Vec<String>,
     #[sea_orm(default_value = "")]
     pub description: String,
+    /// When the post was moved to the trash. None if it was not deleted
+    pub deleted_at: Option<DateTimeWithTimeZone>,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub last_updated: DateTimeWithTimeZone,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created: DateTimeWithTimeZone,
 }
+/// Image posts do not have a visibility. Anyone who can view images can view every post
+static PUBLIC: Visibility = Visibility::Public;
+impl HasVisibility for Model {
+    fn visibility(&self) -> &Visibility {
+        &PUBLIC
+    }
+
+    fn is_owner(&self, user_id: i64) -> bool {
+        self.user_id == user_id
+    }
+}
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
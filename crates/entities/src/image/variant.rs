@@ -0,0 +1,45 @@
+use common::file_location::FileLocation;
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A resized copy of an image. Created the first time it is requested
+///
+/// Images that already fit the size share the file of the original
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel, Deserialize, Serialize)]
+#[sea_orm(table_name = "image_variants")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    pub image_id: i64,
+    /// The name of the size preset in `ImageRules`
+    pub variant: String,
+    pub file: FileLocation,
+    pub content_type: String,
+    pub width: i32,
+    pub height: i32,
+    /// The size of the preset when the variant was created. The variant is created again if the preset changes
+    pub max_width: i32,
+    pub max_height: i32,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::image::image::Entity",
+        from = "Column::ImageId",
+        to = "crate::image::image::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Image,
+}
+
+impl Related<crate::image::image::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Image.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
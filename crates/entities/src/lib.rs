@@ -1,4 +1,5 @@
 pub mod auth_token;
+pub mod blob;
 pub mod image;
 pub mod paste;
 pub mod user;
@@ -6,6 +7,7 @@ pub mod user;
 pub use auth_token::{
     ActiveModel as AuthTokenActiveModel, Entity as AuthTokenEntity, Model as AuthTokenModel,
 };
+pub use blob::{ActiveModel as BlobActiveModel, Entity as BlobEntity, Model as BlobModel};
 use common::visibility::Visibility;
 pub use image::{
     image::{
@@ -14,14 +16,29 @@ pub use image::{
     post::{
         ActiveModel as ImagePostActiveModel, Entity as ImagePostEntity, Model as ImagePostModel,
     },
+    variant::{
+        ActiveModel as ImageVariantActiveModel, Entity as ImageVariantEntity,
+        Model as ImageVariantModel,
+    },
 };
 pub use paste::{
+    comment::{
+        ActiveModel as PasteCommentActiveModel, Entity as PasteCommentEntity,
+        Model as PasteCommentModel,
+    },
     file::{
         ActiveModel as PasteFileActiveModel, Entity as PasteFileEntity, Model as PasteFileModel,
     },
     post::{
         ActiveModel as PastePostActiveModel, Entity as PastePostEntity, Model as PastePostModel,
     },
+    revision::{
+        ActiveModel as PasteFileRevisionActiveModel, Entity as PasteFileRevisionEntity,
+        Model as PasteFileRevisionModel,
+    },
+    view::{
+        ActiveModel as PasteViewActiveModel, Entity as PasteViewEntity, Model as PasteViewModel,
+    },
 };
 use sea_orm::FromQueryResult;
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,70 @@
+use sea_orm::entity::prelude::*;
+
+/// A comment on a paste. Or on a range of lines of one of its files
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "paste_comments")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    pub post_id: i64,
+    /// None if the comment is on the paste itself
+    pub file_id: Option<i64>,
+    /// The revision of the file the lines refer to
+    pub revision: Option<i32>,
+    /// The first line of the annotated range. Starts at 1
+    pub line_start: Option<i32>,
+    /// The last line of the annotated range. Inclusive
+    pub line_end: Option<i32>,
+    pub user_id: i64,
+    pub content: String,
+    /// When the comment was last edited. None if it was never edited
+    pub edited: Option<DateTimeWithTimeZone>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::paste::post::Entity",
+        from = "Column::PostId",
+        to = "crate::paste::post::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Post,
+    #[sea_orm(
+        belongs_to = "crate::paste::file::Entity",
+        from = "Column::FileId",
+        to = "crate::paste::file::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    File,
+    #[sea_orm(
+        belongs_to = "crate::user::Entity",
+        from = "Column::UserId",
+        to = "crate::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    User,
+}
+
+impl Related<crate::paste::post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Post.def()
+    }
+}
+impl Related<crate::paste::file::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::File.def()
+    }
+}
+impl Related<crate::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,18 +1,21 @@
 use common::{
     file_location::FileLocation,
-    paste::file_type::FileType,
+    paste::{encryption::EncryptionEnvelope, file_type::FileType},
     visibility::{HasVisibility, Visibility},
 };
 use sea_orm::{
-    prelude::*, sea_query::SimpleExpr, ConnectionTrait, FromQueryResult, JoinType, QuerySelect,
-    SelectColumns, SelectModel, Selector,
+    prelude::*, sea_query::SimpleExpr, ActiveValue::Set, ConnectionTrait, DbBackend,
+    FromQueryResult, JoinType, QueryOrder, QuerySelect, SelectColumns, SelectModel, Selector,
+    Statement,
 };
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
 use crate::{
-    paste::{FileColumn, FileRelation, PostColumn},
-    PasteFileEntity, PasteFileModel, PastePostEntity, PastePostModel,
+    paste::{CommentColumn, FileColumn, FileRelation, HasExpiration, PostColumn, RevisionColumn},
+    PasteCommentEntity, PasteCommentModel, PasteFileEntity, PasteFileModel,
+    PasteFileRevisionActiveModel, PasteFileRevisionEntity, PasteFileRevisionModel,
+    PastePostActiveModel, PastePostEntity, PastePostModel,
 };
 
 #[derive(FromQueryResult, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -47,6 +50,7 @@ impl PasteAndFileModel {
     fn new_request_from_file() -> Select<super::file::Entity> {
         PasteFileEntity::find()
             .join(JoinType::InnerJoin, super::file::Relation::Post.def())
+            .filter(PostColumn::DeletedAt.is_null())
             .select_column_as(FileColumn::Created, "file_created")
             .select_column_as(PostColumn::Created, "post_created")
             .select_column_as(PostColumn::Id, "file_id")
@@ -85,10 +89,18 @@ pub struct FileOwnerAndVisibility {
     pub file_name: String,
     pub file_type: FileType,
     pub location: FileLocation,
+    pub encryption: Option<EncryptionEnvelope>,
     pub visibility: Visibility,
     pub user_id: i64,
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub burn_after_read: bool,
     pub created: DateTimeWithTimeZone,
 }
+impl HasExpiration for FileOwnerAndVisibility {
+    fn expires_at(&self) -> Option<&DateTimeWithTimeZone> {
+        self.expires_at.as_ref()
+    }
+}
 impl HasVisibility for FileOwnerAndVisibility {
     fn visibility(&self) -> &Visibility {
         &self.visibility
@@ -107,11 +119,14 @@ impl FileOwnerAndVisibility {
         PasteFileEntity::find()
             .column(PostColumn::UserId)
             .column(PostColumn::Visibility)
+            .column(PostColumn::ExpiresAt)
+            .column(PostColumn::BurnAfterRead)
             .join(JoinType::InnerJoin, FileRelation::Post.def())
             .filter(
                 PostColumn::IdStr
                     .eq(post_id)
-                    .and(FileColumn::FileName.eq(file_name)),
+                    .and(FileColumn::FileName.eq(file_name))
+                    .and(PostColumn::DeletedAt.is_null()),
             )
             .into_model::<FileOwnerAndVisibility>()
             .one(connections)
@@ -119,13 +134,18 @@ impl FileOwnerAndVisibility {
     }
 }
 
+/// Finds a post that is not in the trash
 #[inline(always)]
 pub async fn find_post_by_id(
     connections: &impl ConnectionTrait,
     id: i64,
 ) -> Result<Option<PastePostModel>, DbErr> {
-    PastePostEntity::find_by_id(id).one(connections).await
+    PastePostEntity::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(connections)
+        .await
 }
+/// Finds a post that is not in the trash
 #[inline(always)]
 pub async fn find_post_by_str_id(
     connections: &impl ConnectionTrait,
@@ -133,9 +153,60 @@ pub async fn find_post_by_str_id(
 ) -> Result<Option<PastePostModel>, DbErr> {
     PastePostEntity::find()
         .filter(PostColumn::IdStr.eq(id))
+        .filter(PostColumn::DeletedAt.is_null())
+        .one(connections)
+        .await
+}
+/// Finds a post that is in the trash
+pub async fn find_deleted_post(
+    connections: &impl ConnectionTrait,
+    id: i64,
+) -> Result<Option<PastePostModel>, DbErr> {
+    PastePostEntity::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_not_null())
         .one(connections)
         .await
 }
+/// The posts of a user that are in the trash. Most recently deleted first
+pub async fn get_trash(
+    connections: &impl ConnectionTrait,
+    user_id: i64,
+) -> Result<Vec<PastePostModel>, DbErr> {
+    PastePostEntity::find()
+        .filter(PostColumn::UserId.eq(user_id))
+        .filter(PostColumn::DeletedAt.is_not_null())
+        .order_by_desc(PostColumn::DeletedAt)
+        .all(connections)
+        .await
+}
+/// Moves the post to the trash. Or restores it if `deleted_at` is None
+pub async fn set_post_deleted(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    deleted_at: Option<DateTimeWithTimeZone>,
+) -> Result<(), DbErr> {
+    let post = PastePostActiveModel {
+        id: Set(post_id),
+        deleted_at: Set(deleted_at),
+        ..Default::default()
+    };
+    PastePostEntity::update(post).exec(connections).await?;
+    Ok(())
+}
+/// The ids of the posts that were moved to the trash before `deleted_before`
+pub async fn find_posts_deleted_before(
+    connections: &impl ConnectionTrait,
+    deleted_before: DateTimeWithTimeZone,
+) -> Result<Vec<i64>, DbErr> {
+    PastePostEntity::find()
+        .select_only()
+        .column(PostColumn::Id)
+        .filter(PostColumn::DeletedAt.lte(deleted_before))
+        .into_tuple()
+        .all(connections)
+        .await
+}
+/// Returns the files of a post sorted by their position
 #[inline(always)]
 pub async fn get_files(
     connections: &impl ConnectionTrait,
@@ -143,14 +214,245 @@ pub async fn get_files(
 ) -> Result<Vec<PasteFileModel>, DbErr> {
     PasteFileEntity::find()
         .filter(FileColumn::PostId.eq(post_id))
+        .order_by_asc(FileColumn::Position)
+        .order_by_asc(FileColumn::Id)
         .all(connections)
         .await
 }
+/// The combined size of the current files in a post. Previous revisions are not included
+pub async fn get_paste_size(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+) -> Result<u64, DbErr> {
+    let files = get_files(connections, post_id).await?;
+    Ok(files
+        .iter()
+        .map(|file| file.location.file_size() as u64)
+        .sum())
+}
+#[inline(always)]
+pub async fn find_file_by_name(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    file_name: &str,
+) -> Result<Option<PasteFileModel>, DbErr> {
+    PasteFileEntity::find()
+        .filter(
+            FileColumn::PostId
+                .eq(post_id)
+                .and(FileColumn::FileName.eq(file_name)),
+        )
+        .one(connections)
+        .await
+}
+/// Sets `last_updated` of the post to the current time
+pub async fn update_last_updated(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+) -> Result<(), DbErr> {
+    let post = PastePostActiveModel {
+        id: Set(post_id),
+        last_updated: Set(chrono::Utc::now().into()),
+        ..Default::default()
+    };
+    PastePostEntity::update(post).exec(connections).await?;
+    Ok(())
+}
 
 #[inline(always)]
 pub async fn does_post_exist(connections: &impl ConnectionTrait, id: i64) -> Result<bool, DbErr> {
     PastePostEntity::find_by_id(id)
+        .filter(PostColumn::DeletedAt.is_null())
         .count(connections)
         .await
         .map(|c| c == 0)
 }
+
+/// Returns the revisions of a file. Oldest first
+pub async fn get_revisions(
+    connections: &impl ConnectionTrait,
+    file_id: i64,
+) -> Result<Vec<PasteFileRevisionModel>, DbErr> {
+    PasteFileRevisionEntity::find()
+        .filter(RevisionColumn::FileId.eq(file_id))
+        .order_by_asc(RevisionColumn::Revision)
+        .all(connections)
+        .await
+}
+/// Finds a specific revision of a file.
+///
+/// If `revision` is `None` the latest revision is returned
+pub async fn find_revision(
+    connections: &impl ConnectionTrait,
+    file_id: i64,
+    revision: Option<i32>,
+) -> Result<Option<PasteFileRevisionModel>, DbErr> {
+    let query = PasteFileRevisionEntity::find().filter(RevisionColumn::FileId.eq(file_id));
+    match revision {
+        Some(revision) => query.filter(RevisionColumn::Revision.eq(revision)),
+        None => query.order_by_desc(RevisionColumn::Revision),
+    }
+    .one(connections)
+    .await
+}
+/// The revision number the next revision of the file will have
+pub async fn next_revision(connections: &impl ConnectionTrait, file_id: i64) -> Result<i32, DbErr> {
+    let latest: Option<Option<i32>> = PasteFileRevisionEntity::find()
+        .select_only()
+        .column_as(Expr::col(RevisionColumn::Revision).max(), "revision")
+        .filter(RevisionColumn::FileId.eq(file_id))
+        .into_tuple()
+        .one(connections)
+        .await?;
+    Ok(latest.flatten().unwrap_or(0) + 1)
+}
+/// Locks the row of the file until the transaction ends.
+///
+/// Makes concurrent revisions of the file wait for each other. So each gets its own [next_revision]
+pub async fn lock_file(connections: &impl ConnectionTrait, file_id: i64) -> Result<(), DbErr> {
+    PasteFileEntity::find_by_id(file_id)
+        .select_only()
+        .column(FileColumn::Id)
+        .lock_exclusive()
+        .into_tuple::<i64>()
+        .one(connections)
+        .await?;
+    Ok(())
+}
+/// Records a new revision of the file
+pub async fn create_revision(
+    connections: &impl ConnectionTrait,
+    file_id: i64,
+    revision: i32,
+    location: FileLocation,
+    encryption: Option<EncryptionEnvelope>,
+    user_id: i64,
+) -> Result<PasteFileRevisionModel, DbErr> {
+    let size = location.file_size() as i64;
+    let revision = PasteFileRevisionActiveModel {
+        file_id: Set(file_id),
+        revision: Set(revision),
+        location: Set(location),
+        size: Set(size),
+        encryption: Set(encryption),
+        user_id: Set(Some(user_id)),
+        ..Default::default()
+    };
+    PasteFileRevisionEntity::insert(revision)
+        .exec_with_returning(connections)
+        .await
+}
+
+/// Returns the comments of a post with the file each comment is on. Oldest first
+///
+/// If `file_name` is set only the comments on that file are returned
+pub async fn get_comments(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    file_name: Option<&str>,
+) -> Result<Vec<(PasteCommentModel, Option<PasteFileModel>)>, DbErr> {
+    let query = PasteCommentEntity::find()
+        .filter(CommentColumn::PostId.eq(post_id))
+        .find_also_related(PasteFileEntity);
+    match file_name {
+        Some(file_name) => query.filter(FileColumn::FileName.eq(file_name)),
+        None => query,
+    }
+    .order_by_asc(CommentColumn::Created)
+    .order_by_asc(CommentColumn::Id)
+    .all(connections)
+    .await
+}
+/// Finds a comment of the post with the file it is on
+pub async fn find_comment(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    comment_id: i64,
+) -> Result<Option<(PasteCommentModel, Option<PasteFileModel>)>, DbErr> {
+    PasteCommentEntity::find_by_id(comment_id)
+        .filter(CommentColumn::PostId.eq(post_id))
+        .find_also_related(PasteFileEntity)
+        .one(connections)
+        .await
+}
+
+static VIEW_TOTALS: &str = r#"
+SELECT COUNT(*)                                AS total_views,
+       COUNT(*) FILTER (WHERE file_id IS NULL) AS paste_views,
+       MAX(created)                            AS last_viewed
+FROM paste_views
+WHERE post_id = $1;
+"#;
+static FILE_VIEWS: &str = r#"
+SELECT paste_file.id AS file_id, paste_file.file_name, COUNT(paste_views.id) AS views
+FROM paste_file
+         LEFT JOIN paste_views ON paste_views.file_id = paste_file.id
+WHERE paste_file.post_id = $1
+GROUP BY paste_file.id
+ORDER BY paste_file.position, paste_file.id;
+"#;
+static DAILY_VIEWS: &str = r#"
+SELECT (created AT TIME ZONE 'UTC')::DATE AS day, COUNT(*) AS views
+FROM paste_views
+WHERE post_id = $1
+  AND created >= $2
+GROUP BY day
+ORDER BY day;
+"#;
+#[derive(FromQueryResult, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ViewTotals {
+    /// Views of the paste and its files
+    pub total_views: i64,
+    /// Views of the paste itself
+    pub paste_views: i64,
+    pub last_viewed: Option<DateTimeWithTimeZone>,
+}
+#[derive(FromQueryResult, Clone, Debug, PartialEq, Eq)]
+pub struct FileViews {
+    pub file_id: i64,
+    pub file_name: String,
+    pub views: i64,
+}
+#[derive(FromQueryResult, Clone, Debug, PartialEq, Eq)]
+pub struct DailyViews {
+    /// In UTC
+    pub day: Date,
+    pub views: i64,
+}
+pub async fn get_view_totals(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+) -> Result<ViewTotals, DbErr> {
+    let statement =
+        Statement::from_sql_and_values(DbBackend::Postgres, VIEW_TOTALS, [post_id.into()]);
+    ViewTotals::find_by_statement(statement)
+        .one(connections)
+        .await
+        .map(Option::unwrap_or_default)
+}
+/// The views of every file of the post. Sorted by the position of the file
+pub async fn get_file_views(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+) -> Result<Vec<FileViews>, DbErr> {
+    let statement =
+        Statement::from_sql_and_values(DbBackend::Postgres, FILE_VIEWS, [post_id.into()]);
+    FileViews::find_by_statement(statement)
+        .all(connections)
+        .await
+}
+/// The views per day since `since`. Days without views are not included
+pub async fn get_daily_views(
+    connections: &impl ConnectionTrait,
+    post_id: i64,
+    since: DateTimeWithTimeZone,
+) -> Result<Vec<DailyViews>, DbErr> {
+    let statement = Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        DAILY_VIEWS,
+        [post_id.into(), since.into()],
+    );
+    DailyViews::find_by_statement(statement)
+        .all(connections)
+        .await
+}
@@ -1,4 +1,7 @@
-use common::{file_location::FileLocation, paste::file_type::FileType};
+use common::{
+    file_location::FileLocation,
+    paste::{encryption::EncryptionEnvelope, file_type::FileType},
+};
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
@@ -10,6 +13,11 @@ pub struct Model {
     pub file_name: String,
     pub file_type: FileType,
     pub location: FileLocation,
+    /// How the current content was encrypted. None if the paste is not encrypted
+    pub encryption: Option<EncryptionEnvelope>,
+    /// The order of the file within the post. Lower values are shown first.
+    #[sea_orm(default_value = 0)]
+    pub position: i32,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub created: DateTimeWithTimeZone,
 }
@@ -1,25 +1,42 @@
 use digestible::Digestible;
 use helper_macros::Response;
-use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use sea_orm::{
     prelude::*, sea_query::SimpleExpr, ConnectionTrait, DbErr, EntityTrait, QueryFilter,
-    QuerySelect,
+    QueryOrder, QuerySelect,
 };
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 use utoipa::ToSchema;
 
 pub use crate::paste::{
+    comment::{Column as CommentColumn, Relation as CommentRelation},
     file::{Column as FileColumn, Relation as FileRelation},
     post::{Column as PostColumn, Relation as PostRelation},
+    revision::{Column as RevisionColumn, Relation as RevisionRelation},
+    view::{Column as ViewColumn, Relation as ViewRelation},
 };
 use crate::{PasteFileEntity, PastePostEntity};
 
+pub mod comment;
 pub mod database_helpers;
 pub mod file;
 pub mod post;
+pub mod revision;
+pub mod view;
 
+/// Implemented by types that contain the expiration of a paste
+pub trait HasExpiration {
+    fn expires_at(&self) -> Option<&DateTimeWithTimeZone>;
+
+    fn is_expired(&self) -> bool {
+        self.expires_at()
+            .map(|expires_at| *expires_at <= chrono::Utc::now())
+            .unwrap_or(false)
+    }
+}
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+#[expires(self.expires_at)]
 #[typeshare]
 pub struct Paste {
     #[typeshare(typescript(type = "bigint"))]
@@ -30,6 +47,20 @@ pub struct Paste {
     pub name: String,
     pub tags: Vec<String>,
     pub description: String,
+    #[schema(value_type = DateTime, nullable)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time_optional")]
+    #[digestible(digest_with = digest_with_hash)]
+    #[typeshare(typescript(type = "Date"))]
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    pub burn_after_read: bool,
+    /// The files are encrypted by the client. The key is not known to the server
+    pub encrypted: bool,
+    /// The id of the paste this paste was forked from
+    #[schema(nullable)]
+    pub forked_from: Option<String>,
+    /// The number of pastes forked from this paste
+    #[typeshare(typescript(type = "bigint"))]
+    pub fork_count: u64,
     #[schema(value_type = DateTime)]
     #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
     #[digestible(digest_with = digest_with_hash)]
@@ -41,9 +72,16 @@ pub struct Paste {
     #[typeshare(typescript(type = "Date"))]
     pub created: DateTimeWithTimeZone,
     /// Not included in a response that does not include files
+    ///
+    /// Sorted by the position of the file within the post
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub files: Vec<String>,
 }
+impl HasExpiration for Paste {
+    fn expires_at(&self) -> Option<&DateTimeWithTimeZone> {
+        self.expires_at.as_ref()
+    }
+}
 impl Paste {
     pub async fn get(
         connections: &impl ConnectionTrait,
@@ -52,20 +90,39 @@ impl Paste {
     ) -> Result<Option<Self>, DbErr> {
         let result = PastePostEntity::find()
             .filter(filter)
+            .filter(PostColumn::DeletedAt.is_null())
             .one(connections)
             .await?;
         if let Some(result) = result {
-            let files: Vec<String> = if !get_files {
+            let files: Vec<String> = if get_files {
                 PasteFileEntity::find()
                     .select_only()
                     .column(file::Column::FileName)
                     .filter(file::Column::PostId.eq(result.id))
+                    .order_by_asc(file::Column::Position)
+                    .order_by_asc(file::Column::Id)
                     .into_tuple()
                     .all(connections)
                     .await?
             } else {
                 vec![]
             };
+            let forked_from: Option<String> = if let Some(forked_from) = result.forked_from {
+                PastePostEntity::find_by_id(forked_from)
+                    .filter(PostColumn::DeletedAt.is_null())
+                    .select_only()
+                    .column(PostColumn::IdStr)
+                    .into_tuple()
+                    .one(connections)
+                    .await?
+            } else {
+                None
+            };
+            let fork_count = PastePostEntity::find()
+                .filter(PostColumn::ForkedFrom.eq(result.id))
+                .filter(PostColumn::DeletedAt.is_null())
+                .count(connections)
+                .await?;
             Ok(Some(Self {
                 id: result.id,
                 id_str: result.id_str,
@@ -73,6 +130,11 @@ impl Paste {
                 name: result.name,
                 tags: result.tags,
                 description: result.description,
+                expires_at: result.expires_at,
+                burn_after_read: result.burn_after_read,
+                encrypted: result.encrypted,
+                forked_from,
+                fork_count,
                 last_updated: result.last_updated,
                 files,
                 created: result.created,
@@ -97,19 +159,12 @@ impl Paste {
         Self::get(connections, PostColumn::IdStr.eq(id_str), get_files).await
     }
 }
-pub async fn generate_post_paste_id(connections: &impl ConnectionTrait) -> Result<String, DbErr> {
+/// Generates a random id for a post. Uniqueness is enforced by the unique index on [post::Column::IdStr]
+pub fn generate_post_paste_id(length: usize, alphabet: &[u8]) -> String {
     let mut rand = StdRng::from_entropy();
-    loop {
-        let post_id: String = (0..8).map(|_| rand.sample(Alphanumeric) as char).collect();
-        if PastePostEntity::find()
-            .filter(post::Column::IdStr.eq(post_id.clone()))
-            .count(connections)
-            .await?
-            == 0
-        {
-            return Ok(post_id);
-        }
-    }
+    (0..length)
+        .map(|_| alphabet[rand.gen_range(0..alphabet.len())] as char)
+        .collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Digestible, ToSchema)]
@@ -120,6 +175,8 @@ pub struct PastePermissions {
     /// View and Delete Other Images
     pub admin: bool,
     pub view_public: bool,
+    /// Choose the id of new pastes. Such as `deploy-runbook`
+    pub custom_slug: bool,
 }
 impl Default for PastePermissions {
     fn default() -> Self {
@@ -127,6 +184,7 @@ impl Default for PastePermissions {
             create: true,
             admin: false,
             view_public: true,
+            custom_slug: false,
         }
     }
 }
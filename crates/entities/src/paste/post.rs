@@ -1,6 +1,8 @@
 use common::visibility::{HasVisibility, Visibility};
 use sea_orm::entity::prelude::*;
 
+use crate::paste::HasExpiration;
+
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "paste_posts")]
 pub struct Model {
@@ -15,6 +17,22 @@ pub struct Model {
     #[sea_orm(default_value = "")]
     pub description: String,
     pub visibility: Visibility,
+    /// When the paste will be deleted. None if the paste never expires
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    /// The paste is deleted the first time a file is read by someone other than the owner
+    #[sea_orm(default_value = "false")]
+    pub burn_after_read: bool,
+    /// The files are encrypted by the client. The server only has the ciphertext
+    #[sea_orm(default_value = "false")]
+    pub encrypted: bool,
+    /// The SHA-256 of the token that can delete an anonymous paste. None for pastes created by users
+    pub delete_token: Option<String>,
+    /// The post this post was forked from. None if it was not forked or the original was deleted
+    pub forked_from: Option<i64>,
+    /// When the paste was moved to the trash. None if it was not deleted
+    ///
+    /// Deleted pastes are hidden by the helpers in [database_helpers](crate::paste::database_helpers)
+    pub deleted_at: Option<DateTimeWithTimeZone>,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
     pub last_updated: DateTimeWithTimeZone,
     #[sea_orm(default_expr = "Expr::current_timestamp()")]
@@ -29,6 +47,11 @@ impl HasVisibility for Model {
         self.user_id == user_id as i64
     }
 }
+impl HasExpiration for Model {
+    fn expires_at(&self) -> Option<&DateTimeWithTimeZone> {
+        self.expires_at.as_ref()
+    }
+}
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
@@ -39,6 +62,14 @@ pub enum Relation {
         on_delete = "Cascade"
     )]
     User,
+    #[sea_orm(
+        belongs_to = "Entity",
+        from = "Column::ForkedFrom",
+        to = "Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    ForkedFrom,
 }
 
 impl Related<crate::user::Entity> for Entity {
@@ -0,0 +1,57 @@
+use common::{file_location::FileLocation, paste::encryption::EncryptionEnvelope};
+use sea_orm::entity::prelude::*;
+
+/// An immutable copy of the content of a paste file.
+///
+/// A revision is created every time the content of a file changes.
+/// The first revision is the content the file was created with.
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "paste_file_revisions")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    pub file_id: i64,
+    /// Starts at 1 and increases by one for every change to the file
+    pub revision: i32,
+    pub location: FileLocation,
+    pub size: i64,
+    /// Every revision of an encrypted file has its own nonce
+    pub encryption: Option<EncryptionEnvelope>,
+    /// The user that uploaded this revision
+    pub user_id: Option<i64>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::paste::file::Entity",
+        from = "Column::FileId",
+        to = "crate::paste::file::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    File,
+    #[sea_orm(
+        belongs_to = "crate::user::Entity",
+        from = "Column::UserId",
+        to = "crate::user::Column::Id",
+        on_update = "Cascade",
+        on_delete = "SetNull"
+    )]
+    User,
+}
+
+impl Related<crate::paste::file::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::File.def()
+    }
+}
+impl Related<crate::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+
+/// A view of a paste or one of its files.
+///
+/// Repeated views by the same viewer are only recorded once per deduplication window
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "paste_views")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = true)]
+    pub id: i64,
+    pub post_id: i64,
+    /// None if the paste itself was viewed
+    pub file_id: Option<i64>,
+    #[sea_orm(default_expr = "Expr::current_timestamp()")]
+    pub created: DateTimeWithTimeZone,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "crate::paste::post::Entity",
+        from = "Column::PostId",
+        to = "crate::paste::post::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    Post,
+    #[sea_orm(
+        belongs_to = "crate::paste::file::Entity",
+        from = "Column::FileId",
+        to = "crate::paste::file::Column::Id",
+        on_update = "Cascade",
+        on_delete = "Cascade"
+    )]
+    File,
+}
+
+impl Related<crate::paste::post::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Post.def()
+    }
+}
+impl Related<crate::paste::file::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::File.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,10 +1,27 @@
-use sea_orm::{prelude::*, ConnectionTrait, QuerySelect};
+use sea_orm::{prelude::*, ConnectionTrait, DbBackend, FromQueryResult, QuerySelect, Statement};
 
 use super::Column as UserColumn;
 use crate::{
-    auth_token, user::user_responses::User, AuthTokenEntity, AuthTokenModel, UserEntity, UserModel,
+    auth_token,
+    user::user_responses::{StorageUsage, User},
+    AuthTokenEntity, AuthTokenModel, UserEntity, UserModel,
 };
 
+/// Paste bytes include every revision because previous revisions are kept on disk
+static STORAGE_USAGE: &str = r#"
+SELECT (SELECT COALESCE(SUM(paste_file_revisions.size), 0)::BIGINT
+        FROM paste_file_revisions
+                 INNER JOIN paste_file ON paste_file.id = paste_file_revisions.file_id
+                 INNER JOIN paste_posts ON paste_posts.id = paste_file.post_id
+        WHERE paste_posts.user_id = $1)                                    AS paste_bytes,
+       (SELECT COUNT(*) FROM paste_posts WHERE paste_posts.user_id = $1) AS pastes,
+       (SELECT COALESCE(SUM(COALESCE(image.file -> 'Local' ->> 'size', image.file -> 'Blob' ->> 'size')::BIGINT), 0)::BIGINT
+        FROM image
+                 INNER JOIN image_posts ON image_posts.id = image.post_id
+        WHERE image_posts.user_id = $1)                                    AS image_bytes,
+       (SELECT COUNT(*) FROM image_posts WHERE image_posts.user_id = $1) AS images;
+"#;
+
 pub async fn add_user(
     connection: &impl ConnectionTrait,
     active_model: super::ActiveModel,
@@ -78,6 +95,7 @@ pub async fn first_user(connections: &impl ConnectionTrait) -> Result<bool, DbEr
     UserEntity::find()
         .select_only()
         .column(UserColumn::Id)
+        .filter(UserColumn::Id.ne(super::ANONYMOUS_USER_ID))
         .count(connections)
         .await
         .map(|count| count == 0)
@@ -98,3 +116,28 @@ pub async fn does_user_exist(
         .await
         .map(|count| count > 0)
 }
+/// Calculates the storage used by a user
+pub async fn get_storage_usage(
+    connection: &impl ConnectionTrait,
+    user_id: i64,
+) -> Result<StorageUsage, DbErr> {
+    let statement =
+        Statement::from_sql_and_values(DbBackend::Postgres, STORAGE_USAGE, [user_id.into()]);
+    StorageUsage::find_by_statement(statement)
+        .one(connection)
+        .await
+        .map(Option::unwrap_or_default)
+}
+/// Locks the row of the user until the transaction ends.
+///
+/// Makes concurrent uploads by the user wait for each other. So their storage is checked one at a time
+pub async fn lock_user(connection: &impl ConnectionTrait, user_id: i64) -> Result<(), DbErr> {
+    UserEntity::find_by_id(user_id)
+        .select_only()
+        .column(UserColumn::Id)
+        .lock_exclusive()
+        .into_tuple::<i64>()
+        .one(connection)
+        .await?;
+    Ok(())
+}
@@ -7,6 +7,9 @@ use sea_orm::entity::prelude::*;
 
 use crate::user::permissions::Permissions;
 
+/// The owner of pastes created without logging in. Created by the migrations. It can not log in
+pub const ANONYMOUS_USER_ID: i64 = 0;
+
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "users")]
 pub struct Model {
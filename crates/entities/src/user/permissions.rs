@@ -31,6 +31,9 @@ impl Permissions {
     pub fn is_paste_admin(&self) -> bool {
         self.paste_permissions.admin || self.admin
     }
+    pub fn can_use_custom_slug(&self) -> bool {
+        self.paste_permissions.custom_slug || self.is_paste_admin()
+    }
     pub fn is_image_admin(&self) -> bool {
         self.image_permissions.admin || self.admin
     }
@@ -47,6 +50,7 @@ impl Permissions {
                 create: true,
                 admin: true,
                 view_public: true,
+                custom_slug: true,
             },
             user_permissions: UserPermissions {
                 view_profile: true,
@@ -63,11 +67,12 @@ impl Permissions {
                 admin: false,
                 view_public: true,
             },
-            admin: true,
+            admin: false,
             paste_permissions: PastePermissions {
                 create: false,
                 admin: false,
                 view_public: true,
+                custom_slug: false,
             },
             user_permissions: UserPermissions {
                 view_profile: true,
@@ -65,3 +65,14 @@ pub struct UserProfile {
     #[digestible(digest_with = digest_with_hash)]
     pub created: DateTimeWithTimeZone,
 }
+/// The storage used by a user
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, FromQueryResult, ToSchema, Serialize, Digestible,
+)]
+pub struct StorageUsage {
+    /// Total size of all paste files. Including previous revisions
+    pub paste_bytes: i64,
+    pub pastes: i64,
+    pub image_bytes: i64,
+    pub images: i64,
+}
@@ -4,6 +4,21 @@ mod m20220101_000001_users;
 mod m20230123_091026_create_auth_tokens;
 mod m20230123_113217_create_uploads;
 mod m20230822_185310_init;
+mod m20230905_121500_paste_file_position;
+mod m20230912_183000_paste_file_revisions;
+mod m20230918_094500_paste_expiration;
+mod m20230925_101500_paste_forks;
+mod m20231002_090000_paste_file_storage;
+mod m20231009_090000_blobs;
+mod m20231016_090000_encrypted_pastes;
+mod m20231023_090000_paste_views;
+mod m20231030_090000_paste_comments;
+mod m20231106_090000_anonymous_pastes;
+mod m20231113_090000_soft_delete;
+mod m20231120_090000_image_uploads;
+mod m20231127_090000_image_variants;
+mod m20231204_090000_local_file_hashes;
+mod m20231211_090000_image_variant_presets;
 
 pub struct Migrator;
 
@@ -15,6 +30,21 @@ impl MigratorTrait for Migrator {
             Box::new(m20220101_000001_users::Migration),
             Box::new(m20230123_091026_create_auth_tokens::Migration),
             Box::new(m20230123_113217_create_uploads::Migration),
+            Box::new(m20230905_121500_paste_file_position::Migration),
+            Box::new(m20230912_183000_paste_file_revisions::Migration),
+            Box::new(m20230918_094500_paste_expiration::Migration),
+            Box::new(m20230925_101500_paste_forks::Migration),
+            Box::new(m20231002_090000_paste_file_storage::Migration),
+            Box::new(m20231009_090000_blobs::Migration),
+            Box::new(m20231016_090000_encrypted_pastes::Migration),
+            Box::new(m20231023_090000_paste_views::Migration),
+            Box::new(m20231030_090000_paste_comments::Migration),
+            Box::new(m20231106_090000_anonymous_pastes::Migration),
+            Box::new(m20231113_090000_soft_delete::Migration),
+            Box::new(m20231120_090000_image_uploads::Migration),
+            Box::new(m20231127_090000_image_variants::Migration),
+            Box::new(m20231204_090000_local_file_hashes::Migration),
+            Box::new(m20231211_090000_image_variant_presets::Migration),
         ]
     }
 }
@@ -30,3 +60,31 @@ macro_rules! entities {
     };
 }
 pub(crate) use entities;
+
+/// Adds a column to a table if the table does not already contain it.
+///
+/// Tables are created from the current entities.
+/// So a fresh database will already contain the columns added by later migrations.
+pub(crate) async fn add_column_if_missing<T>(
+    manager: &SchemaManager<'_>,
+    table: T,
+    mut column: ColumnDef,
+) -> Result<(), DbErr>
+where
+    T: Iden + 'static,
+{
+    if manager
+        .has_column(table.to_string(), column.get_column_name())
+        .await?
+    {
+        return Ok(());
+    }
+    manager
+        .alter_table(
+            Table::alter()
+                .table(table)
+                .add_column(&mut column)
+                .to_owned(),
+        )
+        .await
+}
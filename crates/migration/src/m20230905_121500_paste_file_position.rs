@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            PasteFile::Table,
+            ColumnDef::new(PasteFile::Position)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PasteFile::Table)
+                    .drop_column(PasteFile::Position)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasteFile {
+    Table,
+    Position,
+}
@@ -0,0 +1,56 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+/// Every existing file gets a first revision pointing at its current content
+static BACKFILL: &str = r#"
+INSERT INTO paste_file_revisions (file_id, revision, location, size, user_id, created)
+SELECT paste_file.id,
+       1,
+       paste_file.location,
+       COALESCE((paste_file.location -> 'Local' ->> 'size')::BIGINT, 0),
+       paste_posts.user_id,
+       paste_file.created
+FROM paste_file
+         INNER JOIN paste_posts ON paste_posts.id = paste_file.post_id;
+"#;
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        crate::entities!(schema, manager, entities::PasteFileRevisionEntity);
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-paste_file_revisions-file_id-revision")
+                    .table(PasteFileRevision::Table)
+                    .col(PasteFileRevision::FileId)
+                    .col(PasteFileRevision::Revision)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(BACKFILL)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasteFileRevision::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasteFileRevision {
+    #[sea_orm(iden = "paste_file_revisions")]
+    Table,
+    FileId,
+    Revision,
+}
@@ -0,0 +1,49 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            PastePost::Table,
+            ColumnDef::new(PastePost::ExpiresAt)
+                .timestamp_with_time_zone()
+                .null()
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            PastePost::Table,
+            ColumnDef::new(PastePost::BurnAfterRead)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PastePost::Table)
+                    .drop_column(PastePost::ExpiresAt)
+                    .drop_column(PastePost::BurnAfterRead)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PastePost {
+    #[sea_orm(iden = "paste_posts")]
+    Table,
+    ExpiresAt,
+    BurnAfterRead,
+}
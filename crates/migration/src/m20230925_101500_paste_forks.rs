@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Fresh databases create the column and foreign key from the entity
+        if !manager.has_column("paste_posts", "forked_from").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(PastePost::Table)
+                        .add_column(ColumnDef::new(PastePost::ForkedFrom).big_integer().null())
+                        .add_foreign_key(
+                            TableForeignKey::new()
+                                .name("fk-paste_posts-forked_from")
+                                .from_tbl(PastePost::Table)
+                                .from_col(PastePost::ForkedFrom)
+                                .to_tbl(PastePost::Table)
+                                .to_col(PastePost::Id)
+                                .on_update(ForeignKeyAction::Cascade)
+                                .on_delete(ForeignKeyAction::SetNull),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .name("idx-paste_posts-forked_from")
+                    .table(PastePost::Table)
+                    .col(PastePost::ForkedFrom)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PastePost::Table)
+                    .drop_column(PastePost::ForkedFrom)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PastePost {
+    #[sea_orm(iden = "paste_posts")]
+    Table,
+    Id,
+    ForkedFrom,
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{DbBackend, Statement},
+};
+
+/// Every file stored on disk. [is_stored_by_name] picks the ones this migration moves
+static LOCAL_FILES: &str = r#"
+SELECT id, file_name, location -> 'Local' ->> 'location' AS path
+FROM paste_file
+WHERE location -> 'Local' ->> 'location' IS NOT NULL;
+"#;
+static UPDATE_FILE_LOCATION: &str = r#"
+UPDATE paste_file
+SET location = json_build_object('Local', json_build_object('location', $1::TEXT, 'size', location -> 'Local' -> 'size'))
+WHERE id = $2;
+"#;
+static UPDATE_REVISION_LOCATION: &str = r#"
+UPDATE paste_file_revisions
+SET location = json_build_object('Local', json_build_object('location', $1::TEXT, 'size', location -> 'Local' -> 'size'))
+WHERE file_id = $2
+  AND location -> 'Local' ->> 'location' = $3;
+"#;
+/// The files of every post that has more than one file with the same name
+static POSTS_WITH_DUPLICATES: &str = r#"
+SELECT id, post_id, file_name
+FROM paste_file
+WHERE post_id IN (SELECT post_id FROM paste_file GROUP BY post_id, file_name HAVING COUNT(*) > 1)
+ORDER BY post_id, id;
+"#;
+static RENAME_FILE: &str = r#"
+UPDATE paste_file
+SET file_name = $1
+WHERE id = $2;
+"#;
+
+/// Moves files stored at `{post_id}/{file_name}` to `{post_id}/files/{random name}`.
+/// Then makes file names unique within a post.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        let files = connection
+            .query_all(Statement::from_string(DbBackend::Postgres, LOCAL_FILES))
+            .await?;
+        let mut moved = HashSet::new();
+        for file in files {
+            let id: i64 = file.try_get("", "id")?;
+            let file_name: String = file.try_get("", "file_name")?;
+            let path: String = file.try_get("", "path")?;
+            if !is_stored_by_name(&path, &file_name) {
+                continue;
+            }
+            let new_path = new_location(&path, &file_name);
+            if let Some(parent) = new_path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| {
+                    DbErr::Custom(format!("Failed to create {}: {e}", parent.display()))
+                })?;
+            }
+            // Files with duplicate names shared one file. Every row gets its own copy
+            match std::fs::copy(&path, &new_path) {
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(DbErr::Custom(format!("Failed to move {path}: {e}"))),
+            }
+            let new_path = new_path.to_string_lossy().into_owned();
+            connection
+                .execute(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    UPDATE_FILE_LOCATION,
+                    [new_path.clone().into(), id.into()],
+                ))
+                .await?;
+            connection
+                .execute(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    UPDATE_REVISION_LOCATION,
+                    [new_path.into(), id.into(), path.clone().into()],
+                ))
+                .await?;
+            moved.insert(path);
+        }
+        // Old files are only removed once nothing points to them
+        for path in moved {
+            if let Err(e) = std::fs::remove_file(&path) {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    return Err(DbErr::Custom(format!("Failed to remove {path}: {e}")));
+                }
+            }
+        }
+        rename_duplicates(connection).await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-paste_file-post_id-file_name")
+                    .table(PasteFile::Table)
+                    .col(PasteFile::PostId)
+                    .col(PasteFile::FileName)
+                    .unique()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Files are not moved back. The new locations work with the old layout
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-paste_file-post_id-file_name")
+                    .table(PasteFile::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+/// Files with the same name in a post get ` (2)`, ` (3)` and so on added to their names.
+/// Skipping any names that are already used in the post. The oldest file keeps its name
+async fn rename_duplicates(connection: &impl ConnectionTrait) -> Result<(), DbErr> {
+    let files = connection
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            POSTS_WITH_DUPLICATES,
+        ))
+        .await?;
+    let mut files_by_post: BTreeMap<i64, Vec<(i64, String)>> = BTreeMap::new();
+    for file in files {
+        let id: i64 = file.try_get("", "id")?;
+        let post_id: i64 = file.try_get("", "post_id")?;
+        let file_name: String = file.try_get("", "file_name")?;
+        files_by_post
+            .entry(post_id)
+            .or_default()
+            .push((id, file_name));
+    }
+    for files in files_by_post.into_values() {
+        let mut taken: HashSet<String> = files.iter().map(|(_, name)| name.clone()).collect();
+        let mut kept = HashSet::new();
+        for (id, file_name) in files {
+            if kept.insert(file_name.clone()) {
+                continue;
+            }
+            let new_name = unused_name(&file_name, &taken);
+            connection
+                .execute(Statement::from_sql_and_values(
+                    DbBackend::Postgres,
+                    RENAME_FILE,
+                    [new_name.clone().into(), id.into()],
+                ))
+                .await?;
+            taken.insert(new_name);
+        }
+    }
+    Ok(())
+}
+/// `{file name} ({number})` with the lowest number from 2 that is not taken
+fn unused_name(file_name: &str, taken: &HashSet<String>) -> String {
+    (2..)
+        .map(|number| format!("{file_name} ({number})"))
+        .find(|name| !taken.contains(name))
+        .expect("There is always an unused number")
+}
+/// Files moved by this migration are at `{post directory}/files/{random name}`.
+/// Files that still need to be moved are at `{post directory}/{file name}`
+fn is_stored_by_name(path: &str, file_name: &str) -> bool {
+    path.strip_suffix(file_name)
+        .is_some_and(|directory| directory.ends_with(['/', '\\']))
+}
+/// `{post directory}/files/{random name}`.
+///
+/// The post directory is the stored path without the file name.
+/// Names containing `../` are stripped as text so the file is moved back inside the post directory
+fn new_location(path: &str, file_name: &str) -> PathBuf {
+    let post_directory = path
+        .strip_suffix(file_name)
+        .map(Path::new)
+        .or_else(|| Path::new(path).parent())
+        .unwrap_or(Path::new("."));
+    post_directory
+        .join("files")
+        .join(uuid::Uuid::new_v4().simple().to_string())
+}
+
+#[derive(DeriveIden)]
+pub enum PasteFile {
+    #[sea_orm(iden = "paste_file")]
+    Table,
+    PostId,
+    FileName,
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{is_stored_by_name, unused_name};
+
+    #[test]
+    fn stored_by_name() {
+        assert!(is_stored_by_name("data/pastes/1/main.rs", "main.rs"));
+        assert!(is_stored_by_name("/srv/files/pastes/1/main.rs", "main.rs"));
+        // Moved files are not moved again. Even if the root contains `/files/`
+        assert!(!is_stored_by_name(
+            "/srv/files/pastes/1/files/0b5e4c8a2f1d4e7c9a3b6d8f1e2c4a6b",
+            "main.rs"
+        ));
+        // Only the whole name matches
+        assert!(!is_stored_by_name("data/pastes/1/domain.rs", "main.rs"));
+    }
+    #[test]
+    fn unused_names_skip_taken_names() {
+        let taken: HashSet<String> = ["a", "a (2)", "a (3)"]
+            .into_iter()
+            .map(str::to_owned)
+            .collect();
+        assert_eq!(unused_name("a", &taken), "a (4)");
+        assert_eq!(unused_name("b", &taken), "b (2)");
+    }
+}
@@ -0,0 +1,96 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+/// Counts the rows referencing each blob.
+///
+/// `TG_ARGV[0]` is the `FileLocation` column of the table. Rows pointing at a blob that does not exist yet create it
+static COUNT_BLOB_REFERENCES: &str = r#"
+CREATE OR REPLACE FUNCTION count_blob_references() RETURNS TRIGGER AS
+$$
+DECLARE
+    old_blob JSONB;
+    new_blob JSONB;
+BEGIN
+    IF TG_OP <> 'INSERT' THEN
+        old_blob := to_jsonb(OLD) -> TG_ARGV[0] -> 'Blob';
+    END IF;
+    IF TG_OP <> 'DELETE' THEN
+        new_blob := to_jsonb(NEW) -> TG_ARGV[0] -> 'Blob';
+    END IF;
+    IF old_blob ->> 'hash' IS NOT DISTINCT FROM new_blob ->> 'hash' THEN
+        RETURN NULL;
+    END IF;
+    IF new_blob IS NOT NULL THEN
+        INSERT INTO blobs (hash, size, reference_count)
+        VALUES (new_blob ->> 'hash', (new_blob ->> 'size')::BIGINT, 1)
+        ON CONFLICT (hash) DO UPDATE SET reference_count = blobs.reference_count + 1,
+                                         updated         = now();
+    END IF;
+    IF old_blob IS NOT NULL THEN
+        UPDATE blobs
+        SET reference_count = reference_count - 1,
+            updated         = now()
+        WHERE hash = old_blob ->> 'hash';
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+"#;
+static REVISION_TRIGGER: &str = r#"
+CREATE TRIGGER paste_file_revisions_blob_references
+    AFTER INSERT OR UPDATE OF location OR DELETE
+    ON paste_file_revisions
+    FOR EACH ROW
+EXECUTE FUNCTION count_blob_references('location');
+"#;
+static IMAGE_TRIGGER: &str = r#"
+CREATE TRIGGER image_blob_references
+    AFTER INSERT OR UPDATE OF file OR DELETE
+    ON image
+    FOR EACH ROW
+EXECUTE FUNCTION count_blob_references('file');
+"#;
+/// Garbage collection only looks at blobs without references
+static UNREFERENCED_INDEX: &str = r#"
+CREATE INDEX IF NOT EXISTS "idx-blobs-unreferenced" ON blobs (updated) WHERE reference_count <= 0;
+"#;
+static DROP_TRIGGERS: &str = r#"
+DROP TRIGGER IF EXISTS paste_file_revisions_blob_references ON paste_file_revisions;
+DROP TRIGGER IF EXISTS image_blob_references ON image;
+DROP FUNCTION IF EXISTS count_blob_references();
+"#;
+
+/// Adds the blob store. Existing files keep their `FileLocation::Local` and are not deduplicated
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        crate::entities!(schema, manager, entities::BlobEntity);
+        let connection = manager.get_connection();
+        connection.execute_unprepared(UNREFERENCED_INDEX).await?;
+        connection.execute_unprepared(COUNT_BLOB_REFERENCES).await?;
+        connection.execute_unprepared(REVISION_TRIGGER).await?;
+        connection.execute_unprepared(IMAGE_TRIGGER).await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(DROP_TRIGGERS)
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Blob::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Blob {
+    #[sea_orm(iden = "blobs")]
+    Table,
+}
@@ -0,0 +1,84 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            PastePost::Table,
+            ColumnDef::new(PastePost::Encrypted)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            PasteFile::Table,
+            ColumnDef::new(PasteFile::Encryption)
+                .json()
+                .null()
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            PasteFileRevision::Table,
+            ColumnDef::new(PasteFileRevision::Encryption)
+                .json()
+                .null()
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PasteFileRevision::Table)
+                    .drop_column(PasteFileRevision::Encryption)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PasteFile::Table)
+                    .drop_column(PasteFile::Encryption)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PastePost::Table)
+                    .drop_column(PastePost::Encrypted)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PastePost {
+    #[sea_orm(iden = "paste_posts")]
+    Table,
+    Encrypted,
+}
+#[derive(DeriveIden)]
+pub enum PasteFile {
+    #[sea_orm(iden = "paste_file")]
+    Table,
+    Encryption,
+}
+#[derive(DeriveIden)]
+pub enum PasteFileRevision {
+    #[sea_orm(iden = "paste_file_revisions")]
+    Table,
+    Encryption,
+}
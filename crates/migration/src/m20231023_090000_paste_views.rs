@@ -0,0 +1,48 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        crate::entities!(schema, manager, entities::PasteViewEntity);
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-paste_views-post_id-created")
+                    .table(PasteView::Table)
+                    .col(PasteView::PostId)
+                    .col(PasteView::Created)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-paste_views-file_id")
+                    .table(PasteView::Table)
+                    .col(PasteView::FileId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasteView::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasteView {
+    #[sea_orm(iden = "paste_views")]
+    Table,
+    PostId,
+    FileId,
+    Created,
+}
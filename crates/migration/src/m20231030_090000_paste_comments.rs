@@ -0,0 +1,36 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        crate::entities!(schema, manager, entities::PasteCommentEntity);
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-paste_comments-post_id")
+                    .table(PasteComment::Table)
+                    .col(PasteComment::PostId)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PasteComment::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PasteComment {
+    #[sea_orm(iden = "paste_comments")]
+    Table,
+    PostId,
+}
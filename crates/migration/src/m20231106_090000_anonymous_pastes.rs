@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+/// The owner of anonymous pastes. It has no password so it can not log in
+static ANONYMOUS_USER: &str = r#"
+INSERT INTO users (id, name, username, email, permissions, password, banned)
+VALUES (0,
+        'Anonymous',
+        'anonymous',
+        'anonymous@localhost',
+        '{"admin": false, "paste_permissions": {"create": false}, "image_permissions": {"create": false}}',
+        NULL,
+        true)
+ON CONFLICT (id) DO NOTHING;
+"#;
+/// Removing the user also removes the anonymous pastes. Their owner is deleted with cascade
+static DELETE_ANONYMOUS_USER: &str = "DELETE FROM users WHERE id = 0;";
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            PastePost::Table,
+            ColumnDef::new(PastePost::DeleteToken)
+                .string()
+                .null()
+                .to_owned(),
+        )
+        .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(ANONYMOUS_USER)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(DELETE_ANONYMOUS_USER)
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PastePost::Table)
+                    .drop_column(PastePost::DeleteToken)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PastePost {
+    #[sea_orm(iden = "paste_posts")]
+    Table,
+    DeleteToken,
+}
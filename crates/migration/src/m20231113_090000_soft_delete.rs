@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+/// Adds `deleted_at` to paste and image posts. Deleted posts stay in the trash until they are purged
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            PastePost::Table,
+            ColumnDef::new(PastePost::DeletedAt)
+                .timestamp_with_time_zone()
+                .null()
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            ImagePost::Table,
+            ColumnDef::new(ImagePost::DeletedAt)
+                .timestamp_with_time_zone()
+                .null()
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(PastePost::Table)
+                    .drop_column(PastePost::DeletedAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImagePost::Table)
+                    .drop_column(ImagePost::DeletedAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum PastePost {
+    #[sea_orm(iden = "paste_posts")]
+    Table,
+    DeletedAt,
+}
+#[derive(DeriveIden)]
+pub enum ImagePost {
+    #[sea_orm(iden = "image_posts")]
+    Table,
+    DeletedAt,
+}
@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+/// Records the format and dimensions of images. Image post ids become unique so they can be used in URLs
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            Image::Table,
+            ColumnDef::new(Image::ContentType)
+                .string()
+                .not_null()
+                .default("application/octet-stream")
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            Image::Table,
+            ColumnDef::new(Image::Width)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            Image::Table,
+            ColumnDef::new(Image::Height)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        )
+        .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .if_not_exists()
+                    .unique()
+                    .name("idx-image_posts-id_str")
+                    .table(ImagePost::Table)
+                    .col(ImagePost::IdStr)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-image_posts-id_str")
+                    .table(ImagePost::Table)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Image::Table)
+                    .drop_column(Image::ContentType)
+                    .drop_column(Image::Width)
+                    .drop_column(Image::Height)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum Image {
+    #[sea_orm(iden = "image")]
+    Table,
+    ContentType,
+    Width,
+    Height,
+}
+#[derive(DeriveIden)]
+pub enum ImagePost {
+    #[sea_orm(iden = "image_posts")]
+    Table,
+    IdStr,
+}
@@ -0,0 +1,61 @@
+use sea_orm_migration::prelude::*;
+
+use crate::sea_orm::Schema;
+
+/// Variants reference blobs the same way images do
+static VARIANT_TRIGGER: &str = r#"
+CREATE TRIGGER image_variants_blob_references
+    AFTER INSERT OR UPDATE OF file OR DELETE
+    ON image_variants
+    FOR EACH ROW
+EXECUTE FUNCTION count_blob_references('file');
+"#;
+static DROP_TRIGGER: &str = r#"
+DROP TRIGGER IF EXISTS image_variants_blob_references ON image_variants;
+"#;
+
+/// Adds the resized copies of images
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let schema = Schema::new(manager.get_database_backend());
+        crate::entities!(schema, manager, entities::ImageVariantEntity);
+        manager
+            .create_index(
+                Index::create()
+                    .unique()
+                    .name("idx-image_variants-image_id-variant")
+                    .table(ImageVariant::Table)
+                    .col(ImageVariant::ImageId)
+                    .col(ImageVariant::Variant)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .get_connection()
+            .execute_unprepared(VARIANT_TRIGGER)
+            .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .get_connection()
+            .execute_unprepared(DROP_TRIGGER)
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImageVariant::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ImageVariant {
+    #[sea_orm(iden = "image_variants")]
+    Table,
+    ImageId,
+    Variant,
+}
@@ -0,0 +1,83 @@
+use std::{collections::HashMap, fs::File};
+
+use sea_orm_migration::{
+    prelude::*,
+    sea_orm::{DbBackend, Statement},
+};
+use sha2::{Digest, Sha256};
+
+/// The tables with a `FileLocation` column. Variants are always in the blob store
+static LOCATION_COLUMNS: [(&str, &str); 3] = [
+    ("paste_file", "location"),
+    ("paste_file_revisions", "location"),
+    ("image", "file"),
+];
+
+/// Records the SHA-256 of the files that are not in the blob store.
+///
+/// So their content is not hashed for every request. Files that can not be read are left without a hash
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        // Revisions share their files with the file rows
+        let mut hashes: HashMap<String, Option<String>> = HashMap::new();
+        for (table, column) in LOCATION_COLUMNS {
+            let rows = connection
+                .query_all(Statement::from_string(
+                    DbBackend::Postgres,
+                    format!(
+                        r#"SELECT id, {column} -> 'Local' ->> 'location' AS path FROM {table}
+                           WHERE {column} -> 'Local' IS NOT NULL AND {column} -> 'Local' -> 'hash' IS NULL;"#
+                    ),
+                ))
+                .await?;
+            for row in rows {
+                let id: i64 = row.try_get("", "id")?;
+                let path: String = row.try_get("", "path")?;
+                let hash = hashes
+                    .entry(path)
+                    .or_insert_with_key(|path| hash_file(path))
+                    .clone();
+                let Some(hash) = hash else {
+                    continue;
+                };
+                connection
+                    .execute(Statement::from_sql_and_values(
+                        DbBackend::Postgres,
+                        format!(
+                            r#"UPDATE {table}
+                               SET {column} = jsonb_set({column}::JSONB, '{{Local,hash}}', to_jsonb($1::TEXT))::JSON
+                               WHERE id = $2;"#
+                        ),
+                        [hash.into(), id.into()],
+                    ))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let connection = manager.get_connection();
+        for (table, column) in LOCATION_COLUMNS {
+            connection
+                .execute_unprepared(&format!(
+                    r#"UPDATE {table} SET {column} = ({column}::JSONB #- '{{Local,hash}}')::JSON
+                       WHERE {column} -> 'Local' IS NOT NULL;"#
+                ))
+                .await?;
+        }
+        Ok(())
+    }
+}
+/// SHA-256 of a file as lowercase hex. None if it can not be read
+fn hash_file(path: &str) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,53 @@
+use sea_orm_migration::prelude::*;
+
+/// Records the size of the preset a variant was created with. So variants are created again when a preset changes
+///
+/// Existing variants get a size of 0 and are created again the next time they are requested
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        crate::add_column_if_missing(
+            manager,
+            ImageVariant::Table,
+            ColumnDef::new(ImageVariant::MaxWidth)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        )
+        .await?;
+        crate::add_column_if_missing(
+            manager,
+            ImageVariant::Table,
+            ColumnDef::new(ImageVariant::MaxHeight)
+                .integer()
+                .not_null()
+                .default(0)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ImageVariant::Table)
+                    .drop_column(ImageVariant::MaxWidth)
+                    .drop_column(ImageVariant::MaxHeight)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+pub enum ImageVariant {
+    #[sea_orm(iden = "image_variants")]
+    Table,
+    MaxWidth,
+    MaxHeight,
+}
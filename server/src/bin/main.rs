@@ -7,6 +7,7 @@ use actix_web::{
     App, HttpRequest, HttpServer, Scope,
 };
 use clap::Parser;
+use common::paste::encryption::{ENCRYPTION_ALGORITHM, ENCRYPTION_NONCE};
 use digestible::Digestible;
 use entities::user::database_helpers::first_user;
 use helper_macros::{serde_as_rules, Response};
@@ -17,10 +18,13 @@ use nitro_share::{
     images,
     images::ImageRules,
     open_api, paste,
-    paste::PasteRules,
+    paste::{
+        anonymous::AnonymousPastes, password::PasteUnlocks, views::PasteViews, PasteRules,
+        DELETE_TOKEN_HEADER,
+    },
     responses::JsonResponse,
     state::State,
-    tracing_setup, user,
+    storage, tracing_setup, user,
     user::{
         middleware::HandleSession,
         session::{SessionManager, SessionManagerType},
@@ -56,6 +60,9 @@ async fn main() -> std::io::Result<()> {
         paste_rules,
         site_rules,
         profile_rules: public_profiles,
+        blob_store,
+        storage_check,
+        proxy,
         tracing,
     } = if !args.config.exists() {
         let config = ServerConfig::default();
@@ -101,7 +108,23 @@ async fn main() -> std::io::Result<()> {
     let payload_config =
         Data::new(PayloadConfig::default().limit(site_rules.max_payload.get_as_bytes()));
     let database = Data::new(database);
+    paste::expiration::start_reaper(
+        database.clone(),
+        paste_rules.clone(),
+        image_rules.clone(),
+        blob_store.clone(),
+    );
+    storage::start_checker(
+        database.clone(),
+        storage_check,
+        paste_rules.clone(),
+        image_rules.clone(),
+        blob_store.clone(),
+    );
     let session = Data::new(session);
+    let paste_unlocks = Data::new(PasteUnlocks::default());
+    let anonymous_pastes = Data::new(AnonymousPastes::default());
+    let paste_views = Data::new(PasteViews::start(database.clone(), paste_rules.as_ref()));
     let openapi = open_api::ApiDoc::openapi();
 
     let server = HttpServer::new(move || {
@@ -109,6 +132,9 @@ async fn main() -> std::io::Result<()> {
             .allow_any_origin()
             .allow_any_header()
             .allow_any_method()
+            // Clients on other origins need the envelope to decrypt raw files
+            // and the delete token of anonymous raw pastes
+            .expose_headers([ENCRYPTION_ALGORITHM, ENCRYPTION_NONCE, DELETE_TOKEN_HEADER])
             .supports_credentials();
 
         App::new()
@@ -118,8 +144,13 @@ async fn main() -> std::io::Result<()> {
             .app_data(state.clone())
             .app_data(image_rules.clone())
             .app_data(paste_rules.clone())
+            .app_data(blob_store.clone())
+            .app_data(paste_unlocks.clone())
+            .app_data(anonymous_pastes.clone())
+            .app_data(paste_views.clone())
             .app_data(public_profiles.clone())
             .app_data(payload_config.clone())
+            .app_data(proxy.clone())
             .wrap(TracingLogger::default())
             .wrap(cors)
             .service(
@@ -144,7 +175,15 @@ async fn main() -> std::io::Result<()> {
                     .wrap(HandleSession {
                         session_manager: session.clone().into_inner(),
                     })
-                    .service(Scope::new("/paste").configure(paste::init)),
+                    .service(Scope::new("/paste").configure(paste::init_raw))
+                    .service(Scope::new("/images").configure(images::init_raw)),
+            )
+            .service(
+                Scope::new("/render")
+                    .wrap(HandleSession {
+                        session_manager: session.clone().into_inner(),
+                    })
+                    .service(Scope::new("/paste").configure(paste::init_render)),
             )
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}").url("/api-docs/openapi.json", openapi.clone()),
@@ -0,0 +1,297 @@
+//! Content addressed storage for uploaded files.
+//!
+//! Content is stored once at `{location}/{first two characters of the hash}/{SHA-256 of the content}`.
+//! Database triggers count the rows that reference each blob.
+//! Blobs without references are deleted by [BlobStore::collect_garbage]
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, Utc};
+use common::file_location::FileLocation;
+use config_types::chrono_types::duration::{ConfigDuration, Unit};
+use entities::blob::database_helpers::delete_unreferenced_blobs;
+use sea_orm::{DbErr, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::DatabaseConnection;
+
+/// The most blobs deleted in a single transaction
+const GARBAGE_COLLECTION_BATCH: u64 = 100;
+/// The size of the chunks read by [BlobStore::copy]
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlobStore {
+    pub location: PathBuf,
+    /// How long a blob is kept after its last reference is removed.
+    ///
+    /// Burned pastes are read after their rows are deleted. So this must be longer than a request
+    pub release_delay: ConfigDuration,
+}
+impl Default for BlobStore {
+    fn default() -> Self {
+        Self {
+            location: PathBuf::from("blobs"),
+            release_delay: ConfigDuration {
+                duration: Duration::minutes(10),
+                unit: Unit::Minutes,
+            },
+        }
+    }
+}
+impl BlobStore {
+    /// Where the blob with the hash is stored
+    pub fn blob_path(&self, hash: &str) -> PathBuf {
+        self.location.join(&hash[..2]).join(hash)
+    }
+    /// A new temporary file. Inside the store so it can be renamed into place
+    pub fn temp_path(&self) -> PathBuf {
+        self.location
+            .join("tmp")
+            .join(Uuid::new_v4().simple().to_string())
+    }
+    /// Stages content that is already in memory
+    pub async fn write(&self, content: &[u8]) -> std::io::Result<StagedBlob> {
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&temp, content).await?;
+        Ok(self.staged(temp, Sha256::digest(content), content.len()))
+    }
+    /// Stages a copy of a file. The content is streamed. So it is never held in memory
+    pub async fn copy(&self, path: &Path) -> std::io::Result<StagedBlob> {
+        let temp = self.temp_path();
+        if let Some(parent) = temp.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut source = File::open(path).await?;
+        let mut destination = File::create(&temp).await?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        let mut size = 0;
+        let result: std::io::Result<()> = async {
+            loop {
+                let read = source.read(&mut buffer).await?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..read]);
+                destination.write_all(&buffer[..read]).await?;
+                size += read;
+            }
+            destination.flush().await
+        }
+        .await;
+        if let Err(e) = result {
+            drop(destination);
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(e);
+        }
+        Ok(self.staged(temp, hasher.finalize(), size))
+    }
+    /// Creates the [StagedBlob] for a temporary file that has been written
+    pub fn staged(
+        &self,
+        temp: PathBuf,
+        digest: impl std::fmt::LowerHex,
+        size: usize,
+    ) -> StagedBlob {
+        let hash = format!("{digest:x}");
+        StagedBlob {
+            temp,
+            location: self.blob_path(&hash),
+            hash,
+            size,
+        }
+    }
+    /// Deletes the blobs that have not been referenced for [BlobStore::release_delay]
+    ///
+    /// # Returns
+    /// The number of blobs deleted
+    pub async fn collect_garbage(&self, database: &DatabaseConnection) -> Result<u64, DbErr> {
+        let released_before = Utc::now() - self.release_delay.duration;
+        let mut deleted = 0;
+        loop {
+            let transaction = database.begin().await?;
+            let hashes = delete_unreferenced_blobs(
+                &transaction,
+                released_before.into(),
+                GARBAGE_COLLECTION_BATCH,
+            )
+            .await?;
+            // The rows stay locked until the content is gone. So the blob can not be referenced again in between
+            for hash in &hashes {
+                let path = self.blob_path(hash);
+                if let Err(e) = tokio::fs::remove_file(&path).await {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        warn!("Failed to delete blob {path:?}: {e}");
+                    }
+                }
+            }
+            transaction.commit().await?;
+            deleted += hashes.len() as u64;
+            if (hashes.len() as u64) < GARBAGE_COLLECTION_BATCH {
+                return Ok(deleted);
+            }
+        }
+    }
+}
+
+/// Content in a temporary file that has not been added to the store.
+///
+/// Insert the rows referencing [StagedBlob::location] then call [StagedBlob::commit].
+/// Until it is referenced the blob could be deleted at any time
+#[derive(Debug)]
+#[must_use]
+pub struct StagedBlob {
+    temp: PathBuf,
+    hash: String,
+    location: PathBuf,
+    size: usize,
+}
+impl StagedBlob {
+    /// Where the content will be once it is committed
+    pub fn location(&self) -> FileLocation {
+        FileLocation::Blob {
+            hash: self.hash.clone(),
+            location: self.location.clone(),
+            size: self.size,
+        }
+    }
+    pub fn size(&self) -> usize {
+        self.size
+    }
+    /// The temporary file. For reading the content before it is committed
+    pub fn temp_path(&self) -> &Path {
+        &self.temp
+    }
+    /// Moves the content into the store.
+    ///
+    /// If the blob already exists it is replaced with the same content
+    pub async fn commit(self) -> std::io::Result<()> {
+        if let Some(parent) = self.location.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::rename(&self.temp, &self.location).await
+    }
+    /// Removes the temporary file
+    pub async fn discard(self) {
+        if let Err(e) = tokio::fs::remove_file(&self.temp).await {
+            warn!("Failed to remove staged blob {:?}: {e}", self.temp);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::Duration;
+    use config_types::chrono_types::duration::{ConfigDuration, Unit};
+    use entities::{paste::database_helpers::create_revision, BlobEntity, PastePostEntity};
+    use sea_orm::EntityTrait;
+    use sha2::{Digest, Sha256};
+    use uuid::Uuid;
+
+    use super::BlobStore;
+    use crate::{test_utils, DatabaseConnection};
+
+    fn store(directory: &Path, release_delay: Duration) -> BlobStore {
+        BlobStore {
+            location: directory.to_path_buf(),
+            release_delay: ConfigDuration {
+                duration: release_delay,
+                unit: Unit::Minutes,
+            },
+        }
+    }
+    async fn reference_count(database: &DatabaseConnection, hash: &str) -> Option<i64> {
+        BlobEntity::find_by_id(hash.to_owned())
+            .one(database)
+            .await
+            .unwrap()
+            .map(|blob| blob.reference_count)
+    }
+
+    #[actix_web::test]
+    async fn copy_hashes_the_content() {
+        let directory = tempfile::tempdir().unwrap();
+        let store = store(directory.path(), Duration::minutes(10));
+        // Larger than one chunk
+        let content = vec![7u8; super::COPY_BUFFER_SIZE * 2 + 3];
+        let source = directory.path().join("source");
+        std::fs::write(&source, &content).unwrap();
+
+        let staged = store.copy(&source).await.unwrap();
+        let hash = format!("{:x}", Sha256::digest(&content));
+        assert_eq!(staged.size(), content.len());
+        assert_eq!(staged.location().blob_hash(), Some(hash.as_str()));
+        staged.commit().await.unwrap();
+        assert_eq!(std::fs::read(store.blob_path(&hash)).unwrap(), content);
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn revisions_are_counted() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let store = store(directory.path(), Duration::minutes(10));
+        let staged = store.write(Uuid::new_v4().as_bytes()).await.unwrap();
+        let location = staged.location();
+        let hash = location.blob_hash().unwrap().to_owned();
+        staged.commit().await.unwrap();
+
+        let post_id = test_utils::create_paste(&database).await;
+        let file_id = test_utils::create_file(&database, post_id, "a.txt", location.clone()).await;
+        assert_eq!(reference_count(&database, &hash).await, Some(1));
+        // A revision with the same content is another reference
+        create_revision(&database, file_id, 2, location, None, 0)
+            .await
+            .unwrap();
+        assert_eq!(reference_count(&database, &hash).await, Some(2));
+
+        PastePostEntity::delete_by_id(post_id)
+            .exec(&database)
+            .await
+            .unwrap();
+        assert_eq!(reference_count(&database, &hash).await, Some(0));
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn garbage_is_kept_for_the_release_delay() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let delayed = store(directory.path(), Duration::minutes(10));
+        let staged = delayed.write(Uuid::new_v4().as_bytes()).await.unwrap();
+        let location = staged.location();
+        let hash = location.blob_hash().unwrap().to_owned();
+        staged.commit().await.unwrap();
+        let post_id = test_utils::create_paste(&database).await;
+        test_utils::create_file(&database, post_id, "a.txt", location).await;
+
+        // Referenced blobs are never collected
+        let immediate = store(directory.path(), Duration::zero());
+        immediate.collect_garbage(&database).await.unwrap();
+        assert_eq!(reference_count(&database, &hash).await, Some(1));
+
+        PastePostEntity::delete_by_id(post_id)
+            .exec(&database)
+            .await
+            .unwrap();
+        delayed.collect_garbage(&database).await.unwrap();
+        assert_eq!(reference_count(&database, &hash).await, Some(0));
+        assert!(delayed.blob_path(&hash).exists());
+
+        immediate.collect_garbage(&database).await.unwrap();
+        assert_eq!(reference_count(&database, &hash).await, None);
+        assert!(!immediate.blob_path(&hash).exists());
+    }
+}
@@ -1,10 +1,16 @@
 pub mod tracing;
 
-use std::path::PathBuf;
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
-use actix_web::web::Data;
+use actix_web::{web::Data, HttpRequest};
 use chrono::Duration;
-use config_types::{chrono_types::duration::ConfigDuration, size_config::ConfigSize};
+use config_types::{
+    chrono_types::duration::{ConfigDuration, Unit},
+    size_config::ConfigSize,
+};
 use digestible::Digestible;
 use entities::user::permissions::Permissions;
 use helper_macros::Rules;
@@ -12,7 +18,7 @@ use sea_orm::ConnectOptions;
 use serde::{Deserialize, Serialize};
 use typeshare::typeshare;
 
-use crate::{images::ImageRules, paste::PasteRules};
+use crate::{blob::BlobStore, images::ImageRules, paste::PasteRules, storage::StorageCheckConfig};
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(default)]
@@ -26,6 +32,11 @@ pub struct ServerConfig {
     pub paste_rules: Data<PasteRules>,
     pub site_rules: Data<SiteRules>,
     pub profile_rules: Data<ProfileRules>,
+    /// Where uploaded files are stored
+    pub blob_store: Data<BlobStore>,
+    /// Checks that the files on disk match the database
+    pub storage_check: StorageCheckConfig,
+    pub proxy: Data<ProxyConfig>,
     pub tracing: tracing::TracingConfiguration,
 }
 #[derive(Debug, Deserialize, Serialize, Rules, Digestible)]
@@ -38,11 +49,75 @@ pub struct SiteRules {
     pub require_email_verification: bool,
     #[rule]
     pub name: String,
+    /// The largest request body that is not an upload. Also the largest details field sent with an upload
+    ///
+    /// Uploads are limited by the rules of the upload route. Such as [PasteRules] and [ImageRules]
     #[rule(serialize_with = config_types::size_config::serde_impl::serialize_as_u64)]
     #[typeshare(typescript(type = "bigint"))]
     pub max_payload: ConfigSize,
     #[rule]
     pub anonymous_permissions: Permissions,
+    /// Storage limits for users
+    #[rule]
+    pub user_quota: StorageQuota,
+    /// Storage limits for users with the admin permission
+    #[rule]
+    pub admin_quota: StorageQuota,
+    /// Storage limits for pastes created without logging in
+    ///
+    /// This is one pool shared by every anonymous client. [SiteRules::anonymous_paste_limit] limits each address
+    #[rule]
+    pub anonymous_quota: StorageQuota,
+    /// How many pastes one address can create without logging in
+    pub anonymous_paste_limit: AnonymousPasteLimit,
+}
+impl SiteRules {
+    /// The storage limits for a user with the given permissions
+    ///
+    /// The admin tier is chosen by [Permissions::admin]. Paste and image admins without it get [SiteRules::user_quota].
+    /// [crate::paste::PasteRules::lifetime_for] uses the same tiers
+    pub fn quota_for(&self, permissions: &Permissions) -> &StorageQuota {
+        if permissions.admin {
+            &self.admin_quota
+        } else {
+            &self.user_quota
+        }
+    }
+}
+/// Limits on how much a single user can store. None is unlimited
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Digestible)]
+#[serde(default)]
+#[typeshare]
+pub struct StorageQuota {
+    /// Total size of all paste files. Including previous revisions
+    #[typeshare(typescript(type = "bigint"))]
+    pub max_paste_storage: Option<ConfigSize>,
+    #[typeshare(typescript(type = "bigint"))]
+    pub max_pastes: Option<u64>,
+    #[typeshare(typescript(type = "bigint"))]
+    pub max_image_storage: Option<ConfigSize>,
+    #[typeshare(typescript(type = "bigint"))]
+    pub max_images: Option<u64>,
+}
+/// How many pastes one address can create without logging in. So one client can not fill [SiteRules::anonymous_quota]
+#[derive(Debug, Clone, Deserialize, Serialize, Digestible)]
+#[serde(default)]
+#[typeshare]
+pub struct AnonymousPasteLimit {
+    pub max_pastes: u32,
+    #[typeshare(typescript(type = "string"))]
+    pub window: ConfigDuration,
+}
+impl Default for AnonymousPasteLimit {
+    fn default() -> Self {
+        Self {
+            max_pastes: 10,
+            window: ConfigDuration {
+                duration: Duration::minutes(60),
+                unit: Unit::Minutes,
+            },
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Rules, Digestible)]
@@ -68,9 +143,55 @@ impl Default for SiteRules {
             max_payload: ConfigSize::new_from_kibibytes(256),
             require_email_verification: false,
             anonymous_permissions: Permissions::new_anonymous(),
+            user_quota: StorageQuota::default(),
+            admin_quota: StorageQuota::default(),
+            anonymous_quota: StorageQuota::default(),
+            anonymous_paste_limit: AnonymousPasteLimit::default(),
         }
     }
 }
+/// How the address of a client is found
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// Uses the `Forwarded` and `X-Forwarded-For` headers for the address of the client.
+    ///
+    /// Only enable this behind a reverse proxy that sets them. Otherwise clients can send any address
+    pub trust_forwarded_headers: bool,
+}
+impl ProxyConfig {
+    pub fn client_address(&self, req: &HttpRequest) -> Option<IpAddr> {
+        if self.trust_forwarded_headers {
+            let forwarded = req
+                .connection_info()
+                .realip_remote_addr()
+                .and_then(parse_address);
+            if forwarded.is_some() {
+                return forwarded;
+            }
+        }
+        req.peer_addr().map(|address| address.ip())
+    }
+}
+/// Forwarded addresses can include a port. IPv6 addresses can be in brackets
+fn parse_address(address: &str) -> Option<IpAddr> {
+    if let Ok(address) = address.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+    address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+/// The address of the client that sent the request. Following the [ProxyConfig] of the server
+pub fn client_address(req: &HttpRequest) -> Option<IpAddr> {
+    match req.app_data::<Data<ProxyConfig>>() {
+        Some(proxy) => proxy.client_address(req),
+        None => ProxyConfig::default().client_address(req),
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TlsConfig {
     pub private_key: PathBuf,
@@ -89,6 +210,9 @@ impl Default for ServerConfig {
             paste_rules: Default::default(),
             site_rules: Default::default(),
             profile_rules: Default::default(),
+            blob_store: Default::default(),
+            storage_check: Default::default(),
+            proxy: Default::default(),
             tracing: Default::default(),
         }
     }
@@ -168,3 +292,67 @@ impl Into<ConnectOptions> for Database {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+    use actix_web::{test::TestRequest, web::Data};
+
+    use super::{client_address, parse_address, ProxyConfig};
+
+    const PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 4000);
+
+    fn forwarded_request(trust_forwarded_headers: bool) -> TestRequest {
+        TestRequest::default()
+            .peer_addr(PEER)
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .app_data(Data::new(ProxyConfig {
+                trust_forwarded_headers,
+            }))
+    }
+
+    #[test]
+    fn forwarded_headers_are_ignored_by_default() {
+        let req = forwarded_request(false).to_http_request();
+        assert_eq!(client_address(&req), Some(PEER.ip()));
+        let req = TestRequest::default()
+            .peer_addr(PEER)
+            .insert_header(("x-forwarded-for", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(client_address(&req), Some(PEER.ip()));
+    }
+
+    #[test]
+    fn trusted_forwarded_headers() {
+        let req = forwarded_request(true).to_http_request();
+        assert_eq!(
+            client_address(&req),
+            Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+        );
+        let req = TestRequest::default()
+            .peer_addr(PEER)
+            .insert_header(("forwarded", "for=203.0.113.9"))
+            .app_data(Data::new(ProxyConfig {
+                trust_forwarded_headers: true,
+            }))
+            .to_http_request();
+        assert_eq!(
+            client_address(&req),
+            Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 9)))
+        );
+    }
+
+    #[test]
+    fn forwarded_addresses_with_ports() {
+        let v6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        assert_eq!(parse_address("[2001:db8::1]:4711"), Some(v6));
+        assert_eq!(parse_address("[2001:db8::1]"), Some(v6));
+        assert_eq!(parse_address("2001:db8::1"), Some(v6));
+        assert_eq!(
+            parse_address("203.0.113.9:4711"),
+            Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 9)))
+        );
+        assert_eq!(parse_address("unknown"), None);
+    }
+}
@@ -1,12 +1,14 @@
-use actix_web::ResponseError;
+use actix_multipart::MultipartError;
+use actix_web::{error::PayloadError, http::header::InvalidHeaderValue, ResponseError};
+use common::paste::file_name::InvalidFileName;
 use either::Either;
 use sea_orm::DbErr;
 use serde::{ser::SerializeStruct, Serialize};
-use simdutf8::basic::Utf8Error;
+use simdutf8::compat::Utf8Error;
 use this_actix_error::ActixError;
 use thiserror::Error;
 
-use crate::user::session::SessionError;
+use crate::{paste::render::RenderError, user::session::SessionError};
 
 #[derive(Debug, Error, ActixError)]
 pub enum WebsiteError {
@@ -29,10 +31,91 @@ pub enum WebsiteError {
     NotFound,
     #[error("Bad Request")]
     #[status_code(BAD_REQUEST)]
-    UTF8ERROR(Utf8Error),
+    UTF8ERROR(#[from] Utf8Error),
     #[error("Exceeds Maximum Length")]
     #[status_code(BAD_REQUEST)]
     ExceedsMaxLength,
+    #[error("Invalid File Type")]
+    #[status_code(BAD_REQUEST)]
+    InvalidFileType,
+    #[error("Invalid Visibility")]
+    #[status_code(BAD_REQUEST)]
+    InvalidVisibility,
+    #[error("Invalid File Name: {0}")]
+    #[status_code(BAD_REQUEST)]
+    InvalidFileName(#[from] InvalidFileName),
+    #[error("File Already Exists")]
+    #[status_code(CONFLICT)]
+    FileAlreadyExists,
+    #[error("Paste names can not be empty")]
+    #[status_code(BAD_REQUEST)]
+    EmptyPasteName,
+    #[error("Invalid Slug. Use 3 to 64 lowercase letters, digits and dashes")]
+    #[status_code(BAD_REQUEST)]
+    InvalidSlug,
+    #[error("Paste ID Already In Use")]
+    #[status_code(CONFLICT)]
+    PasteIdTaken,
+    #[error("Invalid Line Range")]
+    #[status_code(BAD_REQUEST)]
+    InvalidLineRange,
+    #[error("Expiration must be in the future")]
+    #[status_code(BAD_REQUEST)]
+    InvalidExpiration,
+    #[error("Exceeds Maximum Paste Size")]
+    #[status_code(BAD_REQUEST)]
+    ExceedsMaxPasteSize,
+    #[error("Storage Quota Exceeded")]
+    #[status_code(FORBIDDEN)]
+    QuotaExceeded,
+    #[error("Failed to read the request body")]
+    #[status_code(BAD_REQUEST)]
+    PayloadError(#[from] PayloadError),
+    #[error("Invalid Multipart Request")]
+    #[status_code(BAD_REQUEST)]
+    MultipartError(#[from] MultipartError),
+    #[error("Invalid JSON")]
+    #[status_code(BAD_REQUEST)]
+    InvalidJson(serde_json::Error),
+    #[error("Unexpected Field {0}")]
+    #[status_code(BAD_REQUEST)]
+    UnexpectedField(String),
+    #[error("Missing Field {0}")]
+    #[status_code(BAD_REQUEST)]
+    MissingField(&'static str),
+    #[error("Request Too Large")]
+    #[status_code(PAYLOAD_TOO_LARGE)]
+    RequestTooLarge,
+    #[error("Too Many Files")]
+    #[status_code(BAD_REQUEST)]
+    TooManyFiles,
+    #[error("Too many incorrect passwords. Try again later")]
+    #[status_code(TOO_MANY_REQUESTS)]
+    TooManyAttempts,
+    #[error("Too many pastes created without logging in. Try again later")]
+    #[status_code(TOO_MANY_REQUESTS)]
+    TooManyPastes,
+    #[error("Invalid Encryption Envelope")]
+    #[status_code(BAD_REQUEST)]
+    InvalidEncryption,
+    #[error("Not supported for encrypted pastes")]
+    #[status_code(BAD_REQUEST)]
+    EncryptedPaste,
+    #[error("Not supported for burn after read pastes")]
+    #[status_code(BAD_REQUEST)]
+    BurnAfterRead,
+    #[error("Revision {0} is not valid UTF-8 and can not be compared")]
+    #[status_code(BAD_REQUEST)]
+    RevisionNotUtf8(i32),
+    #[error("Not a supported image")]
+    #[status_code(BAD_REQUEST)]
+    InvalidImage,
+    #[error("Invalid Stored Header")]
+    #[status_code(INTERNAL_SERVER_ERROR)]
+    InvalidHeader(#[from] InvalidHeaderValue),
+    #[error("Failed to render file")]
+    #[status_code(INTERNAL_SERVER_ERROR)]
+    RenderError(#[from] RenderError),
 }
 
 /// Implemented for responses that can partially fail.
@@ -0,0 +1,271 @@
+//! Responses for stored files.
+//!
+//! Validators, conditional requests and byte ranges are handled the same way for every [FileLocation]
+use std::{io::SeekFrom, time::SystemTime};
+
+use actix_web::{
+    body::SizedStream,
+    http::{
+        header::{
+            ContentRange, ContentRangeSpec, ETag, EntityTag, Header, HttpDate, IfModifiedSince,
+            IfNoneMatch, IfRange, LastModified, Range, ACCEPT_RANGES, IF_MODIFIED_SINCE,
+            IF_NONE_MATCH, IF_RANGE, RANGE,
+        },
+        StatusCode,
+    },
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use common::file_location::FileLocation;
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+/// An opened stored file and its validators
+pub struct StoredFile {
+    /// Opened when the response is prepared. So it can still be read if the file is removed afterwards
+    file: File,
+    /// The stored size can be out of date if the file was changed directly
+    length: u64,
+    /// The hash of the content. Weak and based on the size and modification time if no hash is stored
+    etag: EntityTag,
+    last_modified: HttpDate,
+}
+/// The part of the file a request asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RequestedRange {
+    Full,
+    /// First and last byte. Inclusive
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+impl StoredFile {
+    /// Opens the file
+    ///
+    /// # Parameters
+    /// - `modified` - When the content was created. Sent as `Last-Modified`
+    pub async fn new(location: &FileLocation, modified: SystemTime) -> std::io::Result<Self> {
+        let file = File::open(location.path()).await?;
+        let metadata = file.metadata().await?;
+        let length = metadata.len();
+        let etag = match location.content_hash() {
+            Some(hash) => EntityTag::new_strong(hash.to_owned()),
+            // Files without a stored hash are not hashed for every request
+            None => {
+                let modified = metadata
+                    .modified()?
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default();
+                EntityTag::new_weak(format!("{length:x}-{:x}", modified.as_nanos()))
+            }
+        };
+        Ok(Self {
+            file,
+            length,
+            etag,
+            last_modified: HttpDate::from(modified),
+        })
+    }
+    /// True if the copy the client has is current.
+    ///
+    /// `If-Modified-Since` is ignored when `If-None-Match` is sent
+    fn is_not_modified(&self, request: &HttpRequest) -> bool {
+        if request.headers().contains_key(IF_NONE_MATCH) {
+            return match IfNoneMatch::parse(request) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&self.etag)),
+                Err(_) => false,
+            };
+        }
+        if request.headers().contains_key(IF_MODIFIED_SINCE) {
+            if let Ok(IfModifiedSince(since)) = IfModifiedSince::parse(request) {
+                return SystemTime::from(self.last_modified) <= SystemTime::from(since);
+            }
+        }
+        false
+    }
+    /// Only a single range is supported. Requests for multiple ranges get the whole file
+    fn requested_range(&self, request: &HttpRequest, length: u64) -> RequestedRange {
+        if !request.headers().contains_key(RANGE) {
+            return RequestedRange::Full;
+        }
+        // The range is only valid for the content the client already has
+        if request.headers().contains_key(IF_RANGE) {
+            let is_current = match IfRange::parse(request) {
+                Ok(IfRange::EntityTag(tag)) => tag.strong_eq(&self.etag),
+                Ok(IfRange::Date(date)) => date == self.last_modified,
+                Err(_) => false,
+            };
+            if !is_current {
+                return RequestedRange::Full;
+            }
+        }
+        match Range::parse(request) {
+            Ok(Range::Bytes(ranges)) if ranges.len() == 1 => {
+                match ranges[0].to_satisfiable_range(length) {
+                    Some((start, end)) => RequestedRange::Partial(start, end),
+                    None => RequestedRange::Unsatisfiable,
+                }
+            }
+            _ => RequestedRange::Full,
+        }
+    }
+    /// Responds with the file. `304 Not Modified`, `206 Partial Content` or `416 Range Not Satisfiable` if requested.
+    ///
+    /// `response` contains the headers describing the file.
+    /// HEAD requests get the same response. The server does not send the body
+    pub async fn respond(
+        mut self,
+        request: &HttpRequest,
+        mut response: HttpResponseBuilder,
+    ) -> std::io::Result<HttpResponse> {
+        response
+            .insert_header(ETag(self.etag.clone()))
+            .insert_header(LastModified(self.last_modified))
+            .insert_header((ACCEPT_RANGES, "bytes"));
+        if self.is_not_modified(request) {
+            return Ok(response.status(StatusCode::NOT_MODIFIED).finish());
+        }
+        let length = self.length;
+        let (start, end) = match self.requested_range(request, length) {
+            RequestedRange::Full => (0, length),
+            RequestedRange::Partial(start, end) => {
+                response
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: Some((start, end)),
+                        instance_length: Some(length),
+                    }));
+                (start, end + 1)
+            }
+            RequestedRange::Unsatisfiable => {
+                return Ok(response
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .insert_header(ContentRange(ContentRangeSpec::Bytes {
+                        range: None,
+                        instance_length: Some(length),
+                    }))
+                    .finish());
+            }
+        };
+        if start > 0 {
+            self.file.seek(SeekFrom::Start(start)).await?;
+        }
+        let body = ReaderStream::new(self.file.take(end - start));
+        Ok(response.body(SizedStream::new(end - start, body)))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::SystemTime};
+
+    use actix_web::{
+        body::to_bytes,
+        http::{
+            header::{CONTENT_RANGE, ETAG, IF_NONE_MATCH, IF_RANGE, RANGE},
+            StatusCode,
+        },
+        test::TestRequest,
+        HttpResponse,
+    };
+    use common::file_location::FileLocation;
+
+    use super::StoredFile;
+
+    const CONTENT: &[u8] = b"0123456789";
+    const HASH: &str = "84d89877f0d4041efb6bf91a16f0248f2fd573e6af05c19f96bedb9f882f7882";
+
+    fn blob(directory: &Path) -> FileLocation {
+        let location = directory.join(HASH);
+        std::fs::write(&location, CONTENT).unwrap();
+        FileLocation::Blob {
+            hash: HASH.to_owned(),
+            location,
+            size: CONTENT.len(),
+        }
+    }
+    async fn respond(location: &FileLocation, request: TestRequest) -> HttpResponse {
+        let stored = StoredFile::new(location, SystemTime::UNIX_EPOCH)
+            .await
+            .unwrap();
+        stored
+            .respond(&request.to_http_request(), HttpResponse::Ok())
+            .await
+            .unwrap()
+    }
+    fn header(response: &HttpResponse, name: impl actix_web::http::header::AsHeaderName) -> &str {
+        response.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn full_blob() {
+        let directory = tempfile::tempdir().unwrap();
+        let response = respond(&blob(directory.path()), TestRequest::default()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, ETAG), format!("\"{HASH}\""));
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), CONTENT);
+    }
+    #[actix_web::test]
+    async fn blob_range() {
+        let directory = tempfile::tempdir().unwrap();
+        let request = TestRequest::default().insert_header((RANGE, "bytes=2-5"));
+        let response = respond(&blob(directory.path()), request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header(&response, CONTENT_RANGE), "bytes 2-5/10");
+        assert_eq!(
+            to_bytes(response.into_body()).await.unwrap(),
+            &CONTENT[2..6]
+        );
+    }
+    #[actix_web::test]
+    async fn blob_suffix_range() {
+        let directory = tempfile::tempdir().unwrap();
+        let request = TestRequest::default().insert_header((RANGE, "bytes=-3"));
+        let response = respond(&blob(directory.path()), request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), &CONTENT[7..]);
+    }
+    #[actix_web::test]
+    async fn blob_range_outside_of_the_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let request = TestRequest::default().insert_header((RANGE, "bytes=20-"));
+        let response = respond(&blob(directory.path()), request).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header(&response, CONTENT_RANGE), "bytes */10");
+    }
+    #[actix_web::test]
+    async fn blob_range_for_other_content() {
+        let directory = tempfile::tempdir().unwrap();
+        let request = TestRequest::default()
+            .insert_header((RANGE, "bytes=2-5"))
+            .insert_header((IF_RANGE, "\"other\""));
+        let response = respond(&blob(directory.path()), request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(to_bytes(response.into_body()).await.unwrap(), CONTENT);
+    }
+    #[actix_web::test]
+    async fn blob_not_modified() {
+        let directory = tempfile::tempdir().unwrap();
+        let request = TestRequest::default().insert_header((IF_NONE_MATCH, format!("\"{HASH}\"")));
+        let response = respond(&blob(directory.path()), request).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+    #[actix_web::test]
+    async fn local_file_without_hash() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("file");
+        std::fs::write(&path, CONTENT).unwrap();
+        let location = FileLocation::new_local(path, CONTENT.len());
+
+        let response = respond(&location, TestRequest::default()).await;
+        let etag = header(&response, ETAG).to_owned();
+        assert!(etag.starts_with("W/"));
+        // Weak validators can not be used for ranges
+        let request = TestRequest::default()
+            .insert_header((RANGE, "bytes=2-5"))
+            .insert_header((IF_RANGE, etag));
+        let response = respond(&location, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,469 @@
+//! Uploading images.
+//!
+//! Every image is decoded before it is stored. A file that only claims to be an image is refused
+use std::path::{Path, PathBuf};
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::header::LOCATION, post, web, web::Data, HttpResponse};
+use common::paste::file_name::normalize_file_name;
+use entities::{
+    image::{ImageFile, ImagePost},
+    user::database_helpers::get_storage_usage,
+    ImageFileActiveModel, ImageFileEntity, ImagePostActiveModel, ImagePostEntity,
+};
+use image::{
+    io::{Limits, Reader},
+    ImageFormat,
+};
+use sea_orm::{prelude::*, ActiveValue::Set, EntityTrait, NotSet, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use utoipa::{
+    openapi::{
+        AllOfBuilder, ArrayBuilder, KnownFormat, ObjectBuilder, Ref, RefOr, Schema, SchemaFormat,
+        SchemaType,
+    },
+    ToSchema,
+};
+
+use crate::{
+    blob::{BlobStore, StagedBlob},
+    config::SiteRules,
+    error::WebsiteError,
+    images::ImageRules,
+    paste::{create_routes::FileUploadError, paste_id::is_id_taken},
+    policy::{can_create, Actor, Resource},
+    upload::{aborts_request, MultipartUpload},
+    user::{
+        quota::{check_image_count, UploadBudget},
+        Authentication,
+    },
+    DatabaseConnection,
+};
+
+/// The formats that browsers can display
+const SUPPORTED_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Bmp,
+];
+
+#[derive(Deserialize, Serialize, Debug, ToSchema)]
+#[serde(default)]
+pub struct NewImagePost {
+    #[schema(nullable)]
+    pub name: String,
+    #[schema(nullable)]
+    pub description: String,
+    #[schema(nullable)]
+    pub tags: Vec<String>,
+}
+impl Default for NewImagePost {
+    fn default() -> Self {
+        Self {
+            name: "Untitled".to_string(),
+            description: String::new(),
+            tags: vec![],
+        }
+    }
+}
+/// The multipart request used to upload images. Only used for the OpenAPI schema
+///
+/// Fields are read in the order they are sent. So `details` must be sent before `images`
+pub struct NewImages;
+impl<'a> ToSchema<'a> for NewImages {
+    fn schema() -> (&'a str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .property(
+                "details",
+                AllOfBuilder::new()
+                    .nullable(true)
+                    .item(Ref::from_schema_name("NewImagePost")),
+            )
+            .property(
+                "images",
+                ArrayBuilder::new().items(RefOr::T(
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::String)
+                        .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary)))
+                        .into(),
+                )),
+            )
+            .into();
+        ("NewImages", RefOr::T(schema))
+    }
+}
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NewImagePostResponse {
+    pub post: ImagePost,
+    pub images: Vec<ImageFile>,
+    pub errors: Vec<FileUploadError>,
+}
+
+/// The format and dimensions of a decoded image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DecodedImage {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+impl DecodedImage {
+    pub fn content_type(&self) -> &'static str {
+        match self.format {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::WebP => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+            _ => "application/octet-stream",
+        }
+    }
+}
+/// Decodes the whole image. The format is detected from the content
+///
+/// # Returns
+/// None if the file is not an image in one of the [SUPPORTED_FORMATS], it can not be decoded or it exceeds the limits
+pub(crate) fn decode_image(path: &Path, limits: Limits) -> std::io::Result<Option<DecodedImage>> {
+    let mut reader = Reader::open(path)?.with_guessed_format()?;
+    let Some(format) = reader.format().filter(|f| SUPPORTED_FORMATS.contains(f)) else {
+        return Ok(None);
+    };
+    reader.limits(limits);
+    let Ok(image) = reader.decode() else {
+        return Ok(None);
+    };
+    Ok(Some(DecodedImage {
+        format,
+        width: image.width(),
+        height: image.height(),
+    }))
+}
+/// Decodes the image on the blocking thread pool
+async fn decode_staged(content: &StagedBlob, rules: &ImageRules) -> crate::Result<DecodedImage> {
+    let path: PathBuf = content.temp_path().to_owned();
+    let limits = rules.decode_limits();
+    web::block(move || decode_image(&path, limits))
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))??
+        .ok_or(WebsiteError::InvalidImage)
+}
+
+/// Creates the post without any images
+///
+/// A generated id is replaced if it is already taken
+async fn create_post(
+    database: &impl ConnectionTrait,
+    details: NewImagePost,
+    rules: &ImageRules,
+    user_id: i64,
+) -> crate::Result<ImagePost> {
+    let NewImagePost {
+        name,
+        description,
+        tags,
+    } = details;
+    let post = ImagePostActiveModel {
+        id: NotSet,
+        id_str: NotSet,
+        user_id: Set(user_id),
+        name: Set(name),
+        tags: Set(tags),
+        description: Set(description),
+        deleted_at: Set(None),
+        last_updated: NotSet,
+        created: NotSet,
+    };
+    let id_rules = &rules.image_ids;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let string_id = id_rules.generate();
+        let post = ImagePostActiveModel {
+            id_str: Set(string_id.clone()),
+            ..post.clone()
+        };
+        match ImagePostEntity::insert(post)
+            .exec_with_returning(database)
+            .await
+        {
+            Ok(post) => return Ok(post.into()),
+            Err(err) if is_id_taken(&err) && attempts < id_rules.max_attempts => {
+                debug!("Generated image id {string_id} is taken. Generating another");
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+/// Stores a single image of the post
+///
+/// The image is streamed to the blob store and decoded before the row is inserted
+///
+/// # Returns
+/// - `Err((String, WebsiteError))` - If the image was not stored. String is the file name
+async fn handle_image_upload(
+    post_id: i64,
+    database: &DatabaseConnection,
+    upload: &mut MultipartUpload,
+    mut field: Field,
+    index: usize,
+    blobs: &BlobStore,
+    rules: &ImageRules,
+    budget: &mut UploadBudget,
+) -> Result<ImageFile, FileUploadError> {
+    let file_name = match field.content_disposition().get_filename() {
+        Some(file_name) => match normalize_file_name(file_name) {
+            Ok(file_name) => file_name,
+            Err(e) => return Err((file_name.to_owned(), e.into()).into()),
+        },
+        None => format!("image_{}", index),
+    };
+    debug!("Uploading image: {file_name:?}");
+    let content = match upload
+        .save_file(&mut field, blobs, true, |size| budget.check(size as u64))
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => return Err((file_name, e).into()),
+    };
+    let decoded = match decode_staged(&content, rules).await {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            content.discard().await;
+            return Err((file_name, e).into());
+        }
+    };
+    if let Err(e) = budget.reserve(content.size() as u64) {
+        content.discard().await;
+        return Err((file_name, e).into());
+    }
+    insert_staged_image(
+        database,
+        post_id,
+        file_name.clone(),
+        decoded,
+        content,
+        budget,
+    )
+    .await
+    .map_err(|e| (file_name, e).into())
+}
+/// Inserts an image with staged content. Then moves the content into the blob store
+///
+/// The image is inserted in a transaction that is committed after the content. The quota is checked again inside it.
+/// The content is discarded if the image can not be inserted
+async fn insert_staged_image(
+    database: &DatabaseConnection,
+    post_id: i64,
+    name: String,
+    decoded: DecodedImage,
+    content: StagedBlob,
+    budget: &UploadBudget,
+) -> crate::Result<ImageFile> {
+    let image = ImageFileActiveModel {
+        id: NotSet,
+        post_id: Set(post_id),
+        image: Set(name),
+        file: Set(content.location()),
+        content_type: Set(decoded.content_type().to_owned()),
+        width: Set(decoded.width as i32),
+        height: Set(decoded.height as i32),
+        last_updated: NotSet,
+        created: NotSet,
+    };
+    let result: crate::Result<_> = async {
+        let transaction = database.begin().await?;
+        let image = ImageFileEntity::insert(image)
+            .exec_with_returning(&transaction)
+            .await?;
+        budget.recheck(&transaction).await?;
+        Ok((transaction, image))
+    }
+    .await;
+    let (transaction, image) = match result {
+        Ok(ok) => ok,
+        Err(e) => {
+            content.discard().await;
+            return Err(e);
+        }
+    };
+    // Dropping the transaction rolls it back
+    content.commit().await?;
+    transaction.commit().await?;
+    Ok(image.into())
+}
+
+#[utoipa::path(post,
+    impl_for = new,
+    path = "/api/images/new",
+    request_body (content = NewImages, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "The new post. Files that are not images or exceed the storage quota are listed in errors", body = NewImagePostResponse),
+        (status = 400, description = "None of the images could be stored. No post is created"),
+        (status = 403, description = "You can not upload images or have reached the maximum number of image posts"),
+        (status = 413, description = "The request exceeds the upload limits. No post is created")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[post("/new")]
+pub async fn new(
+    auth: Authentication,
+    payload: Multipart,
+    database: Data<DatabaseConnection>,
+    rules: Data<ImageRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+) -> crate::Result<HttpResponse> {
+    let actor = Actor::from(&auth);
+    if !can_create(Resource::Image, &actor) {
+        return Err(WebsiteError::Forbidden);
+    }
+    let quota = site_rules.quota_for(actor.permissions());
+    let usage = get_storage_usage(database.as_ref(), auth.id()).await?;
+    check_image_count(quota, &usage)?;
+    let mut budget = UploadBudget::for_images(quota, auth.id(), &usage);
+    let mut upload = MultipartUpload::new(
+        payload,
+        rules.multipart_limits(site_rules.max_payload.get_as_bytes()),
+    );
+
+    // The post is created when the first image arrives. So the images can be written directly to it
+    let mut post: Option<ImagePost> = None;
+    let mut images = Vec::new();
+    let mut errors = Vec::new();
+    let result: crate::Result<()> = async {
+        let mut details = None;
+        let mut index = 0;
+        while let Some(mut field) = upload.next_field().await? {
+            let name = field.name().to_owned();
+            match name.as_str() {
+                "details" if post.is_none() => {
+                    details = Some(upload.read_json::<NewImagePost>(&mut field).await?);
+                }
+                "images" => {
+                    let post_id = match &post {
+                        Some(post) => post.id,
+                        None => {
+                            let created = create_post(
+                                database.as_ref(),
+                                details.take().unwrap_or_default(),
+                                rules.as_ref(),
+                                auth.id(),
+                            )
+                            .await?;
+                            let id = created.id;
+                            post = Some(created);
+                            id
+                        }
+                    };
+                    let image = handle_image_upload(
+                        post_id,
+                        database.as_ref(),
+                        &mut upload,
+                        field,
+                        index,
+                        blobs.as_ref(),
+                        rules.as_ref(),
+                        &mut budget,
+                    )
+                    .await;
+                    index += 1;
+                    match image {
+                        Ok(image) => images.push(image),
+                        Err(err) if aborts_request(&err.error) => return Err(err.error),
+                        Err(err) => errors.push(err),
+                    }
+                }
+                _ => return Err(WebsiteError::UnexpectedField(name)),
+            }
+        }
+        Ok(())
+    }
+    .await;
+    let post = match (result, post) {
+        (Ok(()), Some(post)) if !images.is_empty() => post,
+        (result, post) => {
+            // A post without images is not kept
+            if let Some(post) = post {
+                ImagePostEntity::delete_by_id(post.id)
+                    .exec(database.as_ref())
+                    .await?;
+            }
+            result?;
+            return if errors.is_empty() {
+                Err(WebsiteError::MissingField("images"))
+            } else {
+                Ok(HttpResponse::BadRequest().json(errors))
+            };
+        }
+    };
+    Ok(HttpResponse::Created()
+        .insert_header((LOCATION, format!("/api/images/{}", post.id_str)))
+        .json(NewImagePostResponse {
+            post,
+            images,
+            errors,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{io::Limits, ImageFormat, RgbImage};
+
+    use super::{decode_image, DecodedImage};
+
+    fn write_png(path: &std::path::Path, width: u32, height: u32) {
+        RgbImage::new(width, height)
+            .save_with_format(path, ImageFormat::Png)
+            .unwrap();
+    }
+
+    #[test]
+    fn decodes_png() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        write_png(&path, 4, 3);
+        assert_eq!(
+            decode_image(&path, Limits::default()).unwrap(),
+            Some(DecodedImage {
+                format: ImageFormat::Png,
+                width: 4,
+                height: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn text_named_png_is_not_an_image() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("notes.png");
+        std::fs::write(&path, "These are not pixels").unwrap();
+        assert_eq!(decode_image(&path, Limits::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn truncated_png_is_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        write_png(&path, 64, 64);
+        let content = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &content[..content.len() / 2]).unwrap();
+        assert_eq!(decode_image(&path, Limits::default()).unwrap(), None);
+    }
+
+    #[test]
+    fn images_over_the_limits_are_rejected() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        write_png(&path, 64, 32);
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(32);
+        assert_eq!(decode_image(&path, limits).unwrap(), None);
+        let mut limits = Limits::default();
+        limits.max_alloc = Some(64);
+        assert_eq!(decode_image(&path, limits).unwrap(), None);
+    }
+}
@@ -0,0 +1,89 @@
+use actix_web::{delete, web, web::Data, HttpResponse};
+use chrono::Utc;
+use entities::{
+    image::{
+        database_helpers::{find_post_by_id, set_post_deleted},
+        ImageColumn,
+    },
+    ImageFileEntity, ImagePostModel,
+};
+use sea_orm::{prelude::*, EntityTrait, QueryFilter};
+
+use crate::{
+    policy::{can_delete, Actor, Resource},
+    user::Authentication,
+    DatabaseConnection,
+};
+
+/// Moves the post to the trash. It is deleted permanently by [purge_trash](super::trash::purge_trash)
+#[utoipa::path(delete,
+    impl_for = delete,
+    path = "/api/images/{id}",
+    params(
+        ("id", description = "The numeric id of the image post")
+    ),
+    responses(
+        (status = 204, description = "The post was moved to the trash"),
+        (status = 403, description = "You can not delete the post"),
+        (status = 404, description = "Image Post Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[delete("/{id}")]
+pub async fn delete(
+    auth: Authentication,
+    path: web::Path<i64>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<HttpResponse> {
+    let Some(post) = find_post_by_id(database.as_ref(), path.into_inner()).await? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !can_delete(Resource::Image, &Actor::from(&auth), &post) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    set_post_deleted(database.as_ref(), post.id, Some(Utc::now().into())).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Deletes a single image. Its content is released by the database triggers
+#[utoipa::path(delete,
+    impl_for = delete_image,
+    path = "/api/images/{id}/image/{image_id}",
+    params(
+        ("id", description = "The numeric id of the image post"),
+        ("image_id", description = "The numeric id of the image")
+    ),
+    responses(
+        (status = 204, description = "The image was deleted"),
+        (status = 403, description = "You can not delete the image"),
+        (status = 404, description = "Image Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[delete("/{id}/image/{image_id}")]
+pub async fn delete_image(
+    auth: Authentication,
+    path: web::Path<(i64, i64)>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<HttpResponse> {
+    let (id, image_id) = path.into_inner();
+    let post: ImagePostModel = find_post_by_id(database.as_ref(), id)
+        .await?
+        .ok_or(crate::Error::NotFound)?;
+    if !can_delete(Resource::Image, &Actor::from(&auth), &post) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let result = ImageFileEntity::delete_many()
+        .filter(ImageColumn::PostId.eq(post.id))
+        .filter(ImageColumn::Id.eq(image_id))
+        .exec(database.as_ref())
+        .await?;
+    if result.rows_affected == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,201 @@
+use actix_web::{
+    get,
+    http::header::{CacheControl, CacheDirective, ContentType, X_CONTENT_TYPE_OPTIONS},
+    web,
+    web::Data,
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use digestible::Digestible;
+use entities::{
+    image::{
+        database_helpers::{find_image, find_post_by_str_id, get_images},
+        ImageFile, ImagePost,
+    },
+    ImagePostModel,
+};
+use helper_macros::Response;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    error::WebsiteError,
+    file_response::StoredFile,
+    images::ImageRules,
+    policy::{can_view_image, Actor},
+    responses::JsonResponse,
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+/// One year. Images can not be replaced so their content never changes
+const IMMUTABLE_MAX_AGE: u32 = 31_536_000;
+
+#[derive(Debug, Serialize, ToSchema, Digestible, Response)]
+pub struct ImageList {
+    /// In the order they were uploaded
+    pub images: Vec<ImageFile>,
+}
+
+/// Checks that the request can view the images of the post
+pub(crate) fn check_can_view(
+    auth: &OptionalAuthentication,
+    rules: &ImageRules,
+    post: &ImagePostModel,
+) -> crate::Result<()> {
+    let actor = Actor::from(auth);
+    if can_view_image(&actor, rules, post) {
+        Ok(())
+    } else if actor.is_anonymous() {
+        Err(WebsiteError::Unauthorized)
+    } else {
+        Err(WebsiteError::Forbidden)
+    }
+}
+/// Finds a post that the request can view
+async fn find_visible_post(
+    database: &DatabaseConnection,
+    id: String,
+    auth: &OptionalAuthentication,
+    rules: &ImageRules,
+) -> crate::Result<ImagePostModel> {
+    let post = find_post_by_str_id(database, id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    check_can_view(auth, rules, &post)?;
+    Ok(post)
+}
+/// The headers of image content
+///
+/// Images are never changed. So they can be cached for a year.
+/// Variants are recreated at the same URL when their preset changes.
+/// So clients must check the ETag before using their copy.
+/// They are only cached by shared caches if images can be viewed without logging in
+pub(crate) fn image_response(
+    content_type: &str,
+    rules: &ImageRules,
+    immutable: bool,
+) -> HttpResponseBuilder {
+    let scope = if rules.show_without_login {
+        CacheDirective::Public
+    } else {
+        CacheDirective::Private
+    };
+    let content_type = content_type
+        .parse::<mime::Mime>()
+        .map(ContentType)
+        .unwrap_or_else(|_| ContentType::octet_stream());
+    let cache_control = if immutable {
+        CacheControl(vec![
+            scope,
+            CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ])
+    } else {
+        CacheControl(vec![scope, CacheDirective::NoCache])
+    };
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header(content_type)
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header(cache_control);
+    response
+}
+
+#[utoipa::path(get,
+    impl_for = get,
+    path = "/api/images/{id}",
+    params(
+        ("id", description = "The id of the image post")
+    ),
+    responses(
+        (status = 200, description = "The image post", body = ImagePost),
+        (status = 401, description = "Images can not be viewed without logging in"),
+        (status = 403, description = "You can not view images"),
+        (status = 404, description = "Image Post Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}")]
+pub async fn get(
+    id: web::Path<String>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    rules: Data<ImageRules>,
+) -> crate::Result<JsonResponse<ImagePost>> {
+    let post = find_visible_post(database.as_ref(), id.into_inner(), &auth, rules.as_ref()).await?;
+    Ok(JsonResponse::from(ImagePost::from(post)))
+}
+
+#[utoipa::path(get,
+    impl_for = get_post_images,
+    path = "/api/images/{id}/images",
+    params(
+        ("id", description = "The id of the image post")
+    ),
+    responses(
+        (status = 200, description = "The images of the post", body = ImageList),
+        (status = 401, description = "Images can not be viewed without logging in"),
+        (status = 403, description = "You can not view images"),
+        (status = 404, description = "Image Post Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/images")]
+pub async fn get_post_images(
+    id: web::Path<String>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    rules: Data<ImageRules>,
+) -> crate::Result<JsonResponse<ImageList>> {
+    let post = find_visible_post(database.as_ref(), id.into_inner(), &auth, rules.as_ref()).await?;
+    let images = get_images(database.as_ref(), post.id)
+        .await?
+        .into_iter()
+        .map(ImageFile::from)
+        .collect();
+    Ok(JsonResponse::from(ImageList { images }))
+}
+
+/// The content of an image. Served with the type detected when it was uploaded
+#[utoipa::path(get,
+    impl_for = get_image,
+    path = "/api/images/{id}/image/{image_id}",
+    params(
+        ("id", description = "The id of the image post"),
+        ("image_id", description = "The numeric id of the image")
+    ),
+    responses(
+        (status = 200, description = "The image"),
+        (status = 304, description = "The image has not changed"),
+        (status = 401, description = "Images can not be viewed without logging in"),
+        (status = 403, description = "You can not view images"),
+        (status = 404, description = "Image Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/image/{image_id}")]
+pub async fn get_image(
+    path: web::Path<(String, i64)>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    rules: Data<ImageRules>,
+    http_request: HttpRequest,
+) -> crate::Result<HttpResponse> {
+    let (id, image_id) = path.into_inner();
+    let post = find_visible_post(database.as_ref(), id, &auth, rules.as_ref()).await?;
+    let image = find_image(database.as_ref(), post.id, image_id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    let response = image_response(&image.content_type, rules.as_ref(), true);
+    let stored = StoredFile::new(&image.file, image.created.into()).await?;
+    Ok(stored.respond(&http_request, response).await?)
+}
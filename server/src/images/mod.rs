@@ -1,13 +1,42 @@
 use std::path::PathBuf;
 
 use actix_web::web;
-use config_types::size_config::ConfigSize;
+use chrono::Duration;
+use config_types::{
+    chrono_types::duration::{ConfigDuration, Unit},
+    size_config::ConfigSize,
+};
 use digestible::Digestible;
 use helper_macros::Rules;
-use serde::{Deserialize, Serialize};
+use image::io::Limits;
+use serde::{de, Deserialize, Deserializer, Serialize};
 use typeshare::typeshare;
 
-pub fn init(_cfg: &mut web::ServiceConfig) {}
+use crate::{paste::paste_id::PasteIdRules, upload::MultipartLimits};
+
+pub mod create_routes;
+pub mod delete_routes;
+pub mod get_routes;
+pub mod trash;
+pub mod variants;
+
+/// Four channels of 16 bits
+const MAX_BYTES_PER_PIXEL: u64 = 8;
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    // Registered before `/{id}` so it is not treated as a post id
+    cfg.service(trash::get_image_trash)
+        .service(create_routes::new)
+        .service(get_routes::get)
+        .service(get_routes::get_post_images)
+        .service(get_routes::get_image)
+        .service(trash::restore)
+        .service(delete_routes::delete)
+        .service(delete_routes::delete_image);
+}
+pub fn init_raw(cfg: &mut web::ServiceConfig) {
+    cfg.service(variants::get_variant);
+}
 #[derive(Debug, Deserialize, Serialize, Rules, Digestible)]
 #[serde(default)]
 #[typeshare]
@@ -17,17 +46,122 @@ pub struct ImageRules {
     pub max_image_size: ConfigSize,
     #[rule]
     pub show_without_login: bool,
+    /// The most images that can be uploaded in a single request
+    #[rule]
+    pub max_images_per_request: usize,
+    /// The widest image that can be uploaded. In pixels
+    #[rule]
+    pub max_width: u32,
+    /// The tallest image that can be uploaded. In pixels
+    #[rule]
+    pub max_height: u32,
+    /// The resized copies of images that can be requested from `/raw/images/{id}/{variant}`
+    ///
+    /// A preset can not be named `original`. That name is the unchanged image
+    #[rule]
+    #[serde(deserialize_with = "deserialize_variants")]
+    pub variants: Vec<ImageSizePreset>,
+    /// How long deleted image posts stay in the trash before they are deleted permanently
+    #[typeshare(typescript(type = "string"))]
+    pub trash_retention: ConfigDuration,
+    /// How the ids of new image posts are generated
+    #[digestible(skip)]
+    #[typeshare(skip)]
+    pub image_ids: PasteIdRules,
+    /// Where images from before the blob store are stored
     #[digestible(skip)]
     #[typeshare(skip)]
     pub location: PathBuf,
 }
 
+impl ImageRules {
+    /// The limits of the image upload routes
+    ///
+    /// # Parameters
+    /// - `max_field_size` - The size of the JSON details sent with the images
+    pub fn multipart_limits(&self, max_field_size: usize) -> MultipartLimits {
+        let max_file_size = self.max_image_size.get_as_bytes();
+        MultipartLimits {
+            max_request_size: max_file_size
+                .saturating_mul(self.max_images_per_request)
+                .saturating_add(max_field_size),
+            max_file_size,
+            max_files: self.max_images_per_request,
+            max_field_size,
+            require_utf8: false,
+        }
+    }
+    /// The limits used when decoding uploads. So a small file can not decode into a huge image
+    ///
+    /// The memory allowed is enough for the largest image with four 16 bit channels
+    pub fn decode_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(
+            (self.max_width as u64)
+                .saturating_mul(self.max_height as u64)
+                .saturating_mul(MAX_BYTES_PER_PIXEL),
+        );
+        limits
+    }
+    /// The size preset with the name
+    pub fn variant(&self, name: &str) -> Option<&ImageSizePreset> {
+        self.variants.iter().find(|preset| preset.name == name)
+    }
+}
 impl Default for ImageRules {
     fn default() -> Self {
         Self {
             max_image_size: ConfigSize::new_from_mebibytes(5),
             show_without_login: true,
+            max_images_per_request: 10,
+            max_width: 10_000,
+            max_height: 10_000,
+            variants: vec![
+                ImageSizePreset {
+                    name: "thumbnail".to_owned(),
+                    max_width: 256,
+                    max_height: 256,
+                },
+                ImageSizePreset {
+                    name: "preview".to_owned(),
+                    max_width: 1280,
+                    max_height: 1280,
+                },
+            ],
+            trash_retention: ConfigDuration {
+                duration: Duration::days(30),
+                unit: Unit::Days,
+            },
+            image_ids: PasteIdRules::default(),
             location: PathBuf::from("images"),
         }
     }
 }
+/// Rejects a preset named [variants::ORIGINAL]. It could never be requested
+fn deserialize_variants<'de, D>(deserializer: D) -> Result<Vec<ImageSizePreset>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let presets = Vec::<ImageSizePreset>::deserialize(deserializer)?;
+    if presets
+        .iter()
+        .any(|preset| preset.name == variants::ORIGINAL)
+    {
+        return Err(de::Error::custom(format!(
+            "`{}` is the unchanged image and can not be the name of a size preset",
+            variants::ORIGINAL
+        )));
+    }
+    Ok(presets)
+}
+/// A size that images are scaled down to. The aspect ratio is kept
+#[derive(Debug, Clone, Deserialize, Serialize, Digestible)]
+#[typeshare]
+pub struct ImageSizePreset {
+    /// Used in the URL of the variant. `original` is the unchanged image
+    pub name: String,
+    pub max_width: u32,
+    pub max_height: u32,
+}
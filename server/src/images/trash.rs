@@ -0,0 +1,212 @@
+//! Deleted image posts.
+//!
+//! Deleting an image post moves it to the trash. The owner can restore it until it is purged [ImageRules::trash_retention] after it was deleted
+use actix_web::{get, post, web, web::Data};
+use chrono::Utc;
+use digestible::Digestible;
+use entities::{
+    image::{
+        database_helpers::{find_deleted_post, get_trash, set_post_deleted},
+        ImagePost, PostColumn,
+    },
+    ImagePostEntity,
+};
+use helper_macros::Response;
+use sea_orm::prelude::*;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    error::WebsiteError,
+    images::ImageRules,
+    policy::{can_delete, Actor, Resource},
+    responses::JsonResponse,
+    user::Authentication,
+    DatabaseConnection,
+};
+
+#[derive(Debug, Serialize, ToSchema, Digestible)]
+pub struct TrashedImagePost {
+    pub id: i64,
+    pub id_str: String,
+    pub name: String,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub deleted_at: DateTimeWithTimeZone,
+    /// When the post will be deleted permanently
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub purged_at: DateTimeWithTimeZone,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible, Response)]
+#[private]
+pub struct ImageTrash {
+    /// Most recently deleted first
+    pub posts: Vec<TrashedImagePost>,
+}
+
+/// The deleted image posts of the user
+#[utoipa::path(get,
+    impl_for = get_image_trash,
+    path = "/api/images/trash",
+    responses(
+        (status = 200, description = "The image posts in the trash", body = ImageTrash),
+        (status = 401, description = "You are not logged in")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[get("/trash")]
+pub async fn get_image_trash(
+    auth: Authentication,
+    database: Data<DatabaseConnection>,
+    rules: Data<ImageRules>,
+) -> crate::Result<JsonResponse<ImageTrash>> {
+    let retention = rules.trash_retention.duration;
+    let posts = get_trash(database.as_ref(), auth.id())
+        .await?
+        .into_iter()
+        .filter_map(|post| {
+            let deleted_at = post.deleted_at?;
+            Some(TrashedImagePost {
+                id: post.id,
+                id_str: post.id_str,
+                name: post.name,
+                deleted_at,
+                purged_at: deleted_at + retention,
+            })
+        })
+        .collect();
+    Ok(JsonResponse::from(ImageTrash { posts }))
+}
+
+/// Moves an image post out of the trash
+#[utoipa::path(post,
+    impl_for = restore,
+    path = "/api/images/{id}/restore",
+    params(
+        ("id", description = "The numeric id of the image post")
+    ),
+    responses(
+        (status = 200, description = "The restored post", body = ImagePost),
+        (status = 403, description = "You can not restore the post"),
+        (status = 404, description = "The post is not in the trash")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[post("/{id}/restore")]
+pub async fn restore(
+    auth: Authentication,
+    id: web::Path<i64>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<ImagePost>> {
+    let mut post = find_deleted_post(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if !can_delete(Resource::Image, &Actor::from(&auth), &post) {
+        return Err(WebsiteError::Forbidden);
+    }
+    set_post_deleted(database.as_ref(), post.id, None).await?;
+    post.deleted_at = None;
+    Ok(JsonResponse::from(ImagePost::from(post)))
+}
+
+/// Permanently deletes the image posts that have been in the trash longer than [ImageRules::trash_retention]
+///
+/// Images in the blob store are released by the database triggers
+pub async fn purge_trash(database: &DatabaseConnection, rules: &ImageRules) -> Result<u64, DbErr> {
+    let deleted_before = Utc::now() - rules.trash_retention.duration;
+    let result = ImagePostEntity::delete_many()
+        .filter(PostColumn::DeletedAt.lte(deleted_before))
+        .exec(database)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+    use entities::{
+        image::database_helpers::{
+            find_deleted_post, find_post_by_id, find_post_by_str_id, get_trash, set_post_deleted,
+        },
+        user::ANONYMOUS_USER_ID,
+        ImagePostEntity,
+    };
+    use sea_orm::EntityTrait;
+
+    use super::purge_trash;
+    use crate::{images::ImageRules, test_utils};
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn deleted_posts_are_only_found_in_the_trash() {
+        let database = test_utils::database().await;
+        let post_id = test_utils::create_image_post(&database).await;
+        let post = find_post_by_id(&database, post_id).await.unwrap().unwrap();
+        assert!(find_deleted_post(&database, post_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        set_post_deleted(&database, post_id, Some(Utc::now().into()))
+            .await
+            .unwrap();
+        assert!(find_post_by_id(&database, post_id).await.unwrap().is_none());
+        assert!(find_post_by_str_id(&database, post.id_str.clone())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(find_deleted_post(&database, post_id)
+            .await
+            .unwrap()
+            .is_some());
+        let trash = get_trash(&database, ANONYMOUS_USER_ID).await.unwrap();
+        assert!(trash.iter().any(|trashed| trashed.id == post_id));
+
+        set_post_deleted(&database, post_id, None).await.unwrap();
+        assert!(find_post_by_id(&database, post_id).await.unwrap().is_some());
+        assert!(find_deleted_post(&database, post_id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn purge_only_deletes_posts_past_the_retention() {
+        let database = test_utils::database().await;
+        let rules = ImageRules::default();
+        let retention = rules.trash_retention.duration;
+        let expired = test_utils::create_image_post(&database).await;
+        let recent = test_utils::create_image_post(&database).await;
+        let kept = test_utils::create_image_post(&database).await;
+        let expired_at = Utc::now() - retention - Duration::minutes(1);
+        set_post_deleted(&database, expired, Some(expired_at.into()))
+            .await
+            .unwrap();
+        set_post_deleted(&database, recent, Some(Utc::now().into()))
+            .await
+            .unwrap();
+
+        assert!(purge_trash(&database, &rules).await.unwrap() >= 1);
+        let exists = |id| {
+            let database = &database;
+            async move {
+                ImagePostEntity::find_by_id(id)
+                    .one(database)
+                    .await
+                    .unwrap()
+                    .is_some()
+            }
+        };
+        assert!(!exists(expired).await);
+        assert!(exists(recent).await);
+        assert!(exists(kept).await);
+    }
+}
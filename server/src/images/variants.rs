@@ -0,0 +1,411 @@
+//! Resized copies of images.
+//!
+//! A variant is created the first time it is requested and stored like any other image.
+//! Later requests are served from storage. The sizes are the [ImageSizePreset]s in [ImageRules::variants]
+use std::{
+    io::{Cursor, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use actix_web::{get, web, web::Data, HttpRequest, HttpResponse};
+use entities::{
+    image::database_helpers::{find_image_by_id, find_variant},
+    ImageFileModel, ImageVariantActiveModel, ImageVariantEntity, ImageVariantModel,
+};
+use image::{io::Reader, DynamicImage, ImageFormat, ImageOutputFormat};
+use sea_orm::{prelude::*, ActiveValue::Set, EntityTrait, NotSet, SqlErr};
+
+use crate::{
+    blob::{BlobStore, StagedBlob},
+    error::WebsiteError,
+    file_response::StoredFile,
+    images::{
+        get_routes::{check_can_view, image_response},
+        ImageRules, ImageSizePreset,
+    },
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+/// The name of the variant that is the unchanged image
+pub const ORIGINAL: &str = "original";
+const JPEG_QUALITY: u8 = 85;
+
+/// A resized image encoded in the format it is served as
+struct ResizedImage {
+    content: Vec<u8>,
+    content_type: &'static str,
+    width: u32,
+    height: u32,
+}
+/// Scales the image down to fit the preset. The aspect ratio is kept
+///
+/// JPEG images stay JPEG. Everything else becomes PNG. Animated images keep their first frame
+fn resize_image(path: &Path, max_width: u32, max_height: u32) -> std::io::Result<ResizedImage> {
+    let reader = Reader::open(path)?.with_guessed_format()?;
+    let jpeg = reader.format() == Some(ImageFormat::Jpeg);
+    let image = reader
+        .decode()
+        .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+    let resized = image.thumbnail(max_width, max_height);
+    let mut content = Cursor::new(Vec::new());
+    let (result, content_type) = if jpeg {
+        let rgb = DynamicImage::ImageRgb8(resized.to_rgb8());
+        (
+            rgb.write_to(&mut content, ImageOutputFormat::Jpeg(JPEG_QUALITY)),
+            "image/jpeg",
+        )
+    } else {
+        (
+            resized.write_to(&mut content, ImageOutputFormat::Png),
+            "image/png",
+        )
+    };
+    result.map_err(|e| std::io::Error::new(ErrorKind::Other, e))?;
+    Ok(ResizedImage {
+        content: content.into_inner(),
+        content_type,
+        width: resized.width(),
+        height: resized.height(),
+    })
+}
+/// If the variant was created with the current size of the preset
+fn matches_preset(variant: &ImageVariantModel, preset: &ImageSizePreset) -> bool {
+    variant.max_width as u32 == preset.max_width && variant.max_height as u32 == preset.max_height
+}
+/// Creates the variant of the image. Images that already fit the preset share the file of the original
+async fn create_variant(
+    database: &DatabaseConnection,
+    blobs: &BlobStore,
+    image: &ImageFileModel,
+    preset: &ImageSizePreset,
+) -> crate::Result<ImageVariantModel> {
+    let variant = ImageVariantActiveModel {
+        id: NotSet,
+        image_id: Set(image.id),
+        variant: Set(preset.name.clone()),
+        file: Set(image.file.clone()),
+        content_type: Set(image.content_type.clone()),
+        width: Set(image.width),
+        height: Set(image.height),
+        max_width: Set(preset.max_width as i32),
+        max_height: Set(preset.max_height as i32),
+        created: NotSet,
+    };
+    let fits = image.width as u32 <= preset.max_width && image.height as u32 <= preset.max_height;
+    if fits {
+        return insert_variant(database, variant, image.id, &preset.name, None).await;
+    }
+    let path: PathBuf = image.file.path().to_owned();
+    let (max_width, max_height) = (preset.max_width, preset.max_height);
+    let resized = web::block(move || resize_image(&path, max_width, max_height))
+        .await
+        .map_err(|e| std::io::Error::new(ErrorKind::Other, e))??;
+    let content = blobs.write(&resized.content).await?;
+    let variant = ImageVariantActiveModel {
+        file: Set(content.location()),
+        content_type: Set(resized.content_type.to_owned()),
+        width: Set(resized.width as i32),
+        height: Set(resized.height as i32),
+        ..variant
+    };
+    insert_variant(database, variant, image.id, &preset.name, Some(content)).await
+}
+/// Inserts the variant. Then moves its staged content into the blob store
+///
+/// If another request created the variant first, that variant is returned and the content is discarded
+async fn insert_variant(
+    database: &DatabaseConnection,
+    variant: ImageVariantActiveModel,
+    image_id: i64,
+    name: &str,
+    content: Option<StagedBlob>,
+) -> crate::Result<ImageVariantModel> {
+    let variant = match ImageVariantEntity::insert(variant)
+        .exec_with_returning(database)
+        .await
+    {
+        Ok(variant) => variant,
+        Err(e) => {
+            if let Some(content) = content {
+                content.discard().await;
+            }
+            if matches!(e.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+                return find_variant(database, image_id, name)
+                    .await?
+                    .ok_or(WebsiteError::NotFound);
+            }
+            return Err(e.into());
+        }
+    };
+    if let Some(content) = content {
+        if let Err(e) = content.commit().await {
+            // Otherwise the variant would point at content that does not exist
+            ImageVariantEntity::delete_by_id(variant.id)
+                .exec(database)
+                .await?;
+            return Err(e.into());
+        }
+    }
+    Ok(variant)
+}
+
+/// An image scaled down to one of the size presets of the server. Created the first time it is requested
+#[utoipa::path(get,
+    impl_for = get_variant,
+    path = "/raw/images/{id}/{variant}",
+    params(
+        ("id", description = "The numeric id of the image"),
+        ("variant", description = "The name of a size preset. `original` for the unchanged image")
+    ),
+    responses(
+        (status = 200, description = "The image"),
+        (status = 304, description = "The image has not changed"),
+        (status = 401, description = "Images can not be viewed without logging in"),
+        (status = 403, description = "You can not view images"),
+        (status = 404, description = "The image or size preset does not exist")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/{variant}")]
+pub async fn get_variant(
+    path: web::Path<(i64, String)>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    rules: Data<ImageRules>,
+    blobs: Data<BlobStore>,
+    http_request: HttpRequest,
+) -> crate::Result<HttpResponse> {
+    let (image_id, variant) = path.into_inner();
+    let (image, post) = find_image_by_id(database.as_ref(), image_id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    check_can_view(&auth, rules.as_ref(), &post)?;
+    let (location, content_type, created, immutable) = if variant == ORIGINAL {
+        (
+            image.file.clone(),
+            image.content_type.clone(),
+            image.created,
+            true,
+        )
+    } else {
+        let preset = rules.variant(&variant).ok_or(WebsiteError::NotFound)?;
+        let variant = match find_variant(database.as_ref(), image.id, &preset.name).await? {
+            Some(variant) if matches_preset(&variant, preset) => variant,
+            Some(stale) => {
+                // The preset changed since the variant was created. The trigger releases its file
+                ImageVariantEntity::delete_by_id(stale.id)
+                    .exec(database.as_ref())
+                    .await?;
+                create_variant(database.as_ref(), blobs.as_ref(), &image, preset).await?
+            }
+            None => create_variant(database.as_ref(), blobs.as_ref(), &image, preset).await?,
+        };
+        (variant.file, variant.content_type, variant.created, false)
+    };
+    let response = image_response(&content_type, rules.as_ref(), immutable);
+    let stored = StoredFile::new(&location, created.into()).await?;
+    Ok(stored.respond(&http_request, response).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::ServiceResponse,
+        http::header::{CACHE_CONTROL, ETAG, LAST_MODIFIED},
+        test,
+        web::Data,
+        App,
+    };
+    use chrono::{DateTime, Utc};
+    use entities::{image::database_helpers::find_variant, ImageFileActiveModel, ImageFileEntity};
+    use image::{io::Reader, ImageFormat, RgbImage};
+    use sea_orm::{ActiveValue::Set, EntityTrait, NotSet};
+
+    use super::{get_variant, resize_image};
+    use crate::{
+        blob::BlobStore, config::SiteRules, images::ImageRules, test_utils, DatabaseConnection,
+    };
+
+    fn write_image(path: &std::path::Path, width: u32, height: u32, format: ImageFormat) {
+        RgbImage::new(width, height)
+            .save_with_format(path, format)
+            .unwrap();
+    }
+
+    #[test]
+    fn png_is_scaled_to_fit() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        write_image(&path, 100, 50, ImageFormat::Png);
+        let resized = resize_image(&path, 20, 20).unwrap();
+        assert_eq!((resized.width, resized.height), (20, 10));
+        assert_eq!(resized.content_type, "image/png");
+        let decoded = Reader::new(std::io::Cursor::new(resized.content))
+            .with_guessed_format()
+            .unwrap();
+        assert_eq!(decoded.format(), Some(ImageFormat::Png));
+        let decoded = decoded.decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (20, 10));
+    }
+
+    #[test]
+    fn jpeg_stays_jpeg() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("photo.jpg");
+        write_image(&path, 40, 80, ImageFormat::Jpeg);
+        let resized = resize_image(&path, 20, 20).unwrap();
+        assert_eq!((resized.width, resized.height), (10, 20));
+        assert_eq!(resized.content_type, "image/jpeg");
+        assert_eq!(
+            image::guess_format(&resized.content).unwrap(),
+            ImageFormat::Jpeg
+        );
+    }
+
+    #[test]
+    fn other_formats_become_png() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.bmp");
+        write_image(&path, 64, 64, ImageFormat::Bmp);
+        let resized = resize_image(&path, 16, 16).unwrap();
+        assert_eq!(resized.content_type, "image/png");
+        assert_eq!(
+            image::guess_format(&resized.content).unwrap(),
+            ImageFormat::Png
+        );
+    }
+
+    #[test]
+    fn broken_images_are_an_error() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("notes.png");
+        std::fs::write(&path, "These are not pixels").unwrap();
+        assert!(resize_image(&path, 16, 16).is_err());
+        assert!(resize_image(&directory.path().join("missing.png"), 16, 16).is_err());
+    }
+
+    #[test]
+    fn original_is_not_a_preset_name() {
+        let rules: ImageRules = toml::from_str(
+            r#"
+            [[variants]]
+            name = "small"
+            max_width = 64
+            max_height = 64
+            "#,
+        )
+        .unwrap();
+        assert!(rules.variant("small").is_some());
+
+        let error = toml::from_str::<ImageRules>(
+            r#"
+            [[variants]]
+            name = "original"
+            max_width = 64
+            max_height = 64
+            "#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("original"));
+    }
+
+    /// Requests the thumbnail with a preset of the size
+    async fn thumbnail(
+        database: &Data<DatabaseConnection>,
+        blobs: &Data<BlobStore>,
+        image_id: i64,
+        size: u32,
+    ) -> ServiceResponse {
+        let mut rules = ImageRules::default();
+        let preset = rules
+            .variants
+            .iter_mut()
+            .find(|preset| preset.name == "thumbnail")
+            .unwrap();
+        preset.max_width = size;
+        preset.max_height = size;
+        let app = test::init_service(
+            App::new()
+                .app_data(database.clone())
+                .app_data(blobs.clone())
+                .app_data(Data::new(rules))
+                .app_data(Data::new(SiteRules::default()))
+                .service(get_variant),
+        )
+        .await;
+        let request = test::TestRequest::get()
+            .uri(&format!("/{image_id}/thumbnail"))
+            .to_request();
+        test::call_service(&app, request).await
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn variant_changes_with_its_preset() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let blobs = BlobStore {
+            location: directory.path().to_path_buf(),
+            ..Default::default()
+        };
+        let path = directory.path().join("image.png");
+        write_image(&path, 100, 50, ImageFormat::Png);
+        let content = blobs.write(&std::fs::read(&path).unwrap()).await.unwrap();
+        let location = content.location();
+        content.commit().await.unwrap();
+        let post_id = test_utils::create_image_post(&database).await;
+        let image = ImageFileEntity::insert(ImageFileActiveModel {
+            id: NotSet,
+            post_id: Set(post_id),
+            image: Set("image.png".to_owned()),
+            file: Set(location),
+            content_type: Set("image/png".to_owned()),
+            width: Set(100),
+            height: Set(50),
+            last_updated: NotSet,
+            created: NotSet,
+        })
+        .exec_with_returning(&database)
+        .await
+        .unwrap();
+        let (database, blobs) = (Data::new(database), Data::new(blobs));
+
+        let small = thumbnail(&database, &blobs, image.id, 20).await;
+        assert!(small.status().is_success());
+        let cache_control = small.headers()[CACHE_CONTROL].to_str().unwrap();
+        assert!(cache_control.contains("no-cache"));
+        assert!(!cache_control.contains("immutable"));
+        let small_etag = small.headers()[ETAG].clone();
+        let small = test::read_body(small).await;
+
+        let large = thumbnail(&database, &blobs, image.id, 40).await;
+        assert!(large.status().is_success());
+        assert_ne!(large.headers()[ETAG], small_etag);
+        let last_modified = large.headers()[LAST_MODIFIED].to_str().unwrap().to_owned();
+        let variant = find_variant(database.as_ref(), image.id, "thumbnail")
+            .await
+            .unwrap()
+            .unwrap();
+        let created: DateTime<Utc> = variant.created.into();
+        assert_eq!(
+            last_modified,
+            created.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+        );
+        let large = test::read_body(large).await;
+
+        let decode = |content: &[u8]| {
+            let image = Reader::new(std::io::Cursor::new(content.to_vec()))
+                .with_guessed_format()
+                .unwrap()
+                .decode()
+                .unwrap();
+            (image.width(), image.height())
+        };
+        assert_eq!(decode(&small), (20, 10));
+        assert_eq!(decode(&large), (40, 20));
+    }
+}
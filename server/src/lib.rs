@@ -1,13 +1,20 @@
 #![allow(clippy::from_over_into)]
 pub mod admin;
+pub mod blob;
 pub mod config;
 pub mod error;
+pub mod file_response;
 pub mod images;
 pub mod open_api;
 pub mod paste;
+pub mod policy;
 pub mod responses;
 pub mod state;
+pub mod storage;
+#[cfg(test)]
+pub(crate) mod test_utils;
 pub mod tracing_setup;
+pub mod upload;
 pub mod user;
 pub mod utils;
 
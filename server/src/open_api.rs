@@ -1,10 +1,16 @@
-use common::{paste::file_type::FileType, visibility::Visibility};
+use common::{
+    paste::{
+        encryption::{EncryptionAlgorithm, EncryptionEnvelope},
+        file_type::FileType,
+    },
+    visibility::Visibility,
+};
 use entities::{
-    image::ImagePermissions,
+    image::{ImageFile, ImagePermissions, ImagePost},
     paste::{Paste, PastePermissions},
     user::{
         permissions::{Permissions, UserPermissions},
-        user_responses::{User, UserProfile},
+        user_responses::{StorageUsage, User, UserProfile},
     },
 };
 use utoipa::{
@@ -16,12 +22,48 @@ use utoipa::{
 };
 
 use crate::{
+    images::{
+        create_routes as image_create_routes,
+        create_routes::{NewImagePost, NewImagePostResponse, NewImages},
+        delete_routes as image_delete_routes, get_routes as image_get_routes,
+        get_routes::ImageList,
+        trash as image_trash,
+        trash::{ImageTrash, TrashedImagePost},
+        variants as image_variants,
+    },
     paste::{
+        archive as paste_archive, comment_routes as paste_comment_routes,
+        comment_routes::{LineRange, NewComment, PasteComment, PasteComments, UpdateComment},
         create_routes as paste_create_routes,
-        create_routes::{FileUploadError, NewFile, NewPaste, NewPasteResponse, NewPost},
-        get_routes as paste_get_routes, raw as paste_raw, PasteFile,
+        create_routes::{
+            FileUploadError, NewFile, NewJsonFile, NewJsonPaste, NewPaste, NewPasteResponse,
+            NewPost,
+        },
+        get_routes as paste_get_routes,
+        get_routes::{SupportedLanguage, SupportedLanguages},
+        password as paste_password,
+        password::{PasteAccessGrant, UnlockPaste},
+        raw as paste_raw,
+        raw::RawFormat,
+        render as paste_render,
+        render::RenderView,
+        revision_routes as paste_revision_routes,
+        revision_routes::{PasteFileRevision, PasteFileRevisions},
+        trash as paste_trash,
+        trash::{PasteTrash, TrashedPaste},
+        update_routes as paste_update_routes,
+        update_routes::{ReorderFiles, ReplaceFile, UpdateFile, UpdatePaste},
+        views as paste_views,
+        views::{DailyViewCount, FileViewCount, PasteStats},
+        PasteFile,
+    },
+    user::{
+        me,
+        me::MeResponse,
+        public,
+        public::CheckRequest,
+        quota::{StorageLimits, UserStorage},
     },
-    user::{me, public, public::CheckRequest},
 };
 
 pub const API_KEY: &str = "api_key";
@@ -35,16 +77,54 @@ impl ApiDoc {
             .schema_from::<Permissions>()
             .schema_from::<User>()
             .schema_from::<UserProfile>()
+            .schema_from::<MeResponse>()
+            .schema_from::<UserStorage>()
+            .schema_from::<StorageUsage>()
+            .schema_from::<StorageLimits>()
             .schema_from::<Paste>()
             .schema_from::<Visibility>()
             .schema_from::<NewFile>()
             .schema_from::<NewPaste>()
             .schema_from::<NewPost>()
+            .schema_from::<NewJsonPaste>()
+            .schema_from::<NewJsonFile>()
             .schema_from::<PasteFile>()
             .schema_from::<NewPasteResponse>()
             .schema_from::<FileType>()
+            .schema_from::<EncryptionAlgorithm>()
+            .schema_from::<EncryptionEnvelope>()
             .schema_from::<FileUploadError>()
             .schema_from::<CheckRequest>()
+            .schema_from::<UpdatePaste>()
+            .schema_from::<UpdateFile>()
+            .schema_from::<ReorderFiles>()
+            .schema_from::<ReplaceFile>()
+            .schema_from::<PasteFileRevision>()
+            .schema_from::<PasteFileRevisions>()
+            .schema_from::<RawFormat>()
+            .schema_from::<RenderView>()
+            .schema_from::<SupportedLanguage>()
+            .schema_from::<SupportedLanguages>()
+            .schema_from::<UnlockPaste>()
+            .schema_from::<PasteAccessGrant>()
+            .schema_from::<PasteStats>()
+            .schema_from::<FileViewCount>()
+            .schema_from::<DailyViewCount>()
+            .schema_from::<LineRange>()
+            .schema_from::<PasteComment>()
+            .schema_from::<PasteComments>()
+            .schema_from::<NewComment>()
+            .schema_from::<UpdateComment>()
+            .schema_from::<PasteTrash>()
+            .schema_from::<TrashedPaste>()
+            .schema_from::<ImagePost>()
+            .schema_from::<ImageFile>()
+            .schema_from::<ImageList>()
+            .schema_from::<NewImagePost>()
+            .schema_from::<NewImages>()
+            .schema_from::<NewImagePostResponse>()
+            .schema_from::<ImageTrash>()
+            .schema_from::<TrashedImagePost>()
             .security_scheme(
                 API_KEY,
                 SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
@@ -55,11 +135,41 @@ impl ApiDoc {
         PathsBuilder::new()
             .path_from::<me::me>()
             .path_from::<public::register_check>()
+            .path_from::<paste_get_routes::languages>()
             .path_from::<paste_get_routes::get>()
             .path_from::<paste_get_routes::get_file>()
             .path_from::<paste_raw::get_file>()
             .path_from::<paste_raw::head_file>()
+            .path_from::<paste_archive::get_zip>()
+            .path_from::<paste_archive::get_tar_gz>()
+            .path_from::<paste_render::render_file>()
             .path_from::<paste_create_routes::new>()
+            .path_from::<paste_create_routes::new_raw>()
+            .path_from::<paste_create_routes::new_json>()
+            .path_from::<paste_create_routes::fork>()
+            .path_from::<paste_password::unlock>()
+            .path_from::<paste_views::stats>()
+            .path_from::<paste_comment_routes::get_paste_comments>()
+            .path_from::<paste_comment_routes::new_comment>()
+            .path_from::<paste_comment_routes::update_comment>()
+            .path_from::<paste_comment_routes::delete_comment>()
+            .path_from::<paste_update_routes::update>()
+            .path_from::<paste_update_routes::update_file>()
+            .path_from::<paste_update_routes::replace_file>()
+            .path_from::<paste_update_routes::reorder_files>()
+            .path_from::<paste_revision_routes::get_revisions>()
+            .path_from::<paste_revision_routes::diff>()
+            .path_from::<paste_trash::get_paste_trash>()
+            .path_from::<paste_trash::restore>()
+            .path_from::<image_create_routes::new>()
+            .path_from::<image_get_routes::get>()
+            .path_from::<image_get_routes::get_post_images>()
+            .path_from::<image_get_routes::get_image>()
+            .path_from::<image_delete_routes::delete>()
+            .path_from::<image_delete_routes::delete_image>()
+            .path_from::<image_trash::get_image_trash>()
+            .path_from::<image_trash::restore>()
+            .path_from::<image_variants::get_variant>()
             .build()
     }
 }
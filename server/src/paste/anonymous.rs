@@ -0,0 +1,90 @@
+//! Limits on pastes created without logging in.
+//!
+//! Anonymous pastes share [crate::config::SiteRules::anonymous_quota]. So each address can only create
+//! [AnonymousPasteLimit::max_pastes] within [AnonymousPasteLimit::window]. One client can not fill the quota for everyone
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+
+use crate::{config::AnonymousPasteLimit, error::WebsiteError};
+
+struct RecentPastes {
+    count: u32,
+    since: DateTime<Utc>,
+}
+/// The anonymous pastes created by each address. Kept in memory. So they are lost on restart
+///
+/// Connections without an address share one count
+#[derive(Default)]
+pub struct AnonymousPastes {
+    created: Mutex<ahash::HashMap<Option<IpAddr>, RecentPastes>>,
+}
+impl AnonymousPastes {
+    /// Counts a paste created by the address. Fails if the address has reached the limit
+    ///
+    /// Counted before the paste is created. So parallel requests can not exceed the limit
+    pub fn start_paste(
+        &self,
+        address: Option<IpAddr>,
+        limit: &AnonymousPasteLimit,
+        now: DateTime<Utc>,
+    ) -> crate::Result<()> {
+        let window = limit.window.duration;
+        let mut created = self.created.lock();
+        created.retain(|_, recent| recent.since + window > now);
+        let recent = created.entry(address).or_insert(RecentPastes {
+            count: 0,
+            since: now,
+        });
+        if recent.count >= limit.max_pastes {
+            return Err(WebsiteError::TooManyPastes);
+        }
+        recent.count += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use chrono::{Duration, Utc};
+
+    use super::AnonymousPastes;
+    use crate::{config::AnonymousPasteLimit, error::WebsiteError};
+
+    fn address(last: u8) -> Option<IpAddr> {
+        Some(Ipv4Addr::new(10, 0, 0, last).into())
+    }
+
+    #[test]
+    fn one_address_can_not_use_up_the_share_of_another() {
+        let limit = AnonymousPasteLimit::default();
+        let pastes = AnonymousPastes::default();
+        let now = Utc::now();
+        for _ in 0..limit.max_pastes {
+            pastes.start_paste(address(1), &limit, now).unwrap();
+        }
+        assert!(matches!(
+            pastes.start_paste(address(1), &limit, now),
+            Err(WebsiteError::TooManyPastes)
+        ));
+        pastes.start_paste(address(2), &limit, now).unwrap();
+        pastes.start_paste(None, &limit, now).unwrap();
+    }
+
+    #[test]
+    fn the_count_resets_after_the_window() {
+        let limit = AnonymousPasteLimit::default();
+        let pastes = AnonymousPastes::default();
+        let now = Utc::now();
+        for _ in 0..limit.max_pastes {
+            pastes.start_paste(address(1), &limit, now).unwrap();
+        }
+        let later = now + limit.window.duration - Duration::seconds(1);
+        assert!(pastes.start_paste(address(1), &limit, later).is_err());
+        let after = now + limit.window.duration;
+        pastes.start_paste(address(1), &limit, after).unwrap();
+    }
+}
@@ -0,0 +1,393 @@
+use std::path::PathBuf;
+
+use actix_web::{
+    get,
+    http::header::{ContentDisposition, DispositionParam, DispositionType},
+    web,
+    web::Data,
+    HttpResponse,
+};
+use async_compression::tokio::write::GzipEncoder;
+use async_zip::{tokio::write::ZipFileWriter, Compression, ZipEntryBuilder};
+use common::paste::{encryption::EncryptionEnvelope, file_type::FileType};
+use entities::{
+    paste::database_helpers::{find_post_by_str_id, get_files},
+    PasteFileModel, PastePostModel,
+};
+use serde::Serialize;
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncWriteExt, DuplexStream},
+};
+use tokio_util::{compat::FuturesAsyncWriteCompatExt, io::ReaderStream};
+use tracing::{error, warn};
+
+use crate::{
+    paste::{
+        delete_post_files,
+        expiration::{burn, check_post},
+        password::PasteAccess,
+        PasteRules,
+    },
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+/// The size of the buffer between the archive writer and the response
+const ARCHIVE_BUFFER_SIZE: usize = 64 * 1024;
+/// Placed at the root of the archive. Next to the directory containing the files
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Describes the paste and its files. Stored as `manifest.json` in the archive
+#[derive(Debug, Serialize)]
+pub struct ArchiveManifest {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub tags: Vec<String>,
+    /// In the order the files appear in the archive
+    pub files: Vec<ArchiveFile>,
+}
+#[derive(Debug, Serialize)]
+pub struct ArchiveFile {
+    /// The path of the file within the archive
+    pub path: String,
+    pub file_name: String,
+    pub file_type: FileType,
+    pub size: usize,
+    /// Needed with the key to decrypt the file. Not included if the paste is not encrypted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionEnvelope>,
+}
+#[derive(Debug, Error)]
+enum ArchiveError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Zip(#[from] async_zip::error::ZipError),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+#[derive(Debug, Clone, Copy)]
+enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+impl ArchiveFormat {
+    fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+/// The path of a file within the archive. Inside the directory of the paste
+///
+/// File names can not escape the directory. Separators are replaced.
+/// Names from before file names were validated that are empty, `.` or `..` become `file_{index}`
+fn entry_path(id_str: &str, file_name: &str, index: usize) -> String {
+    let name = file_name.replace(['/', '\\'], "_");
+    let name = match name.trim() {
+        "" | "." | ".." => format!("file_{index}"),
+        _ => name,
+    };
+    format!("{id_str}/{name}")
+}
+struct Archive {
+    manifest: ArchiveManifest,
+    /// Where the content of the files is stored. In the same order as the manifest.
+    ///
+    /// Each file is only opened while it is written. So a paste with many files does not hold a descriptor for each
+    contents: Vec<PathBuf>,
+    /// Unix timestamp used as the modification time of the entries
+    modified: u64,
+}
+impl Archive {
+    async fn new(post: &PastePostModel, files: Vec<PasteFileModel>) -> std::io::Result<Self> {
+        let mut manifest_files = Vec::with_capacity(files.len());
+        let mut contents = Vec::with_capacity(files.len());
+        for (index, file) in files.into_iter().enumerate() {
+            let path = entry_path(&post.id_str, &file.file_name, index);
+            // Missing content fails the request here. Instead of cutting the archive short
+            let content = file.location.path().to_path_buf();
+            tokio::fs::metadata(&content).await?;
+            manifest_files.push(ArchiveFile {
+                path,
+                file_name: file.file_name,
+                file_type: file.file_type,
+                size: file.location.file_size(),
+                encryption: file.encryption,
+            });
+            contents.push(content);
+        }
+        Ok(Self {
+            manifest: ArchiveManifest {
+                id: post.id_str.clone(),
+                name: post.name.clone(),
+                description: post.description.clone(),
+                tags: post.tags.clone(),
+                files: manifest_files,
+            },
+            contents,
+            modified: post.last_updated.timestamp().max(0) as u64,
+        })
+    }
+    async fn write(self, format: ArchiveFormat, writer: DuplexStream) -> Result<(), ArchiveError> {
+        match format {
+            ArchiveFormat::Zip => self.write_zip(writer).await,
+            ArchiveFormat::TarGz => self.write_tar_gz(writer).await,
+        }
+    }
+    async fn write_zip(self, writer: DuplexStream) -> Result<(), ArchiveError> {
+        let mut zip = ZipFileWriter::with_tokio(writer);
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        zip.write_entry_whole(
+            ZipEntryBuilder::new(MANIFEST_NAME.into(), Compression::Deflate),
+            &manifest,
+        )
+        .await?;
+        for (file, content) in self.manifest.files.iter().zip(self.contents) {
+            let mut content = File::open(content).await?;
+            let entry = ZipEntryBuilder::new(file.path.clone().into(), Compression::Deflate);
+            let mut entry_writer = zip.write_entry_stream(entry).await?.compat_write();
+            tokio::io::copy(&mut content, &mut entry_writer).await?;
+            entry_writer.into_inner().close().await?;
+        }
+        zip.close().await?;
+        Ok(())
+    }
+    async fn write_tar_gz(mut self, writer: DuplexStream) -> Result<(), ArchiveError> {
+        let mut tar = tokio_tar::Builder::new(GzipEncoder::new(writer));
+        let manifest = serde_json::to_vec_pretty(&self.manifest)?;
+        let mut header = self.tar_header(manifest.len() as u64);
+        tar.append_data(&mut header, MANIFEST_NAME, manifest.as_slice())
+            .await?;
+        let contents = std::mem::take(&mut self.contents);
+        for (file, content) in self.manifest.files.iter().zip(contents) {
+            let content = File::open(content).await?;
+            let mut header = self.tar_header(content.metadata().await?.len());
+            tar.append_data(&mut header, &file.path, content).await?;
+        }
+        let mut encoder = tar.into_inner().await?;
+        encoder.shutdown().await?;
+        Ok(())
+    }
+    fn tar_header(&self, size: u64) -> tokio_tar::Header {
+        let mut header = tokio_tar::Header::new_gnu();
+        header.set_size(size);
+        header.set_mode(0o644);
+        header.set_mtime(self.modified);
+        header
+    }
+}
+
+/// Streams every file of a paste as an archive.
+///
+/// The archive is written by a background task while the response is being sent.
+/// So the archive is never held in memory
+async fn archive_response(
+    id: String,
+    format: ArchiveFormat,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    rules: Data<PasteRules>,
+) -> crate::Result<HttpResponse> {
+    let post = find_post_by_str_id(database.as_ref(), id)
+        .await?
+        .ok_or(crate::Error::NotFound)?;
+    access.check(post.id, &post, &auth).await?;
+    let files = get_files(database.as_ref(), post.id).await?;
+    let burned = check_post(&post, &auth)?;
+
+    let file_name = format!("{}.{}", post.id_str, format.extension());
+    // The locations are read before the paste is burned. Its files are deleted once the archive is written
+    let archive = Archive::new(&post, files).await?;
+    if burned {
+        burn(database.as_ref(), post.id).await?;
+    }
+    let post_id = post.id;
+    let (writer, reader) = tokio::io::duplex(ARCHIVE_BUFFER_SIZE);
+    actix_web::rt::spawn(async move {
+        if let Err(e) = archive.write(format, writer).await {
+            error!("Failed to write archive for paste {post_id}: {e}");
+        }
+        if burned {
+            if let Err(e) = delete_post_files(rules.as_ref(), post_id).await {
+                warn!("Failed to delete files for burned paste {post_id}: {e}");
+            }
+        }
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(file_name)],
+        })
+        .streaming(ReaderStream::new(reader)))
+}
+
+#[utoipa::path(get,
+    impl_for = get_zip,
+    path = "/raw/paste/{id}.zip",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    responses(
+        (status = 200, content_type = "application/zip", description = "Every file of the paste and a manifest.json describing them"),
+        (status = 404, description = "Paste Not Found or the paste has expired")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}.zip")]
+pub async fn get_zip(
+    id: web::Path<String>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    rules: Data<PasteRules>,
+) -> crate::Result<HttpResponse> {
+    archive_response(
+        id.into_inner(),
+        ArchiveFormat::Zip,
+        database,
+        auth,
+        access,
+        rules,
+    )
+    .await
+}
+
+#[utoipa::path(get,
+    impl_for = get_tar_gz,
+    path = "/raw/paste/{id}.tar.gz",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    responses(
+        (status = 200, content_type = "application/gzip", description = "Every file of the paste and a manifest.json describing them"),
+        (status = 404, description = "Paste Not Found or the paste has expired")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}.tar.gz")]
+pub async fn get_tar_gz(
+    id: web::Path<String>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    rules: Data<PasteRules>,
+) -> crate::Result<HttpResponse> {
+    archive_response(
+        id.into_inner(),
+        ArchiveFormat::TarGz,
+        database,
+        auth,
+        access,
+        rules,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use async_compression::tokio::bufread::GzipDecoder;
+    use common::paste::file_type::FileType;
+    use futures_util::TryStreamExt;
+    use tokio::io::AsyncReadExt;
+
+    use super::{
+        entry_path, Archive, ArchiveFile, ArchiveFormat, ArchiveManifest, ARCHIVE_BUFFER_SIZE,
+        MANIFEST_NAME,
+    };
+
+    #[test]
+    fn names_stay_in_the_paste_directory() {
+        assert_eq!(entry_path("paste", "main.rs", 0), "paste/main.rs");
+        assert_eq!(
+            entry_path("paste", "../etc/passwd", 1),
+            "paste/.._etc_passwd"
+        );
+        assert_eq!(entry_path("paste", "a\\b.txt", 2), "paste/a_b.txt");
+        assert_eq!(entry_path("paste", "..", 3), "paste/file_3");
+        assert_eq!(entry_path("paste", ".", 4), "paste/file_4");
+        assert_eq!(entry_path("paste", "", 5), "paste/file_5");
+        assert_eq!(entry_path("paste", "  ", 6), "paste/file_6");
+    }
+
+    #[actix_web::test]
+    async fn tar_contains_every_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let names = ["..", ".", "", "notes.txt"];
+        let mut files = Vec::new();
+        let mut contents = Vec::new();
+        for (index, name) in names.iter().enumerate() {
+            let content = directory.path().join(index.to_string());
+            std::fs::write(&content, format!("file {index}")).unwrap();
+            files.push(ArchiveFile {
+                path: entry_path("paste", name, index),
+                file_name: name.to_string(),
+                file_type: FileType::default(),
+                size: 6,
+                encryption: None,
+            });
+            contents.push(content);
+        }
+        let archive = Archive {
+            manifest: ArchiveManifest {
+                id: "paste".to_owned(),
+                name: "Paste".to_owned(),
+                description: String::new(),
+                tags: vec![],
+                files,
+            },
+            contents,
+            modified: 0,
+        };
+        let (writer, mut reader) = tokio::io::duplex(ARCHIVE_BUFFER_SIZE);
+        let mut compressed = Vec::new();
+        let (written, read) = futures_util::join!(
+            archive.write(ArchiveFormat::TarGz, writer),
+            reader.read_to_end(&mut compressed)
+        );
+        written.unwrap();
+        read.unwrap();
+
+        let mut tar = tokio_tar::Archive::new(GzipDecoder::new(compressed.as_slice()));
+        let mut entries = tar.entries().unwrap();
+        let mut paths = Vec::new();
+        while let Some(mut entry) = entries.try_next().await.unwrap() {
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).await.unwrap();
+            if path != MANIFEST_NAME {
+                assert_eq!(content, format!("file {}", paths.len() - 1));
+            }
+            paths.push(path);
+        }
+        assert_eq!(
+            paths,
+            [
+                MANIFEST_NAME,
+                "paste/file_0",
+                "paste/file_1",
+                "paste/file_2",
+                "paste/notes.txt"
+            ]
+        );
+    }
+}
@@ -0,0 +1,334 @@
+use actix_web::{delete, get, patch, post, web, web::Data, HttpResponse};
+use chrono::Utc;
+use digestible::Digestible;
+use entities::{
+    paste::{
+        database_helpers::{
+            find_comment, find_file_by_name, find_post_by_str_id, find_revision, get_comments,
+        },
+        HasExpiration,
+    },
+    PasteCommentActiveModel, PasteCommentEntity, PasteCommentModel, PastePostModel,
+};
+use helper_macros::Response;
+use sea_orm::{prelude::*, ActiveValue::Set};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::WebsiteError,
+    paste::{count_lines, password::PasteAccess},
+    policy::{can_moderate, Actor, Resource},
+    responses::JsonResponse,
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+/// The longest comment. In bytes
+const MAX_COMMENT_LENGTH: usize = 10_000;
+
+/// A range of lines of a file. Both ends are inclusive and the first line is 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema, Digestible)]
+pub struct LineRange {
+    pub start: i32,
+    pub end: i32,
+}
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+pub struct PasteComment {
+    pub id: i64,
+    pub user_id: i64,
+    /// The file the comment is on. None if the comment is on the paste
+    #[schema(nullable)]
+    pub file_name: Option<String>,
+    /// The revision of the file the lines refer to
+    #[schema(nullable)]
+    pub revision: Option<i32>,
+    #[schema(nullable)]
+    pub lines: Option<LineRange>,
+    pub content: String,
+    /// None if the comment was never edited
+    #[schema(value_type = DateTime, nullable)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time_optional")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub edited: Option<DateTimeWithTimeZone>,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub created: DateTimeWithTimeZone,
+}
+impl PasteComment {
+    fn new(model: PasteCommentModel, file_name: Option<String>) -> Self {
+        let lines = match (model.line_start, model.line_end) {
+            (Some(start), Some(end)) => Some(LineRange { start, end }),
+            _ => None,
+        };
+        Self {
+            id: model.id,
+            user_id: model.user_id,
+            file_name,
+            revision: model.revision,
+            lines,
+            content: model.content,
+            edited: model.edited,
+            created: model.created,
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+pub struct PasteComments {
+    /// Oldest comment first
+    pub comments: Vec<PasteComment>,
+}
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewComment {
+    pub content: String,
+    /// Comment on this file instead of the paste
+    #[serde(default)]
+    pub file_name: Option<String>,
+    /// Annotate these lines of the latest revision of the file. Requires `file_name`
+    ///
+    /// The lines must exist in the file. Lines of encrypted files are not checked
+    #[serde(default)]
+    pub lines: Option<LineRange>,
+}
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateComment {
+    pub content: String,
+}
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CommentsQuery {
+    /// Only return the comments on this file
+    pub file_name: Option<String>,
+}
+
+fn check_content(content: &str) -> crate::Result<()> {
+    if content.trim().is_empty() {
+        return Err(WebsiteError::MissingField("content"));
+    }
+    if content.len() > MAX_COMMENT_LENGTH {
+        return Err(WebsiteError::ExceedsMaxLength);
+    }
+    Ok(())
+}
+/// Finds the post and checks that the request can read it. Comments share the visibility of the post
+async fn find_readable_post(
+    database: &DatabaseConnection,
+    id: String,
+    auth: &OptionalAuthentication,
+    access: &PasteAccess,
+) -> crate::Result<PastePostModel> {
+    let post = find_post_by_str_id(database, id)
+        .await?
+        .filter(|post| !post.is_expired())
+        .ok_or(WebsiteError::NotFound)?;
+    access.check(post.id, &post, auth).await?;
+    Ok(post)
+}
+
+#[utoipa::path(get,
+    impl_for = get_paste_comments,
+    path = "/api/paste/{id}/comments",
+    params(
+        ("id", description = "The id of the paste"),
+        CommentsQuery
+    ),
+    responses(
+        (status = 200, description = "The comments of the paste", body = PasteComments),
+        (status = 401, description = "You can not view the paste"),
+        (status = 404, description = "Paste Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/comments")]
+pub async fn get_paste_comments(
+    id: web::Path<String>,
+    query: web::Query<CommentsQuery>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<JsonResponse<PasteComments>> {
+    let post = find_readable_post(database.as_ref(), id.into_inner(), &auth, &access).await?;
+    let comments = get_comments(database.as_ref(), post.id, query.file_name.as_deref())
+        .await?
+        .into_iter()
+        .map(|(comment, file)| PasteComment::new(comment, file.map(|file| file.file_name)))
+        .collect();
+    Ok(JsonResponse::from(PasteComments { comments }))
+}
+
+#[utoipa::path(post,
+    impl_for = new_comment,
+    path = "/api/paste/{id}/comments",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    request_body (content = NewComment, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The comment", body = PasteComment),
+        (status = 400, description = "The comment is empty, too long or the lines are invalid"),
+        (status = 401, description = "You are not logged in or can not view the paste"),
+        (status = 404, description = "Paste or File Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[post("/{id}/comments")]
+pub async fn new_comment(
+    id: web::Path<String>,
+    comment: web::Json<NewComment>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<JsonResponse<PasteComment>> {
+    let Some(user) = auth.as_ref() else {
+        return Err(WebsiteError::Unauthorized);
+    };
+    let post = find_readable_post(database.as_ref(), id.into_inner(), &auth, &access).await?;
+    let NewComment {
+        content,
+        file_name,
+        lines,
+    } = comment.into_inner();
+    check_content(&content)?;
+    if let Some(lines) = &lines {
+        if lines.start < 1 || lines.end < lines.start {
+            return Err(WebsiteError::InvalidLineRange);
+        }
+    }
+
+    let (file, revision) = match file_name {
+        Some(file_name) => {
+            let file = find_file_by_name(database.as_ref(), post.id, &file_name)
+                .await?
+                .ok_or(WebsiteError::NotFound)?;
+            let revision = find_revision(database.as_ref(), file.id, None).await?;
+            if let Some(lines) = &lines {
+                // The server can not read the lines of encrypted files
+                if file.encryption.is_none() {
+                    let location = revision
+                        .as_ref()
+                        .map_or(&file.location, |revision| &revision.location);
+                    let line_count = count_lines(location.path()).await?;
+                    if lines.end as usize > line_count {
+                        return Err(WebsiteError::InvalidLineRange);
+                    }
+                }
+            }
+            (Some(file), revision.map(|revision| revision.revision))
+        }
+        None if lines.is_some() => return Err(WebsiteError::MissingField("file_name")),
+        None => (None, None),
+    };
+    let comment = PasteCommentActiveModel {
+        post_id: Set(post.id),
+        file_id: Set(file.as_ref().map(|file| file.id)),
+        revision: Set(revision),
+        line_start: Set(lines.map(|lines| lines.start)),
+        line_end: Set(lines.map(|lines| lines.end)),
+        user_id: Set(user.id),
+        content: Set(content),
+        edited: Set(None),
+        ..Default::default()
+    };
+    let comment = PasteCommentEntity::insert(comment)
+        .exec_with_returning(database.as_ref())
+        .await?;
+    Ok(JsonResponse::from(PasteComment::new(
+        comment,
+        file.map(|file| file.file_name),
+    )))
+}
+
+#[utoipa::path(patch,
+    impl_for = update_comment,
+    path = "/api/paste/{id}/comments/{comment_id}",
+    params(
+        ("id", description = "The id of the paste"),
+        ("comment_id", description = "The id of the comment")
+    ),
+    request_body (content = UpdateComment, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The edited comment", body = PasteComment),
+        (status = 400, description = "The comment is empty or too long"),
+        (status = 403, description = "Only the author can edit a comment"),
+        (status = 404, description = "Paste or Comment Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[patch("/{id}/comments/{comment_id}")]
+pub async fn update_comment(
+    path: web::Path<(String, i64)>,
+    update: web::Json<UpdateComment>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<JsonResponse<PasteComment>> {
+    let Some(user) = auth.as_ref() else {
+        return Err(WebsiteError::Unauthorized);
+    };
+    let (id, comment_id) = path.into_inner();
+    let post = find_readable_post(database.as_ref(), id, &auth, &access).await?;
+    let (comment, file) = find_comment(database.as_ref(), post.id, comment_id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if comment.user_id != user.id {
+        return Err(WebsiteError::Forbidden);
+    }
+    let content = update.into_inner().content;
+    check_content(&content)?;
+
+    let mut comment: PasteCommentActiveModel = comment.into();
+    comment.content = Set(content);
+    comment.edited = Set(Some(Utc::now().into()));
+    let comment = comment.update(database.as_ref()).await?;
+    Ok(JsonResponse::from(PasteComment::new(
+        comment,
+        file.map(|file| file.file_name),
+    )))
+}
+
+#[utoipa::path(delete,
+    impl_for = delete_comment,
+    path = "/api/paste/{id}/comments/{comment_id}",
+    params(
+        ("id", description = "The id of the paste"),
+        ("comment_id", description = "The id of the comment")
+    ),
+    responses(
+        (status = 204, description = "The comment was deleted"),
+        (status = 403, description = "Only the author, the owner of the paste and paste admins can delete a comment"),
+        (status = 404, description = "Paste or Comment Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[delete("/{id}/comments/{comment_id}")]
+pub async fn delete_comment(
+    path: web::Path<(String, i64)>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<HttpResponse> {
+    let Some(user) = auth.as_ref() else {
+        return Err(WebsiteError::Unauthorized);
+    };
+    let (id, comment_id) = path.into_inner();
+    let post = find_readable_post(database.as_ref(), id, &auth, &access).await?;
+    let (comment, _) = find_comment(database.as_ref(), post.id, comment_id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if comment.user_id != user.id && !can_moderate(Resource::Paste, &Actor::from(user), &post) {
+        return Err(WebsiteError::Forbidden);
+    }
+    PasteCommentEntity::delete_by_id(comment.id)
+        .exec(database.as_ref())
+        .await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -1,28 +1,87 @@
-use std::{collections::HashMap, io::Read};
+use std::collections::{HashMap, HashSet};
 
-use actix_multipart::form::{json::Json as JsonForm, tempfile::TempFile, MultipartForm};
-use actix_web::{http::header::LOCATION, post, web, web::Data, HttpResponse};
-use common::{file_location::FileLocation, paste::file_type::FileType, visibility::Visibility};
+use actix_multipart::{Field, Multipart};
+use actix_web::{
+    http::{
+        header::{ContentType, ACCEPT, CONTENT_TYPE, LOCATION, USER_AGENT},
+        StatusCode,
+    },
+    post, web,
+    web::Data,
+    HttpRequest, HttpResponse,
+};
+use chrono::Utc;
+use common::{
+    file_location::FileLocation,
+    paste::{
+        encryption::EncryptionEnvelope,
+        file_name::normalize_file_name,
+        file_type::{FileType, PROGRAMMING_LANGUAGE},
+        language::Language,
+    },
+    visibility::Visibility,
+};
 use entities::{
     paste,
-    paste::{database_helpers::find_post_by_id, generate_post_paste_id},
-    PasteFileActiveModel, PasteFileEntity, PastePostActiveModel, PastePostEntity, PastePostModel,
+    paste::{
+        database_helpers::{
+            create_revision, find_file_by_name, find_post_by_id, find_post_by_str_id, get_files,
+            get_paste_size,
+        },
+        Paste,
+    },
+    user::{
+        database_helpers::get_storage_usage, permissions::Permissions, user_responses::User,
+        ANONYMOUS_USER_ID,
+    },
+    PasteFileActiveModel, PasteFileEntity, PasteFileModel, PastePostActiveModel, PastePostEntity,
+    PastePostModel,
 };
-use sea_orm::{prelude::*, ActiveValue::Set, EntityTrait, NotSet, QueryFilter};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sea_orm::{prelude::*, ActiveValue::Set, EntityTrait, NotSet, QueryFilter, TransactionTrait};
 use serde::{Deserialize, Serialize};
-use tokio::fs::OpenOptions;
-use tracing::debug;
+use tracing::{debug, warn};
 use utoipa::{
     openapi::{
         AllOfBuilder, ArrayBuilder, KnownFormat, ObjectBuilder, Ref, RefOr, Schema, SchemaFormat,
         SchemaType,
     },
-    ToSchema,
+    IntoParams, ToSchema,
 };
 
-use crate::{error::WebsiteError, paste::PasteRules, user::Authentication, DatabaseConnection};
+use crate::{
+    blob::{BlobStore, StagedBlob},
+    config::{client_address, SiteRules, StorageQuota},
+    error::WebsiteError,
+    paste::{
+        anonymous::AnonymousPastes,
+        check_encryption, check_paste_name, delete_post_files,
+        delete_routes::DELETE_TOKEN_HEADER,
+        expiration::check_post,
+        password::{hash_visibility, PasteAccess},
+        paste_id::{is_id_taken, validate_slug, PasteIdRules},
+        read_content_sample, PasteLifetime, PasteRules,
+    },
+    policy::{can_create, can_edit, Actor, Resource},
+    responses::JsonResponse,
+    upload::{aborts_request, read_body, save_stream, MultipartUpload},
+    user::{
+        quota::{check_paste_count, UploadBudget},
+        Authentication, OptionalAuthentication,
+    },
+    utils::{sha256, token},
+    DatabaseConnection,
+};
 #[derive(Deserialize, Serialize, Default, Debug, ToSchema)]
-pub struct NewFile(pub Option<FileType>);
+#[serde(default)]
+pub struct NewFile {
+    /// Detected from the name and content if not provided
+    #[schema(nullable)]
+    pub file_type: Option<FileType>,
+    /// Required for every file in an encrypted paste
+    #[schema(nullable)]
+    pub encryption: Option<EncryptionEnvelope>,
+}
 #[derive(Deserialize, Serialize, Debug, ToSchema)]
 #[serde(default)]
 pub struct NewPaste {
@@ -36,6 +95,20 @@ pub struct NewPaste {
     pub visibility: Visibility,
     #[schema(nullable)]
     pub file_details: ahash::HashMap<String, NewFile>,
+    /// When the paste should be deleted. Limited by the lifetime rules of the server
+    #[schema(value_type = DateTime, nullable)]
+    pub expires_at: Option<DateTimeWithTimeZone>,
+    /// Delete the paste after the first time a file is viewed by someone other than the owner
+    pub burn_after_read: bool,
+    /// The files are encrypted by the client. Each file must have an [EncryptionEnvelope] in `file_details`
+    ///
+    /// Only supported by multipart uploads
+    pub encrypted: bool,
+    /// Use this as the id of the paste instead of a generated one. Such as `deploy-runbook`
+    ///
+    /// Requires the custom slug permission
+    #[schema(nullable)]
+    pub slug: Option<String>,
 }
 impl Default for NewPaste {
     fn default() -> Self {
@@ -45,14 +118,17 @@ impl Default for NewPaste {
             tags: vec![],
             visibility: Default::default(),
             file_details: HashMap::default(),
+            expires_at: None,
+            burn_after_read: false,
+            encrypted: false,
+            slug: None,
         }
     }
 }
-#[derive(Debug, MultipartForm)]
-pub struct NewPost {
-    pub details: Option<JsonForm<NewPaste>>,
-    pub files: Vec<TempFile>,
-}
+/// The multipart request used to create a paste. Only used for the OpenAPI schema
+///
+/// Fields are read in the order they are sent. So `details` must be sent before `files`
+pub struct NewPost;
 impl<'a> ToSchema<'a> for NewPost {
     fn schema() -> (&'a str, RefOr<Schema>) {
         let schema = ObjectBuilder::new()
@@ -78,88 +154,348 @@ impl<'a> ToSchema<'a> for NewPost {
 
 /// Handles a file upload to a specific post
 ///
+/// The file is streamed directly to the blob store
+///
 /// # Parameters
 /// - `post_id` - The id of the post to upload to
 /// - `database` - The database connection
 /// - `file_details` - A function that takes a file name and returns the file details
-/// - `upload` - The file to upload
-/// - `file_index` - The index of the file in the multipart form
-/// - `rules` - The rules for the server
+/// - `upload` - The multipart request
+/// - `field` - The field containing the file
+/// - `file_index` - The index of the file in the post
+/// - `blobs` - Where the content is stored
+/// - `author` - The user uploading the file. Recorded as the author of the first revision
+/// - `budget` - The remaining space in the paste and the user's quota
+/// - `encrypted` - The post is encrypted. The content is not read or checked for UTF-8
 /// # Returns
 /// - `Ok(())` - If the file was uploaded successfully
 /// - `Err((String, WebsiteError))` - If there was an error uploading the file. String is the file name. WebsiteError is the error
+#[allow(clippy::too_many_arguments)]
 async fn handle_file_upload<D>(
     post_id: i64,
-    database: &impl ConnectionTrait,
+    database: &DatabaseConnection,
     file_details: D,
-    mut upload: TempFile,
+    upload: &mut MultipartUpload,
+    mut field: Field,
     file_index: usize,
-    rules: &PasteRules,
+    blobs: &BlobStore,
+    author: i64,
+    budget: &mut UploadBudget,
+    encrypted: bool,
 ) -> Result<(), FileUploadError>
 where
     D: FnOnce(&str) -> NewFile,
 {
-    let (file_name, details) = if let Some(file_name) = upload.file_name {
-        let details = file_details(&file_name);
-        (file_name, details)
-    } else {
+    let file_name = match field.content_disposition().get_filename() {
+        Some(file_name) => match normalize_file_name(file_name) {
+            Ok(file_name) => file_name,
+            Err(e) => return Err((file_name.to_owned(), e.into()).into()),
+        },
         // No File name. Will be saved as file_{index}. File details will be default
-        let name = format!("file_{}", file_index);
-        let details = file_details(&name);
-        (name, details)
+        None => format!("file_{}", file_index),
+    };
+    match find_file_by_name(database, post_id, &file_name).await {
+        Ok(None) => {}
+        Ok(Some(_)) => return Err((file_name, WebsiteError::FileAlreadyExists).into()),
+        Err(e) => return Err((file_name, e.into()).into()),
+    }
+    let details = file_details(&file_name);
+    let encryption = match check_encryption(encrypted, details.encryption) {
+        Ok(encryption) => encryption,
+        Err(e) => return Err((file_name, e).into()),
     };
     debug!("Uploading file: {file_name:?}");
-    let mime_type = if let Some(file_type) = upload.content_type {
-        file_type.to_string()
-    } else {
-        "text/plain".to_string()
+    // The details sent by the client take priority over the content type of the field
+    let mut file_type = details.file_type.unwrap_or_else(|| {
+        let mut file_type = FileType::default();
+        if let Some(content_type) = field.content_type() {
+            file_type.mime_type = content_type.essence_str().to_owned();
+        }
+        file_type
+    });
+    file_type.process_file(&file_name);
+    let content = match upload
+        .save_file(&mut field, blobs, encrypted, |size| {
+            budget.check(size as u64)
+        })
+        .await
+    {
+        Ok(ok) => ok,
+        Err(e) => return Err((file_name, e).into()),
     };
-    let mut file_type = details.0.unwrap_or_default();
-    file_type.mime_type = mime_type;
-    if rules.max_file_size.get_as_bytes() > upload.size {
-        return Err((file_name, WebsiteError::ExceedsMaxLength).into());
-    }
-
-    let mut as_text = Vec::with_capacity(upload.size);
-    if let Err(e) = upload.file.read_to_end(&mut as_text) {
-        return Err((file_name, WebsiteError::IoError(e)).into());
-    }
-    if let Err(e) = simdutf8::basic::from_utf8(&as_text) {
-        return Err((file_name, WebsiteError::UTF8ERROR(e)).into());
-    }
-    let file_location = rules.location.join(post_id.to_string());
-    if let Err(e) = tokio::fs::create_dir_all(&file_location).await {
-        return Err((file_name, WebsiteError::IoError(e)).into());
+    if let Err(e) = budget.reserve(content.size() as u64) {
+        content.discard().await;
+        return Err((file_name, e).into());
     }
-    let file_location = file_location.join(file_name.clone());
-    let mut file = OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(&file_location)
-        .await
-        .map_err(|e| (file_name.clone(), WebsiteError::IoError(e)))?;
-    if let Err(e) = tokio::io::copy(&mut &as_text[..], &mut file).await {
-        return Err((file_name, WebsiteError::IoError(e)).into());
+    // Ciphertext says nothing about the language
+    if file_type.programming_language.is_none() && !encrypted {
+        match read_content_sample(content.temp_path(), content.size()).await {
+            Ok(sample) => file_type.process_content(&sample),
+            Err(e) => warn!("Failed to read {file_name:?} to detect its language: {e}"),
+        }
     }
-    let location = FileLocation::Local {
-        location: file_location,
-        size: upload.size,
-    };
 
+    if let Err(e) = insert_staged_file(
+        database,
+        post_id,
+        file_name.clone(),
+        file_type,
+        content,
+        encryption,
+        file_index as i32,
+        author,
+        budget,
+    )
+    .await
+    {
+        return Err((file_name, e).into());
+    }
+    Ok(())
+}
+/// Inserts a stored file and its first revision
+///
+/// # Returns
+/// The id of the file
+async fn insert_file(
+    database: &impl ConnectionTrait,
+    post_id: i64,
+    file_name: String,
+    file_type: FileType,
+    location: FileLocation,
+    encryption: Option<EncryptionEnvelope>,
+    position: i32,
+    author: i64,
+) -> Result<i64, DbErr> {
     let file = PasteFileActiveModel {
         id: NotSet,
         post_id: Set(post_id),
-        file_name: Set(file_name.clone()),
+        file_name: Set(file_name),
         file_type: Set(file_type),
-        location: Set(location),
+        location: Set(location.clone()),
+        encryption: Set(encryption.clone()),
+        position: Set(position),
         created: NotSet,
     };
-
-    if let Err(e) = PasteFileEntity::insert(file).exec(database).await {
-        return Err((file_name, WebsiteError::from(e)).into());
+    let file_id = PasteFileEntity::insert(file)
+        .exec(database)
+        .await?
+        .last_insert_id;
+    create_revision(database, file_id, 1, location, encryption, author).await?;
+    Ok(file_id)
+}
+/// Inserts a file with staged content. Then moves the content into the blob store
+///
+/// The file and its revision are inserted in a transaction that is committed after the content.
+/// The quota is checked again inside it.
+/// So a file never points at content that does not exist.
+/// If the commit fails the content is left unreferenced and is found by the storage check
+#[allow(clippy::too_many_arguments)]
+async fn insert_staged_file(
+    database: &DatabaseConnection,
+    post_id: i64,
+    file_name: String,
+    file_type: FileType,
+    content: StagedBlob,
+    encryption: Option<EncryptionEnvelope>,
+    position: i32,
+    author: i64,
+    budget: &UploadBudget,
+) -> crate::Result<()> {
+    let result: crate::Result<_> = async {
+        let transaction = database.begin().await?;
+        insert_file(
+            &transaction,
+            post_id,
+            file_name,
+            file_type,
+            content.location(),
+            encryption,
+            position,
+            author,
+        )
+        .await?;
+        budget.recheck(&transaction).await?;
+        Ok(transaction)
     }
+    .await;
+    let transaction = match result {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            content.discard().await;
+            return Err(e);
+        }
+    };
+    // Dropping the transaction rolls it back
+    content.commit().await?;
+    transaction.commit().await?;
     Ok(())
 }
+/// Deletes a post that failed to be created. So a partially uploaded paste is not left behind
+async fn discard_post(
+    database: &DatabaseConnection,
+    rules: &PasteRules,
+    post_id: i64,
+) -> crate::Result<()> {
+    PastePostEntity::delete_by_id(post_id)
+        .exec(database)
+        .await?;
+    if let Err(e) = delete_post_files(rules, post_id).await {
+        warn!("Failed to delete files for discarded paste {post_id}: {e}");
+    }
+    Ok(())
+}
+/// The user a new paste is created for
+///
+/// Pastes created without logging in are owned by [ANONYMOUS_USER_ID] and get a delete token
+struct PasteCreator<'a> {
+    user_id: i64,
+    permissions: &'a Permissions,
+    anonymous: bool,
+}
+impl<'a> PasteCreator<'a> {
+    /// Uses [SiteRules::anonymous_permissions] if the request is not logged in
+    ///
+    /// # Errors
+    /// If the permissions do not allow creating pastes
+    fn new(auth: &'a OptionalAuthentication) -> crate::Result<Self> {
+        Self::from_actor(Actor::from(auth))
+    }
+    fn from_actor(actor: Actor<'a>) -> crate::Result<Self> {
+        if !can_create(Resource::Paste, &actor) {
+            return Err(if actor.is_anonymous() {
+                WebsiteError::Unauthorized
+            } else {
+                WebsiteError::Forbidden
+            });
+        }
+        Ok(Self {
+            user_id: actor.user_id().unwrap_or(ANONYMOUS_USER_ID),
+            permissions: actor.permissions(),
+            anonymous: actor.is_anonymous(),
+        })
+    }
+    /// Counts an anonymous paste towards [SiteRules::anonymous_paste_limit] of the address. Users are not limited
+    fn check_anonymous_limit(
+        &self,
+        pastes: &AnonymousPastes,
+        request: &HttpRequest,
+        site_rules: &SiteRules,
+    ) -> crate::Result<()> {
+        if !self.anonymous {
+            return Ok(());
+        }
+        pastes.start_paste(
+            client_address(request),
+            &site_rules.anonymous_paste_limit,
+            Utc::now(),
+        )
+    }
+    /// Anonymous pastes share [SiteRules::anonymous_quota]. Each address is limited by [PasteCreator::check_anonymous_limit]
+    fn quota<'r>(&self, site_rules: &'r SiteRules) -> &'r StorageQuota {
+        if self.anonymous {
+            &site_rules.anonymous_quota
+        } else {
+            site_rules.quota_for(self.permissions)
+        }
+    }
+    fn lifetime<'r>(&self, rules: &'r PasteRules) -> &'r PasteLifetime {
+        rules.lifetime_for((!self.anonymous).then_some(self.permissions))
+    }
+}
+/// A post created by [create_post]
+struct CreatedPost {
+    id: i64,
+    id_str: String,
+    /// The details of the files that will be uploaded
+    file_details: ahash::HashMap<String, NewFile>,
+    /// Only for anonymous pastes. Just its hash is stored. So it can not be shown again
+    delete_token: Option<String>,
+}
+/// Creates the post without any files
+///
+/// The post gets the requested slug or a generated id. A generated id is replaced if it is already taken
+async fn create_post(
+    database: &impl ConnectionTrait,
+    details: NewPaste,
+    rules: &PasteRules,
+    creator: &PasteCreator<'_>,
+    forked_from: Option<i64>,
+) -> crate::Result<CreatedPost> {
+    let NewPaste {
+        name,
+        description,
+        tags,
+        visibility,
+        file_details,
+        expires_at,
+        burn_after_read,
+        encrypted,
+        slug,
+    } = details;
+    check_paste_name(&name)?;
+    let expires_at = creator.lifetime(rules).expires_at(expires_at)?;
+    if let Some(slug) = &slug {
+        if !creator.permissions.can_use_custom_slug() {
+            return Err(WebsiteError::Forbidden);
+        }
+        validate_slug(slug)?;
+    }
+    let delete_token = creator.anonymous.then(token::generate_token);
+
+    let post = PastePostActiveModel {
+        id: NotSet,
+        id_str: NotSet,
+        name: Set(name),
+        description: Set(description),
+        visibility: Set(visibility),
+        tags: Set(tags),
+        user_id: Set(creator.user_id),
+        created: NotSet,
+        expires_at: Set(expires_at),
+        burn_after_read: Set(burn_after_read),
+        encrypted: Set(encrypted),
+        delete_token: Set(delete_token.as_ref().map(sha256::encode_to_string)),
+        forked_from: Set(forked_from),
+        deleted_at: Set(None),
+        last_updated: NotSet,
+    };
+    let id_rules = &rules.paste_ids;
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let string_id = slug.clone().unwrap_or_else(|| id_rules.generate());
+        let post = PastePostActiveModel {
+            id_str: Set(string_id.clone()),
+            ..post.clone()
+        };
+        match PastePostEntity::insert(post).exec(database).await {
+            Ok(result) => {
+                return Ok(CreatedPost {
+                    id: result.last_insert_id,
+                    id_str: string_id,
+                    file_details,
+                    delete_token,
+                })
+            }
+            Err(err) if is_id_taken(&err) && slug.is_some() => {
+                return Err(WebsiteError::PasteIdTaken)
+            }
+            Err(err) if is_id_taken(&err) && attempts < id_rules.max_attempts => {
+                debug!("Generated paste id {string_id} is taken. Generating another");
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+/// Errors caused by the server instead of the file. They fail the request instead of being listed in [NewPasteResponse::errors]
+///
+/// So database and storage errors are never shown to the client
+fn is_server_error(error: &WebsiteError) -> bool {
+    matches!(
+        error,
+        WebsiteError::IoError(_) | WebsiteError::DatabaseError(_)
+    )
+}
 #[derive(Debug, Serialize, ToSchema)]
 pub struct FileUploadError {
     pub file_name: String,
@@ -174,16 +510,27 @@ impl From<(String, WebsiteError)> for FileUploadError {
 pub struct NewPasteResponse {
     pub id: i64,
     pub paste_id: String,
+    /// The files that were not valid. Such as a file that exceeds the paste size
     pub errors: Vec<FileUploadError>,
+    /// Deletes the paste when sent as the `Delete-Token` header. Only returned once for pastes created without logging in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(nullable)]
+    pub delete_token: Option<String>,
 }
 impl NewPasteResponse {
-    pub fn new_request(id: i64, paste_id: String, errors: Vec<FileUploadError>) -> HttpResponse {
+    pub fn new_request(
+        id: i64,
+        paste_id: String,
+        errors: Vec<FileUploadError>,
+        delete_token: Option<String>,
+    ) -> HttpResponse {
         HttpResponse::Created()
             .insert_header((LOCATION, format!("/api/paste/{paste_id}")))
             .json(NewPasteResponse {
                 id,
                 paste_id,
                 errors,
+                delete_token,
             })
     }
 }
@@ -192,140 +539,880 @@ impl NewPasteResponse {
     path = "/api/paste/new",
     request_body (content = NewPost, content_type = "multipart/form-data"),
     responses(
-        (status = 201, description = "Paste Contents", body = NewPasteResponse)
+        (status = 201, description = "Paste Contents. Files that exceed the paste size or storage quota are listed in errors", body = NewPasteResponse),
+        (status = 400, description = "The request is not valid. No paste is created"),
+        (status = 401, description = "Anonymous pastes are not allowed"),
+        (status = 403, description = "You can not create pastes or have reached the maximum number of pastes. Or you can not choose the slug"),
+        (status = 409, description = "The slug is already in use"),
+        (status = 413, description = "The request exceeds the upload limits. No paste is created"),
+        (status = 429, description = "Too many pastes created from your address without logging in")
     ),
 security(
+(),
 ("api_key" = [])
 )
 )]
 #[post("/new")]
 pub async fn new(
-    auth: Authentication,
-    upload: MultipartForm<NewPost>,
+    auth: OptionalAuthentication,
+    request: HttpRequest,
+    payload: Multipart,
     database: Data<DatabaseConnection>,
     rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+    anonymous_pastes: Data<AnonymousPastes>,
 ) -> crate::Result<HttpResponse> {
-    if !rules.allow_post_creation_without_file && upload.files.is_empty() {
-        return Ok(HttpResponse::BadRequest().finish());
-    }
-    let NewPost { details, files } = upload.into_inner();
-    let NewPaste {
-        name,
-        description,
-        tags,
-        visibility,
-        mut file_details,
-    } = details
-        .map(|details| details.into_inner())
-        .unwrap_or_default();
+    let creator = PasteCreator::new(&auth)?;
+    creator.check_anonymous_limit(anonymous_pastes.as_ref(), &request, site_rules.as_ref())?;
+    let quota = creator.quota(site_rules.as_ref());
+    let usage = get_storage_usage(database.as_ref(), creator.user_id).await?;
+    check_paste_count(quota, &usage)?;
+    let mut budget = UploadBudget::for_paste(rules.as_ref(), quota, creator.user_id, &usage, 0);
+    let mut upload = MultipartUpload::new(
+        payload,
+        rules.multipart_limits(site_rules.max_payload.get_as_bytes()),
+    );
 
-    let string_id = generate_post_paste_id(database.as_ref()).await?;
-    let post = PastePostActiveModel {
-        id: NotSet,
-        id_str: Set(string_id.clone()),
-        name: Set(name),
-        description: Set(description),
-        visibility: Set(visibility),
-        tags: Set(tags),
-        user_id: Set(auth.id()),
-        created: NotSet,
-        last_updated: NotSet,
-    };
-    let id = PastePostEntity::insert(post)
-        .exec(database.as_ref())
-        .await?
-        .last_insert_id;
-    let mut file_errors = Vec::with_capacity(files.len());
-    for (index, file) in files.into_iter().enumerate() {
-        let file = handle_file_upload(
-            id,
+    // The post is created when the first file arrives. So the files can be written directly to it
+    let mut post: Option<CreatedPost> = None;
+    let mut encrypted = false;
+    let mut file_errors = Vec::new();
+    let result: crate::Result<CreatedPost> = async {
+        let mut details = None;
+        let mut file_details = HashMap::default();
+        let mut index = 0;
+        while let Some(mut field) = upload.next_field().await? {
+            let name = field.name().to_owned();
+            match name.as_str() {
+                "details" if post.is_none() => {
+                    let mut new_details = upload.read_json::<NewPaste>(&mut field).await?;
+                    new_details.visibility = hash_visibility(new_details.visibility)?;
+                    details = Some(new_details);
+                }
+                "files" => {
+                    let post_id = match &post {
+                        Some(post) => post.id,
+                        None => {
+                            let details = details.take().unwrap_or_default();
+                            encrypted = details.encrypted;
+                            let mut created = create_post(
+                                database.as_ref(),
+                                details,
+                                rules.as_ref(),
+                                &creator,
+                                None,
+                            )
+                            .await?;
+                            file_details = std::mem::take(&mut created.file_details);
+                            let id = created.id;
+                            post = Some(created);
+                            id
+                        }
+                    };
+                    let file = handle_file_upload(
+                        post_id,
+                        database.as_ref(),
+                        |file_name| file_details.remove(file_name).unwrap_or_default(),
+                        &mut upload,
+                        field,
+                        index,
+                        blobs.as_ref(),
+                        creator.user_id,
+                        &mut budget,
+                        encrypted,
+                    )
+                    .await;
+                    index += 1;
+                    if let Err(err) = file {
+                        if aborts_request(&err.error) || is_server_error(&err.error) {
+                            return Err(err.error);
+                        }
+                        file_errors.push(err);
+                    }
+                }
+                _ => return Err(WebsiteError::UnexpectedField(name)),
+            }
+        }
+        if let Some(post) = post.take() {
+            return Ok(post);
+        }
+        if !rules.allow_post_creation_without_file {
+            return Err(WebsiteError::MissingField("files"));
+        }
+        create_post(
             database.as_ref(),
-            |file_name| file_details.remove(file_name).unwrap_or_default(),
-            file,
-            index,
+            details.unwrap_or_default(),
             rules.as_ref(),
+            &creator,
+            None,
         )
-        .await;
-        if let Err(err) = file {
-            file_errors.push(err);
+        .await
+    }
+    .await;
+    match result {
+        Ok(created) => Ok(NewPasteResponse::new_request(
+            created.id,
+            created.id_str,
+            file_errors,
+            created.delete_token,
+        )),
+        Err(e) => {
+            if let Some(post) = post {
+                discard_post(database.as_ref(), rules.as_ref(), post.id).await?;
+            }
+            Err(e)
         }
     }
-    Ok(NewPasteResponse::new_request(id, string_id, file_errors))
-}
-#[derive(Debug, MultipartForm)]
-pub struct NewFileUpload {
-    pub details: Option<JsonForm<ahash::HashMap<String, NewFile>>>,
-    pub files: Vec<TempFile>,
 }
 
 /// Adds new files to a paste
+///
+/// Fields are read in the order they are sent. So `details` must be sent before `files`
 #[post("/{id}/new/file")]
 pub async fn new_file(
     auth: Authentication,
     path: web::Path<i64>,
-    upload: MultipartForm<NewFileUpload>,
+    payload: Multipart,
     database: Data<DatabaseConnection>,
     rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
 ) -> crate::Result<HttpResponse> {
     let post: PastePostModel = find_post_by_id(database.as_ref(), path.into_inner())
         .await?
         .ok_or(WebsiteError::NotFound)?;
-    if post.user_id != auth.id() {
+    if !can_edit(Resource::Paste, &Actor::from(&auth), &post) {
         return Ok(HttpResponse::Forbidden().finish());
     }
-    let NewFileUpload { details, mut files } = upload.into_inner();
+    let user: &User = auth.as_ref();
+    let usage = get_storage_usage(database.as_ref(), user.id).await?;
+    let paste_size = get_paste_size(database.as_ref(), post.id).await?;
+    let mut budget = UploadBudget::for_paste(
+        rules.as_ref(),
+        site_rules.quota_for(&user.permissions),
+        user.id,
+        &usage,
+        paste_size,
+    );
+    let mut upload = MultipartUpload::new(
+        payload,
+        rules.multipart_limits(site_rules.max_payload.get_as_bytes()),
+    );
 
     let number_of_files_in_post = PasteFileEntity::find()
         .filter(paste::file::Column::PostId.eq(post.id))
         .count(database.as_ref())
         .await? as usize;
-    if files.len() == 1 {
-        let file = files.remove(0);
-        let details: NewFile = if let Some(details) = details {
-            details.0.into_iter().next().unwrap_or_default().1
-        } else {
-            NewFile::default()
-        };
-        let file = handle_file_upload(
+    let mut details: ahash::HashMap<String, NewFile> = HashMap::default();
+    let mut file_errors = Vec::new();
+    let mut files = 0;
+    while let Some(mut field) = upload.next_field().await? {
+        let name = field.name().to_owned();
+        match name.as_str() {
+            "details" if files == 0 => {
+                details = upload.read_json(&mut field).await?;
+            }
+            "files" => {
+                let file = handle_file_upload(
+                    post.id,
+                    database.as_ref(),
+                    |file_name| details.remove(file_name).unwrap_or_default(),
+                    &mut upload,
+                    field,
+                    files + number_of_files_in_post,
+                    blobs.as_ref(),
+                    auth.id(),
+                    &mut budget,
+                    post.encrypted,
+                )
+                .await;
+                files += 1;
+                if let Err(err) = file {
+                    if aborts_request(&err.error) || is_server_error(&err.error) {
+                        return Err(err.error);
+                    }
+                    file_errors.push(err);
+                }
+            }
+            _ => return Err(WebsiteError::UnexpectedField(name)),
+        }
+    }
+    if files == 1 && file_errors.len() == 1 {
+        Ok(HttpResponse::BadRequest().json(file_errors.remove(0)))
+    } else {
+        Ok(NewPasteResponse::new_request(
             post.id,
+            post.id_str,
+            file_errors,
+            None,
+        ))
+    }
+}
+
+/// Burn after read pastes can not be forked. A fork would keep a permanent copy of the content
+fn check_can_fork(original: &PastePostModel) -> crate::Result<()> {
+    if original.burn_after_read {
+        return Err(WebsiteError::BurnAfterRead);
+    }
+    Ok(())
+}
+/// Copies a paste and its files into a new paste owned by the caller
+///
+/// The fork has the visibility of the original. Including the password of a password protected paste.
+/// Burn after read pastes can not be forked
+#[utoipa::path(post,
+    impl_for = fork,
+    path = "/api/paste/{id}/fork",
+    params(
+        ("id", description = "The id of the paste to fork")
+    ),
+    responses(
+        (status = 201, description = "The new paste", body = Paste),
+        (status = 400, description = "The paste is burned after it is read. So it can not be forked"),
+        (status = 401, description = "You are not logged in or can not view the paste"),
+        (status = 403, description = "Your storage quota is exceeded"),
+        (status = 404, description = "Paste Not Found or the paste has expired")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[post("/{id}/fork")]
+pub async fn fork(
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    id: web::Path<String>,
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+) -> crate::Result<JsonResponse<Paste>> {
+    // Forks are owned by the caller. So they can not be anonymous
+    if auth.as_ref().is_none() {
+        return Err(WebsiteError::Unauthorized);
+    }
+    let creator = PasteCreator::new(&auth)?;
+    let original = find_post_by_str_id(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    access.check(original.id, &original, &auth).await?;
+    check_can_fork(&original)?;
+    let files = get_files(database.as_ref(), original.id).await?;
+
+    let quota = creator.quota(site_rules.as_ref());
+    let usage = get_storage_usage(database.as_ref(), creator.user_id).await?;
+    check_paste_count(quota, &usage)?;
+    let mut budget = UploadBudget::for_paste(rules.as_ref(), quota, creator.user_id, &usage, 0);
+    for file in &files {
+        budget.reserve(file.location.file_size() as u64)?;
+    }
+    // Expired pastes can not be forked
+    check_post(&original, &auth)?;
+
+    let details = NewPaste {
+        name: original.name,
+        description: original.description,
+        tags: original.tags,
+        // A password protected fork keeps the hashed password. So it unlocks with the same password
+        visibility: original.visibility,
+        encrypted: original.encrypted,
+        ..Default::default()
+    };
+    let id = create_post(
+        database.as_ref(),
+        details,
+        rules.as_ref(),
+        &creator,
+        Some(original.id),
+    )
+    .await?
+    .id;
+    let result = copy_files(
+        database.as_ref(),
+        blobs.as_ref(),
+        id,
+        files,
+        creator.user_id,
+        &budget,
+    )
+    .await;
+    if let Err(e) = result {
+        discard_post(database.as_ref(), rules.as_ref(), id).await?;
+        return Err(e);
+    }
+
+    let paste = Paste::get_by_id(database.as_ref(), id, true)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    Ok(JsonResponse(paste, StatusCode::CREATED))
+}
+/// Copies the files of another post into a post
+///
+/// Files in the blob store are shared with the original. Older files are added to the blob store
+async fn copy_files(
+    database: &DatabaseConnection,
+    blobs: &BlobStore,
+    post_id: i64,
+    files: Vec<PasteFileModel>,
+    author: i64,
+    budget: &UploadBudget,
+) -> crate::Result<()> {
+    let mut staged = Vec::new();
+    let result: crate::Result<()> = async {
+        let transaction = database.begin().await?;
+        for file in files {
+            let location = match file.location {
+                location @ FileLocation::Blob { .. } => location,
+                FileLocation::Local { location, .. } => {
+                    let content = blobs.copy(&location).await?;
+                    let location = content.location();
+                    staged.push(content);
+                    location
+                }
+            };
+            insert_file(
+                &transaction,
+                post_id,
+                file.file_name,
+                file.file_type,
+                location,
+                file.encryption,
+                file.position,
+                author,
+            )
+            .await?;
+        }
+        budget.recheck(&transaction).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        for content in staged {
+            content.discard().await;
+        }
+        return Err(e);
+    }
+    for content in staged {
+        content.commit().await?;
+    }
+    Ok(())
+}
+
+/// Header alternative to the `name` query parameter of [new_raw]
+const FILE_NAME_HEADER: &str = "file-name";
+/// Header alternative to the `visibility` query parameter of [new_raw]
+const PASTE_VISIBILITY_HEADER: &str = "paste-visibility";
+/// Header alternative to the `slug` query parameter of [new_raw]
+const PASTE_SLUG_HEADER: &str = "paste-slug";
+
+/// The options of a paste created from a request body.
+///
+/// Each option can also be sent as a header. Query parameters take priority
+#[derive(Debug, Default, Deserialize, IntoParams)]
+pub struct RawPasteQuery {
+    /// The name of the file and the paste. Header `File-Name`
+    pub name: Option<String>,
+    /// The programming language of the file. Detected if not provided. Header `Programming-Language`
+    pub language: Option<String>,
+    /// `public`, `unlisted` or `private`. Header `Paste-Visibility`
+    pub visibility: Option<String>,
+    /// The id of the paste instead of a generated one. Requires the custom slug permission. Header `Paste-Slug`
+    pub slug: Option<String>,
+}
+impl RawPasteQuery {
+    /// Fills in the options that were not in the query from the headers
+    fn with_headers(mut self, request: &HttpRequest) -> Self {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
+        self.name = self.name.or_else(|| header(FILE_NAME_HEADER));
+        self.language = self
+            .language
+            .or_else(|| header(PROGRAMMING_LANGUAGE.as_str()));
+        self.visibility = self.visibility.or_else(|| header(PASTE_VISIBILITY_HEADER));
+        self.slug = self.slug.or_else(|| header(PASTE_SLUG_HEADER));
+        self
+    }
+    fn visibility(&self) -> crate::Result<Visibility> {
+        let Some(visibility) = self.visibility.as_deref() else {
+            return Ok(Visibility::default());
+        };
+        match visibility.to_ascii_lowercase().as_str() {
+            "public" => Ok(Visibility::Public),
+            "unlisted" => Ok(Visibility::Unlisted),
+            "private" => Ok(Visibility::Private { visible_to: vec![] }),
+            _ => Err(WebsiteError::InvalidVisibility),
+        }
+    }
+    /// The file type from the options and the `Content-Type` of the request
+    fn file_type(&self, request: &HttpRequest, file_name: &str) -> crate::Result<FileType> {
+        let content_type = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            // curl sends form data unless told otherwise. Neither says anything about the content
+            .filter(|content_type| {
+                !content_type.starts_with("application/x-www-form-urlencoded")
+                    && !content_type.starts_with("application/octet-stream")
+            });
+        let mut file_type = match content_type {
+            Some(content_type) => {
+                FileType::new_from_header(content_type).ok_or(WebsiteError::InvalidFileType)?
+            }
+            None => FileType::default(),
+        };
+        file_type.programming_language = self.language.clone();
+        file_type.process_file(file_name);
+        if !file_type.check() {
+            return Err(WebsiteError::InvalidFileType);
+        }
+        Ok(file_type)
+    }
+    /// The name from the options. Or `paste` with the extension of the language
+    fn file_name(&self) -> crate::Result<String> {
+        if let Some(name) = &self.name {
+            return Ok(normalize_file_name(name)?);
+        }
+        let extension = self
+            .language
+            .as_deref()
+            .and_then(Language::find)
+            .and_then(|language| language.extensions.first())
+            .unwrap_or(&"txt");
+        Ok(format!("paste.{extension}"))
+    }
+}
+/// Terminals get a plain text URL instead of JSON. Unless they ask for JSON
+fn wants_plain_text(request: &HttpRequest) -> bool {
+    let header = |name| {
+        request
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+    };
+    let accept = header(ACCEPT);
+    if accept.contains("application/json") {
+        return false;
+    }
+    if accept.contains("text/plain") {
+        return true;
+    }
+    let user_agent = header(USER_AGENT);
+    ["curl/", "Wget/", "HTTPie/", "xh/"]
+        .iter()
+        .any(|client| user_agent.starts_with(client))
+}
+/// The address of the rendered file. Includes the host the request was sent to
+fn file_url(request: &HttpRequest, paste_id: &str, file_name: &str) -> String {
+    let connection = request.connection_info();
+    format!(
+        "{}://{}/render/paste/{paste_id}/file/{}",
+        connection.scheme(),
+        connection.host(),
+        utf8_percent_encode(file_name, NON_ALPHANUMERIC)
+    )
+}
+
+/// Creates a paste with a single file from the request body
+///
+/// ```sh
+/// cat log.txt | curl --data-binary @- -H "Authorization: Bearer $TOKEN" "https://example.com/api/paste/raw?name=log.txt"
+/// ```
+#[utoipa::path(post,
+    impl_for = new_raw,
+    path = "/api/paste/raw",
+    params(RawPasteQuery),
+    request_body (content = String, content_type = "text/plain", description = "The content of the file"),
+    responses(
+        (status = 201, description = "The paste was created. A plain text URL to the file is returned to terminals", body = NewPasteResponse),
+        (status = 400, description = "The file is not valid UTF-8 or the options are invalid"),
+        (status = 401, description = "Anonymous pastes are not allowed"),
+        (status = 403, description = "You can not create pastes or have reached the maximum number of pastes or your storage quota. Or you can not choose the slug"),
+        (status = 409, description = "The slug is already in use"),
+        (status = 429, description = "Too many pastes created from your address without logging in")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[post("/raw")]
+pub async fn new_raw(
+    auth: OptionalAuthentication,
+    query: web::Query<RawPasteQuery>,
+    request: HttpRequest,
+    payload: web::Payload,
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+    anonymous_pastes: Data<AnonymousPastes>,
+) -> crate::Result<HttpResponse> {
+    let creator = PasteCreator::new(&auth)?;
+    creator.check_anonymous_limit(anonymous_pastes.as_ref(), &request, site_rules.as_ref())?;
+    let options = query.into_inner().with_headers(&request);
+    let file_name = options.file_name()?;
+    let mut file_type = options.file_type(&request, &file_name)?;
+    let details = NewPaste {
+        name: file_name.clone(),
+        visibility: options.visibility()?,
+        slug: options.slug.clone(),
+        ..Default::default()
+    };
+
+    let quota = creator.quota(site_rules.as_ref());
+    let usage = get_storage_usage(database.as_ref(), creator.user_id).await?;
+    check_paste_count(quota, &usage)?;
+    let budget = UploadBudget::for_paste(rules.as_ref(), quota, creator.user_id, &usage, 0);
+    let max_file_size = rules.max_file_size.get_as_bytes();
+    let created = create_post(database.as_ref(), details, rules.as_ref(), &creator, None).await?;
+    let id = created.id;
+
+    let result: crate::Result<()> = async {
+        let content = save_stream(payload, blobs.as_ref(), true, |_, size| {
+            if size > max_file_size {
+                return Err(WebsiteError::ExceedsMaxLength);
+            }
+            budget.check(size as u64)
+        })
+        .await?;
+        if file_type.programming_language.is_none() {
+            match read_content_sample(content.temp_path(), content.size()).await {
+                Ok(sample) => file_type.process_content(&sample),
+                Err(e) => warn!("Failed to read {file_name:?} to detect its language: {e}"),
+            }
+        }
+        insert_staged_file(
             database.as_ref(),
-            |_| details,
-            file,
-            number_of_files_in_post,
-            rules.as_ref(),
+            id,
+            file_name.clone(),
+            file_type,
+            content,
+            None,
+            0,
+            creator.user_id,
+            &budget,
         )
-        .await;
-        if let Err(err) = file {
-            Ok(HttpResponse::BadRequest().json(err))
-        } else {
-            Ok(NewPasteResponse::new_request(post.id, post.id_str, vec![]))
+        .await
+    }
+    .await;
+    if let Err(e) = result {
+        discard_post(database.as_ref(), rules.as_ref(), id).await?;
+        return Err(e);
+    }
+
+    if wants_plain_text(&request) {
+        let mut response = HttpResponse::Created();
+        response
+            .insert_header((LOCATION, format!("/api/paste/{}", created.id_str)))
+            .content_type(ContentType::plaintext());
+        // The body is only the URL. So it can be piped into other commands
+        if let Some(delete_token) = &created.delete_token {
+            response.insert_header((DELETE_TOKEN_HEADER, delete_token.as_str()));
         }
+        Ok(response.body(format!(
+            "{}\n",
+            file_url(&request, &created.id_str, &file_name)
+        )))
     } else {
-        let mut file_errors = Vec::with_capacity(files.len());
+        Ok(NewPasteResponse::new_request(
+            id,
+            created.id_str,
+            vec![],
+            created.delete_token,
+        ))
+    }
+}
 
-        let mut map = if let Some(details) = details {
-            details.into_inner()
-        } else {
-            HashMap::default()
+/// A file sent as JSON
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewJsonFile {
+    pub name: String,
+    pub content: String,
+    /// Detected from the name and content if not provided
+    #[serde(default)]
+    pub file_type: Option<FileType>,
+}
+/// A paste and its files sent as JSON
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct NewJsonPaste {
+    #[serde(flatten)]
+    pub details: NewPaste,
+    #[serde(default)]
+    pub files: Vec<NewJsonFile>,
+}
+
+/// Creates a paste from JSON. For scripts that do not want to build a multipart request
+#[utoipa::path(post,
+    impl_for = new_json,
+    path = "/api/paste/new/json",
+    request_body (content = NewJsonPaste, content_type = "application/json"),
+    responses(
+        (status = 201, description = "Paste Contents. Files that exceed the paste size or storage quota are listed in errors", body = NewPasteResponse),
+        (status = 400, description = "The request is not valid. No paste is created"),
+        (status = 401, description = "Anonymous pastes are not allowed"),
+        (status = 403, description = "You can not create pastes or have reached the maximum number of pastes. Or you can not choose the slug"),
+        (status = 409, description = "The slug is already in use"),
+        (status = 413, description = "The request exceeds the upload limits. No paste is created"),
+        (status = 429, description = "Too many pastes created from your address without logging in")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[post("/new/json")]
+pub async fn new_json(
+    auth: OptionalAuthentication,
+    request: HttpRequest,
+    payload: web::Payload,
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+    anonymous_pastes: Data<AnonymousPastes>,
+) -> crate::Result<HttpResponse> {
+    let creator = PasteCreator::new(&auth)?;
+    creator.check_anonymous_limit(anonymous_pastes.as_ref(), &request, site_rules.as_ref())?;
+    let limits = rules.multipart_limits(site_rules.max_payload.get_as_bytes());
+    // The whole body is held while it is parsed. So it is limited like a multipart request.
+    // Escaping in JSON counts towards the limit
+    let NewJsonPaste { mut details, files } = {
+        let body = read_body(payload, limits.max_request_size).await?;
+        serde_json::from_slice(&body).map_err(WebsiteError::InvalidJson)?
+    };
+    details.visibility = hash_visibility(details.visibility)?;
+    // Ciphertext is not text. So it can not be sent as a JSON string
+    if details.encrypted {
+        return Err(WebsiteError::EncryptedPaste);
+    }
+    if files.len() > limits.max_files {
+        return Err(WebsiteError::TooManyFiles);
+    }
+    if files.is_empty() && !rules.allow_post_creation_without_file {
+        return Err(WebsiteError::MissingField("files"));
+    }
+
+    let quota = creator.quota(site_rules.as_ref());
+    let usage = get_storage_usage(database.as_ref(), creator.user_id).await?;
+    check_paste_count(quota, &usage)?;
+    let mut budget = UploadBudget::for_paste(rules.as_ref(), quota, creator.user_id, &usage, 0);
+    let created = create_post(database.as_ref(), details, rules.as_ref(), &creator, None).await?;
+    let id = created.id;
+
+    let mut file_errors = Vec::new();
+    let mut file_names = HashSet::new();
+    for (index, mut file) in files.into_iter().enumerate() {
+        file.name = match normalize_file_name(&file.name) {
+            Ok(name) => name,
+            Err(e) => {
+                file_errors.push(FileUploadError::from((file.name, e.into())));
+                continue;
+            }
         };
-        for (index, file) in files.into_iter().enumerate() {
-            let file = handle_file_upload(
-                post.id,
-                database.as_ref(),
-                |name| map.remove(name).unwrap_or_default(),
-                file,
-                index + number_of_files_in_post,
-                rules.as_ref(),
-            )
-            .await;
-            if let Err(err) = file {
-                file_errors.push(err);
+        let file_name = file.name.clone();
+        if !file_names.insert(file_name.clone()) {
+            file_errors.push(FileUploadError::from((
+                file_name,
+                WebsiteError::FileAlreadyExists,
+            )));
+            continue;
+        }
+        let result = save_json_file(
+            database.as_ref(),
+            blobs.as_ref(),
+            id,
+            file,
+            index,
+            creator.user_id,
+            &mut budget,
+            limits.max_file_size,
+        )
+        .await;
+        if let Err(error) = result {
+            if is_server_error(&error) {
+                discard_post(database.as_ref(), rules.as_ref(), id).await?;
+                return Err(error);
             }
+            file_errors.push(FileUploadError::from((file_name, error)));
         }
-        Ok(NewPasteResponse::new_request(
-            post.id,
-            post.id_str,
-            file_errors,
-        ))
+    }
+    Ok(NewPasteResponse::new_request(
+        id,
+        created.id_str,
+        file_errors,
+        created.delete_token,
+    ))
+}
+#[allow(clippy::too_many_arguments)]
+async fn save_json_file(
+    database: &DatabaseConnection,
+    blobs: &BlobStore,
+    post_id: i64,
+    file: NewJsonFile,
+    index: usize,
+    author: i64,
+    budget: &mut UploadBudget,
+    max_file_size: usize,
+) -> crate::Result<()> {
+    let NewJsonFile {
+        name,
+        content,
+        file_type,
+    } = file;
+    if content.len() > max_file_size {
+        return Err(WebsiteError::ExceedsMaxLength);
+    }
+    budget.reserve(content.len() as u64)?;
+    let mut file_type = file_type.unwrap_or_default();
+    if !file_type.check() {
+        return Err(WebsiteError::InvalidFileType);
+    }
+    file_type.process_file(&name);
+    file_type.process_content(&content);
+
+    let content = blobs.write(content.as_bytes()).await?;
+    insert_staged_file(
+        database,
+        post_id,
+        name,
+        file_type,
+        content,
+        None,
+        index as i32,
+        author,
+        budget,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use chrono::Utc;
+    use common::visibility::Visibility;
+    use entities::{
+        user::{permissions::Permissions, ANONYMOUS_USER_ID},
+        PastePostModel,
+    };
+    use sea_orm::DbErr;
+
+    use super::{check_can_fork, is_server_error, PasteCreator};
+    use crate::{config::SiteRules, error::WebsiteError, paste::PasteRules, policy::Actor};
+
+    #[test]
+    fn anonymous_creators_share_the_anonymous_rules() {
+        let mut permissions = Permissions::new_anonymous();
+        permissions.paste_permissions.create = true;
+        let creator = PasteCreator::from_actor(Actor::new(None, &permissions)).unwrap();
+        assert_eq!(creator.user_id, ANONYMOUS_USER_ID);
+        assert!(creator.anonymous);
+
+        let site_rules = SiteRules::default();
+        let rules = PasteRules::default();
+        assert!(ptr::eq(
+            creator.quota(&site_rules),
+            &site_rules.anonymous_quota
+        ));
+        assert!(ptr::eq(creator.lifetime(&rules), &rules.anonymous_lifetime));
+    }
+
+    #[test]
+    fn users_get_their_own_rules() {
+        let user = Permissions::default();
+        let creator = PasteCreator::from_actor(Actor::new(Some(1), &user)).unwrap();
+        assert_eq!(creator.user_id, 1);
+        assert!(!creator.anonymous);
+        let site_rules = SiteRules::default();
+        assert!(ptr::eq(creator.quota(&site_rules), &site_rules.user_quota));
+
+        let admin = Permissions::new_admin();
+        let creator = PasteCreator::from_actor(Actor::new(Some(2), &admin)).unwrap();
+        assert!(ptr::eq(creator.quota(&site_rules), &site_rules.admin_quota));
+    }
+
+    #[test]
+    fn quota_and_lifetime_use_the_same_tier() {
+        let site_rules = SiteRules::default();
+        let rules = PasteRules::default();
+        let mut paste_admin = Permissions::default();
+        paste_admin.paste_permissions.admin = true;
+        let creator = PasteCreator::from_actor(Actor::new(Some(1), &paste_admin)).unwrap();
+        assert!(ptr::eq(creator.quota(&site_rules), &site_rules.user_quota));
+        assert!(ptr::eq(creator.lifetime(&rules), &rules.user_lifetime));
+
+        let admin = Permissions::new_admin();
+        let creator = PasteCreator::from_actor(Actor::new(Some(2), &admin)).unwrap();
+        assert!(ptr::eq(creator.quota(&site_rules), &site_rules.admin_quota));
+        assert!(ptr::eq(creator.lifetime(&rules), &rules.admin_lifetime));
+    }
+
+    #[test]
+    fn creating_needs_the_create_permission() {
+        let anonymous = Permissions::new_anonymous();
+        assert!(matches!(
+            PasteCreator::from_actor(Actor::new(None, &anonymous)),
+            Err(WebsiteError::Unauthorized)
+        ));
+        let mut user = Permissions::default();
+        user.paste_permissions.create = false;
+        assert!(matches!(
+            PasteCreator::from_actor(Actor::new(Some(1), &user)),
+            Err(WebsiteError::Forbidden)
+        ));
+    }
+
+    #[test]
+    fn only_server_errors_fail_the_upload() {
+        assert!(is_server_error(&WebsiteError::from(DbErr::Custom(
+            "connection lost".to_owned()
+        ))));
+        assert!(is_server_error(&WebsiteError::from(std::io::Error::from(
+            std::io::ErrorKind::Other
+        ))));
+        for error in [
+            WebsiteError::ExceedsMaxLength,
+            WebsiteError::ExceedsMaxPasteSize,
+            WebsiteError::QuotaExceeded,
+            WebsiteError::InvalidFileType,
+            WebsiteError::FileAlreadyExists,
+        ] {
+            assert!(!is_server_error(&error), "{error}");
+        }
+    }
+
+    #[test]
+    fn burn_after_read_pastes_can_not_be_forked() {
+        let mut post = PastePostModel {
+            id: 1,
+            id_str: "original".to_owned(),
+            user_id: 1,
+            name: "Secret".to_owned(),
+            tags: vec![],
+            description: String::new(),
+            visibility: Visibility::Public,
+            expires_at: None,
+            burn_after_read: false,
+            encrypted: false,
+            delete_token: None,
+            forked_from: None,
+            deleted_at: None,
+            last_updated: Utc::now().into(),
+            created: Utc::now().into(),
+        };
+        assert!(check_can_fork(&post).is_ok());
+        post.burn_after_read = true;
+        assert!(matches!(
+            check_can_fork(&post),
+            Err(WebsiteError::BurnAfterRead)
+        ));
     }
 }
@@ -1,53 +1,72 @@
-use actix_web::{delete, web, web::Data, HttpResponse};
+use actix_web::{delete, web, web::Data, HttpRequest, HttpResponse};
+use chrono::Utc;
 use entities::{
-    paste, user::user_responses::User, PasteFileEntity, PastePostEntity, PastePostModel,
+    paste,
+    paste::database_helpers::{find_post_by_id, set_post_deleted},
+    PasteFileEntity, PastePostModel,
 };
-use sea_orm::{prelude::*, EntityTrait, IntoActiveModel, QueryFilter};
+use sea_orm::{prelude::*, EntityTrait, QueryFilter};
 
-use crate::{user::Authentication, DatabaseConnection};
+use crate::{
+    policy::{can_delete, Actor, Resource},
+    user::OptionalAuthentication,
+    utils::sha256,
+    DatabaseConnection,
+};
+
+/// The token returned when a paste is created without logging in
+pub const DELETE_TOKEN_HEADER: &str = "delete-token";
+
+/// Anyone the policy allows and requests with the delete token of the paste can delete it
+fn can_delete_paste(
+    paste: &PastePostModel,
+    auth: &OptionalAuthentication,
+    request: &HttpRequest,
+) -> bool {
+    if can_delete(Resource::Paste, &Actor::from(auth), paste) {
+        return true;
+    }
+    let token = request
+        .headers()
+        .get(DELETE_TOKEN_HEADER)
+        .and_then(|value| value.to_str().ok());
+    match (token, &paste.delete_token) {
+        (Some(token), Some(hash)) => sha256::encode_to_string(token) == *hash,
+        _ => false,
+    }
+}
+/// Moves the paste to the trash. It is deleted permanently by [purge_trash](super::trash::purge_trash)
 #[delete("/{id}")]
 pub async fn delete(
-    auth: Authentication,
+    auth: OptionalAuthentication,
     path: web::Path<i64>,
     database: Data<DatabaseConnection>,
+    request: HttpRequest,
 ) -> crate::Result<HttpResponse> {
-    let Some(paste) = PastePostEntity::find_by_id(path.into_inner())
-        .one(database.as_ref())
-        .await?
-    else {
+    let Some(paste) = find_post_by_id(database.as_ref(), path.into_inner()).await? else {
         return Ok(HttpResponse::NotFound().finish());
     };
-    let user: User = auth.into();
-    if paste.user_id != user.id || !user.permissions.admin {
+    if !can_delete_paste(&paste, &auth, &request) {
         return Ok(HttpResponse::Forbidden().finish());
     }
-    let id = paste.id;
-    PastePostEntity::delete(paste.into_active_model())
-        .exec(database.as_ref())
-        .await?;
-    // I am pretty sure this is not needed
-    PasteFileEntity::delete_many()
-        .filter(paste::file::Column::PostId.eq(id))
-        .exec(database.as_ref())
-        .await?;
+    set_post_deleted(database.as_ref(), paste.id, Some(Utc::now().into())).await?;
     Ok(HttpResponse::NoContent().finish())
 }
 
 #[delete("/{id}/file/{file_name}")]
 pub async fn delete_file(
-    auth: Authentication,
+    auth: OptionalAuthentication,
     path: web::Path<(i64, String)>,
     database: Data<DatabaseConnection>,
+    request: HttpRequest,
 ) -> crate::Result<HttpResponse> {
     let (id, file_id) = path.into_inner();
 
-    let post: PastePostModel = PastePostEntity::find_by_id(id)
-        .one(database.as_ref())
+    let post: PastePostModel = find_post_by_id(database.as_ref(), id)
         .await?
         .ok_or(crate::Error::NotFound)?;
-    let user: User = auth.into();
 
-    if post.user_id != user.id || !user.permissions.admin {
+    if !can_delete_paste(&post, &auth, &request) {
         return Ok(HttpResponse::Forbidden().finish());
     }
     let result = PasteFileEntity::delete_many()
@@ -60,3 +79,65 @@ pub async fn delete_file(
     }
     Ok(HttpResponse::NoContent().finish())
 }
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{test::TestRequest, web::Data};
+    use chrono::Utc;
+    use common::visibility::Visibility;
+    use entities::{user::ANONYMOUS_USER_ID, PastePostModel};
+
+    use super::{can_delete_paste, DELETE_TOKEN_HEADER};
+    use crate::{config::SiteRules, user::OptionalAuthentication, utils::sha256};
+
+    fn anonymous_paste(delete_token: Option<&str>) -> PastePostModel {
+        PastePostModel {
+            id: 1,
+            id_str: "abcd1234".to_owned(),
+            user_id: ANONYMOUS_USER_ID,
+            name: "Anonymous Paste".to_owned(),
+            tags: vec![],
+            description: String::new(),
+            visibility: Visibility::Public,
+            expires_at: None,
+            burn_after_read: false,
+            encrypted: false,
+            delete_token: delete_token.map(sha256::encode_to_string),
+            forked_from: None,
+            deleted_at: None,
+            last_updated: Utc::now().into(),
+            created: Utc::now().into(),
+        }
+    }
+    fn anonymous() -> OptionalAuthentication {
+        OptionalAuthentication::Anonymous {
+            site_rules: Data::new(SiteRules::default()),
+        }
+    }
+
+    #[test]
+    fn delete_token_allows_deleting() {
+        let paste = anonymous_paste(Some("token"));
+        let request = TestRequest::default()
+            .insert_header((DELETE_TOKEN_HEADER, "token"))
+            .to_http_request();
+        assert!(can_delete_paste(&paste, &anonymous(), &request));
+    }
+
+    #[test]
+    fn wrong_or_missing_delete_token() {
+        let paste = anonymous_paste(Some("token"));
+        let wrong = TestRequest::default()
+            .insert_header((DELETE_TOKEN_HEADER, "other"))
+            .to_http_request();
+        assert!(!can_delete_paste(&paste, &anonymous(), &wrong));
+        let missing = TestRequest::default().to_http_request();
+        assert!(!can_delete_paste(&paste, &anonymous(), &missing));
+        // Pastes created by users have no token. So no header can match
+        let paste = anonymous_paste(None);
+        let empty = TestRequest::default()
+            .insert_header((DELETE_TOKEN_HEADER, ""))
+            .to_http_request();
+        assert!(!can_delete_paste(&paste, &anonymous(), &empty));
+    }
+}
@@ -0,0 +1,134 @@
+use actix_web::web::Data;
+use chrono::Utc;
+use common::visibility::HasVisibility;
+use entities::{
+    paste::{database_helpers::FileOwnerAndVisibility, HasExpiration, PostColumn},
+    PastePostEntity, PastePostModel,
+};
+use sea_orm::{prelude::*, QuerySelect};
+use tracing::{error, info, warn};
+
+use crate::{
+    blob::BlobStore, images, images::ImageRules, paste::PasteRules, user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+/// Checks that the file can still be read and if reading it burns the paste.
+///
+/// The content must be opened before the paste is burned. So it is never lost to a failed read
+///
+/// # Returns
+/// - `Ok(true)` - Reading burns the paste. The caller should open the file, call [burn] then [delete_post_files](super::delete_post_files)
+/// - `Ok(false)` - The file can be read normally
+/// - `Err(WebsiteError::NotFound)` - The paste has expired
+pub(crate) fn check_file(
+    file: &FileOwnerAndVisibility,
+    auth: &OptionalAuthentication,
+) -> crate::Result<bool> {
+    check(file, file.burn_after_read, auth)
+}
+/// [check_file] for reading every file of a paste at once
+pub(crate) fn check_post(
+    post: &PastePostModel,
+    auth: &OptionalAuthentication,
+) -> crate::Result<bool> {
+    check(post, post.burn_after_read, auth)
+}
+fn check(
+    paste: &(impl HasExpiration + HasVisibility),
+    burn_after_read: bool,
+    auth: &OptionalAuthentication,
+) -> crate::Result<bool> {
+    if paste.is_expired() {
+        return Err(crate::Error::NotFound);
+    }
+    if !burn_after_read {
+        return Ok(false);
+    }
+    let is_owner = auth
+        .as_ref()
+        .map(|user| paste.is_owner(user.id))
+        .unwrap_or(false);
+    Ok(!is_owner)
+}
+/// Burns a paste after its content has been opened.
+///
+/// Only one request can delete the row. Any others will get a `NotFound` and must not send the content
+pub(crate) async fn burn(database: &DatabaseConnection, post_id: i64) -> crate::Result<()> {
+    let result = PastePostEntity::delete_by_id(post_id)
+        .exec(database)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(crate::Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Deletes all pastes that have expired along with their files
+pub async fn delete_expired(
+    database: &DatabaseConnection,
+    rules: &PasteRules,
+) -> Result<u64, DbErr> {
+    let expired: Vec<i64> = PastePostEntity::find()
+        .select_only()
+        .column(PostColumn::Id)
+        .filter(PostColumn::ExpiresAt.lte(Utc::now()))
+        .into_tuple()
+        .all(database)
+        .await?;
+    if expired.is_empty() {
+        return Ok(0);
+    }
+    let result = PastePostEntity::delete_many()
+        .filter(PostColumn::Id.is_in(expired.clone()))
+        .exec(database)
+        .await?;
+    for post_id in expired {
+        if let Err(e) = super::delete_post_files(rules, post_id).await {
+            warn!("Failed to delete files for expired paste {post_id}: {e}");
+        }
+    }
+    Ok(result.rows_affected)
+}
+
+/// Starts a background task that deletes expired pastes every `expiration_check_interval`.
+///
+/// Pastes and images past their trash retention and blobs that are no longer referenced are deleted at the same time
+pub fn start_reaper(
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+    image_rules: Data<ImageRules>,
+    blobs: Data<BlobStore>,
+) {
+    let period = rules
+        .expiration_check_interval
+        .duration
+        .to_std()
+        .unwrap_or(std::time::Duration::from_secs(300));
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            match delete_expired(database.as_ref(), rules.as_ref()).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {deleted} expired pastes"),
+                Err(e) => error!("Failed to delete expired pastes: {e}"),
+            }
+            match super::trash::purge_trash(database.as_ref(), rules.as_ref()).await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {purged} pastes from the trash"),
+                Err(e) => error!("Failed to purge deleted pastes: {e}"),
+            }
+            match images::trash::purge_trash(database.as_ref(), image_rules.as_ref()).await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {purged} image posts from the trash"),
+                Err(e) => error!("Failed to purge deleted image posts: {e}"),
+            }
+            match blobs.collect_garbage(database.as_ref()).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {deleted} unreferenced blobs"),
+                Err(e) => error!("Failed to delete unreferenced blobs: {e}"),
+            }
+        }
+    });
+}
@@ -1,12 +1,27 @@
 use actix_web::{get, web, web::Data};
-use common::visibility::HasVisibility;
-use entities::{
-    paste::{database_helpers::FileOwnerAndVisibility, Paste},
-    user::{permissions::Permissions, user_responses::User},
+use common::paste::language::{Language, LANGUAGES};
+use digestible::Digestible;
+use entities::paste::{
+    database_helpers::{find_post_by_str_id, FileOwnerAndVisibility},
+    HasExpiration, Paste,
 };
+use helper_macros::Response;
+use serde::Serialize;
+use tracing::warn;
+use typeshare::typeshare;
+use utoipa::ToSchema;
 
 use crate::{
-    paste::PasteFile, responses::JsonResponse, user::OptionalAuthentication, DatabaseConnection,
+    paste::{
+        delete_post_files,
+        expiration::{burn, check_file},
+        password::PasteAccess,
+        views::ViewTracker,
+        PasteFile, PasteRules,
+    },
+    responses::JsonResponse,
+    user::OptionalAuthentication,
+    DatabaseConnection,
 };
 
 #[utoipa::path(get,
@@ -17,7 +32,9 @@ use crate::{
     ),
     responses(
         (status = 200, description = "Paste Contents", body = Paste),
-        (status = 404, description = "Paste Not Found")
+        (status = 401, description = "You can not view the paste. Password protected pastes need the `Paste-Password` or `Paste-Access` header"),
+        (status = 404, description = "Paste Not Found"),
+        (status = 429, description = "Too many incorrect passwords. Try again later")
     ),
 security(
 (),
@@ -28,19 +45,29 @@ security(
 pub async fn get(
     id: web::Path<String>,
     database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    views: ViewTracker,
 ) -> crate::Result<JsonResponse<Paste>> {
-    Paste::get_by_id_str(database.as_ref(), id.into_inner(), true)
+    let post = find_post_by_str_id(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(crate::Error::NotFound)?;
+    access.check(post.id, &post, &auth).await?;
+    let paste = Paste::get_by_id(database.as_ref(), post.id, true)
         .await?
-        .ok_or(crate::Error::NotFound)
-        .map(|paste| JsonResponse::from(paste))
+        .filter(|paste| !paste.is_expired())
+        .ok_or(crate::Error::NotFound)?;
+    views.record(post.id, None, &post, &auth);
+    Ok(JsonResponse::from(paste))
 }
 
 #[utoipa::path(get,
 impl_for = get_file,
     path = "/api/paste/{id}/file/{file_name}",
     responses(
-        (status = 200, description = "File Contents", body = PasteFile),
-        (status = 404, description = "File Not Found")
+        (status = 200, description = "File Contents. If the paste is burn after read it is deleted after this request", body = PasteFile),
+        (status = 401, description = "You can not view the paste. Password protected pastes need the `Paste-Password` or `Paste-Access` header"),
+        (status = 404, description = "File Not Found or the paste has expired")
     ),
 security(
 (),
@@ -52,6 +79,9 @@ pub async fn get_file(
     id: web::Path<(String, String)>,
     database: Data<DatabaseConnection>,
     auth: OptionalAuthentication,
+    access: PasteAccess,
+    views: ViewTracker,
+    rules: Data<PasteRules>,
 ) -> crate::Result<JsonResponse<PasteFile>> {
     let (id, file_name) = id.into_inner();
     let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(
@@ -61,22 +91,63 @@ pub async fn get_file(
     )
     .await?
     .ok_or(crate::Error::NotFound)?;
+    access.check(file.post_id, &file, &auth).await?;
+    let burned = check_file(&file, &auth)?;
+    // A burned paste no longer exists to have views
+    if !burned {
+        views.record(file.post_id, Some(file.id), &file, &auth);
+    }
 
-    if file.requires_auth() {
-        let OptionalAuthentication::Auth(auth) = auth else {
-            return Err(crate::Error::Unauthorized);
-        };
-        let user: User = auth.into();
-        if !user.permissions.is_paste_admin() && !file.is_visible_to(user.id) {
-            return Err(crate::Error::Unauthorized);
+    let post_id = file.post_id;
+    let file = PasteFile::new(file).await?;
+    if burned {
+        burn(database.as_ref(), post_id).await?;
+        if let Err(e) = delete_post_files(rules.as_ref(), post_id).await {
+            warn!("Failed to delete files for burned paste {post_id}: {e}");
         }
-    } else if !<OptionalAuthentication as AsRef<Permissions>>::as_ref(&auth)
-        .paste_permissions
-        .view_public
-    {
-        return Err(crate::Error::Unauthorized);
     }
-
-    let file = PasteFile::new(file).await;
     Ok(JsonResponse::from(file))
 }
+
+/// A programming language that can be detected and highlighted
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible)]
+#[typeshare]
+pub struct SupportedLanguage {
+    /// Used as the `programming_language` of a file
+    pub id: String,
+    pub name: String,
+    pub mime_type: String,
+    pub extensions: Vec<String>,
+    pub file_names: Vec<String>,
+}
+impl From<&Language> for SupportedLanguage {
+    fn from(language: &Language) -> Self {
+        Self {
+            id: language.id.to_owned(),
+            name: language.name.to_owned(),
+            mime_type: language.mime_type.to_owned(),
+            extensions: language.extensions.iter().map(|v| v.to_string()).collect(),
+            file_names: language.file_names.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+#[refresh_duration(days(1))]
+#[typeshare]
+pub struct SupportedLanguages {
+    pub languages: Vec<SupportedLanguage>,
+}
+
+#[utoipa::path(get,
+    impl_for = languages,
+    path = "/api/paste/languages",
+    responses(
+        (status = 200, description = "The programming languages that can be detected", body = SupportedLanguages),
+    ),
+)]
+#[get("/languages")]
+pub async fn languages() -> JsonResponse<SupportedLanguages> {
+    JsonResponse::from(SupportedLanguages {
+        languages: LANGUAGES.iter().map(SupportedLanguage::from).collect(),
+    })
+}
@@ -1,31 +1,143 @@
-use std::path::PathBuf;
+use std::{
+    io::SeekFrom,
+    path::{Path, PathBuf},
+};
 
 use actix_web::web;
-use common::{file_location::FileLocation, paste::file_type::FileType};
-use config_types::size_config::ConfigSize;
+use chrono::{Duration, Utc};
+use common::{
+    file_location::FileLocation,
+    paste::{
+        encryption::{EncryptionEnvelope, NONCE_LENGTH},
+        file_type::FileType,
+    },
+};
+use config_types::{
+    chrono_types::duration::{ConfigDuration, Unit},
+    size_config::ConfigSize,
+};
 use digestible::Digestible;
-use entities::paste::database_helpers::FileOwnerAndVisibility;
+use entities::{paste::database_helpers::FileOwnerAndVisibility, user::permissions::Permissions};
 use helper_macros::{Response, Rules};
 use sea_orm::prelude::DateTimeWithTimeZone;
 use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use typeshare::typeshare;
 use utoipa::ToSchema;
 
+use crate::{
+    error::WebsiteError, paste::paste_id::PasteIdRules, upload::MultipartLimits,
+    utils::base64_utils,
+};
+
+pub mod anonymous;
+pub mod archive;
+pub mod comment_routes;
 pub mod create_routes;
 mod delete_routes;
+pub mod expiration;
 pub mod get_routes;
+pub mod password;
+pub mod paste_id;
 pub mod raw;
+pub mod render;
+pub mod revision_routes;
+pub mod trash;
+pub mod update_routes;
+pub mod views;
+
+pub use delete_routes::DELETE_TOKEN_HEADER;
 
 pub fn init(cfg: &mut web::ServiceConfig) {
-    cfg.service(get_routes::get)
+    // Registered before `/{id}` so they are not treated as paste ids
+    cfg.service(get_routes::languages)
+        .service(trash::get_paste_trash)
+        .service(get_routes::get)
         .service(get_routes::get_file)
         .service(create_routes::new)
+        .service(create_routes::new_raw)
+        .service(create_routes::new_json)
         .service(create_routes::new_file)
+        .service(create_routes::fork)
+        .service(password::unlock)
+        .service(views::stats)
+        .service(comment_routes::get_paste_comments)
+        .service(comment_routes::new_comment)
+        .service(comment_routes::update_comment)
+        .service(comment_routes::delete_comment)
+        .service(update_routes::update)
+        .service(update_routes::update_file)
+        .service(update_routes::replace_file)
+        .service(update_routes::reorder_files)
+        .service(revision_routes::get_revisions)
+        .service(revision_routes::diff)
+        .service(trash::restore)
         .service(delete_routes::delete)
         .service(delete_routes::delete_file);
 }
 pub fn init_raw(cfg: &mut web::ServiceConfig) {
-    cfg.service(raw::get_file).service(raw::head_file);
+    cfg.service(raw::get_file)
+        .service(raw::head_file)
+        .service(archive::get_zip)
+        .service(archive::get_tar_gz);
+}
+pub fn init_render(cfg: &mut web::ServiceConfig) {
+    cfg.service(render::render_file);
+}
+
+/// Deletes the files of a post that are not in the blob store. Including all revisions
+///
+/// Blobs are deleted by [BlobStore::collect_garbage](crate::blob::BlobStore::collect_garbage) once nothing references them
+pub(crate) async fn delete_post_files(rules: &PasteRules, post_id: i64) -> std::io::Result<()> {
+    match tokio::fs::remove_dir_all(rules.post_directory(post_id)).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+/// Reads the content of a paste file
+pub(crate) async fn read_to_string(location: &FileLocation) -> std::io::Result<String> {
+    tokio::fs::read_to_string(location.path()).await
+}
+/// Counts the lines of a file. A final line without a line break is counted
+pub(crate) async fn count_lines(path: &Path) -> std::io::Result<usize> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut lines = 0;
+    let mut last = None;
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        lines += buffer[..read].iter().filter(|byte| **byte == b'\n').count();
+        last = Some(buffer[read - 1]);
+    }
+    if last.is_some_and(|last| last != b'\n') {
+        lines += 1;
+    }
+    Ok(lines)
+}
+/// The number of bytes read from each end of a file to detect its language
+const CONTENT_SAMPLE_SIZE: usize = 4 * 1024;
+/// Reads the start and end of a file. Enough to find a shebang or modeline
+pub(crate) async fn read_content_sample(path: &Path, size: usize) -> std::io::Result<String> {
+    let mut file = tokio::fs::File::open(path).await?;
+    if size <= CONTENT_SAMPLE_SIZE * 2 {
+        let mut content = Vec::with_capacity(size);
+        file.read_to_end(&mut content).await?;
+        return Ok(String::from_utf8_lossy(&content).into_owned());
+    }
+    let mut head = vec![0; CONTENT_SAMPLE_SIZE];
+    file.read_exact(&mut head).await?;
+    file.seek(SeekFrom::End(-(CONTENT_SAMPLE_SIZE as i64)))
+        .await?;
+    let mut tail = vec![0; CONTENT_SAMPLE_SIZE];
+    file.read_exact(&mut tail).await?;
+    Ok(format!(
+        "{}\n{}",
+        String::from_utf8_lossy(&head),
+        String::from_utf8_lossy(&tail)
+    ))
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize, ToSchema, Digestible, Response)]
@@ -35,30 +147,64 @@ pub struct PasteFile {
     pub file_name: String,
     pub file_type: FileType,
     pub size: u64,
+    /// The content of the file. Base64 encoded ciphertext if the file is encrypted
     pub file: String,
+    /// How the file was encrypted. None if the paste is not encrypted
+    #[schema(nullable)]
+    pub encryption: Option<EncryptionEnvelope>,
     #[schema(value_type = DateTime)]
     #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
     #[digestible(digest_with = digest_with_hash)]
     pub created: DateTimeWithTimeZone,
 }
 impl PasteFile {
-    pub async fn new(model: FileOwnerAndVisibility) -> Self {
-        let (size, content) = match model.location {
-            FileLocation::Local { location, size } => {
-                let content = tokio::fs::read_to_string(location).await.unwrap();
-                let size = size as u64;
-                (size, content)
-            }
+    /// Reads the content of the file. Encrypted content is base64 encoded
+    pub async fn new(model: FileOwnerAndVisibility) -> crate::Result<Self> {
+        let size = model.location.file_size() as u64;
+        let content = if model.encryption.is_some() {
+            tokio::fs::read(model.location.path())
+                .await
+                .map(base64_utils::encode)?
+        } else {
+            tokio::fs::read_to_string(model.location.path()).await?
         };
-        Self {
+        Ok(Self {
             id: model.id,
             post_id: model.post_id,
             file_name: model.file_name,
             file_type: model.file_type,
             size,
             file: content,
+            encryption: model.encryption,
             created: model.created,
+        })
+    }
+}
+/// Paste names must contain something other than whitespace
+pub(crate) fn check_paste_name(name: &str) -> crate::Result<()> {
+    if name.trim().is_empty() {
+        return Err(WebsiteError::EmptyPasteName);
+    }
+    Ok(())
+}
+/// Checks the envelope sent for a file against the paste it is uploaded to
+///
+/// Files in encrypted pastes must have an envelope. Other files must not
+pub(crate) fn check_encryption(
+    encrypted: bool,
+    envelope: Option<EncryptionEnvelope>,
+) -> crate::Result<Option<EncryptionEnvelope>> {
+    match envelope {
+        Some(envelope) if encrypted => {
+            let nonce = base64_utils::decode(&envelope.nonce)
+                .map_err(|_| WebsiteError::InvalidEncryption)?;
+            if nonce.len() != NONCE_LENGTH {
+                return Err(WebsiteError::InvalidEncryption);
+            }
+            Ok(Some(envelope))
         }
+        None if !encrypted => Ok(None),
+        _ => Err(WebsiteError::InvalidEncryption),
     }
 }
 #[derive(Debug, Deserialize, Serialize, Rules, Digestible)]
@@ -77,10 +223,112 @@ pub struct PasteRules {
     pub allow_file_updates: bool,
     #[rule()]
     pub allow_post_creation_without_file: bool,
+    /// The most files that can be uploaded in a single request
+    #[rule]
+    pub max_files_per_request: usize,
+    /// Lifetime of pastes created by anonymous users
+    #[rule]
+    pub anonymous_lifetime: PasteLifetime,
+    /// Lifetime of pastes created by users
+    #[rule]
+    pub user_lifetime: PasteLifetime,
+    /// Lifetime of pastes created by users with the admin permission. The same users that get [crate::config::SiteRules::admin_quota]
+    ///
+    /// Paste admins without it get [PasteRules::user_lifetime]
+    #[rule]
+    pub admin_lifetime: PasteLifetime,
+    /// How often expired pastes are deleted
+    #[typeshare(typescript(type = "string"))]
+    pub expiration_check_interval: ConfigDuration,
+    /// Repeated views by the same user or address within this window are counted once
+    #[typeshare(typescript(type = "string"))]
+    pub view_deduplication_window: ConfigDuration,
+    /// How long deleted pastes stay in the trash before they are deleted permanently
+    #[typeshare(typescript(type = "string"))]
+    pub trash_retention: ConfigDuration,
+    /// How the ids of new pastes are generated
+    #[digestible(skip)]
+    #[typeshare(skip)]
+    pub paste_ids: PasteIdRules,
     #[digestible(skip)]
     #[typeshare(skip)]
     pub location: PathBuf,
 }
+impl PasteRules {
+    /// The limits of the paste upload routes
+    ///
+    /// # Parameters
+    /// - `max_field_size` - The size of the JSON details sent with the files
+    pub fn multipart_limits(&self, max_field_size: usize) -> MultipartLimits {
+        let max_file_size = self.max_file_size.get_as_bytes();
+        let max_files_size = max_file_size.saturating_mul(self.max_files_per_request);
+        let max_files_size = match &self.max_paste_size {
+            Some(max_paste_size) => max_files_size.min(max_paste_size.get_as_bytes()),
+            None => max_files_size,
+        };
+        MultipartLimits {
+            max_request_size: max_files_size.saturating_add(max_field_size),
+            max_file_size,
+            max_files: self.max_files_per_request,
+            max_field_size,
+            require_utf8: true,
+        }
+    }
+    /// The directory of the files of a post that were uploaded before the blob store
+    pub fn post_directory(&self, post_id: i64) -> PathBuf {
+        self.location.join(post_id.to_string())
+    }
+    /// The lifetime rules for the given permissions. None is an anonymous user
+    ///
+    /// The admin tier is chosen by [Permissions::admin] like [crate::config::SiteRules::quota_for]
+    pub fn lifetime_for(&self, permissions: Option<&Permissions>) -> &PasteLifetime {
+        match permissions {
+            None => &self.anonymous_lifetime,
+            Some(permissions) if permissions.admin => &self.admin_lifetime,
+            Some(_) => &self.user_lifetime,
+        }
+    }
+}
+/// Limits on how long a paste can exist before it is deleted
+#[derive(Debug, Clone, Default, Deserialize, Serialize, Digestible)]
+#[serde(default)]
+#[typeshare]
+pub struct PasteLifetime {
+    /// Used when the paste does not request an expiration. None means the paste does not expire
+    #[typeshare(typescript(type = "string"))]
+    pub default_lifetime: Option<ConfigDuration>,
+    /// The longest a paste can exist. None means there is no limit
+    #[typeshare(typescript(type = "string"))]
+    pub max_lifetime: Option<ConfigDuration>,
+}
+impl PasteLifetime {
+    /// Calculates the expiration of a new paste.
+    ///
+    /// Requested expirations past the maximum lifetime are shortened to the maximum lifetime
+    pub fn expires_at(
+        &self,
+        requested: Option<DateTimeWithTimeZone>,
+    ) -> crate::Result<Option<DateTimeWithTimeZone>> {
+        let now: DateTimeWithTimeZone = Utc::now().into();
+        let max = self
+            .max_lifetime
+            .as_ref()
+            .map(|max_lifetime| now + max_lifetime.duration);
+        let expires_at = match requested {
+            Some(requested) if requested <= now => return Err(WebsiteError::InvalidExpiration),
+            Some(requested) => Some(requested),
+            None => self
+                .default_lifetime
+                .as_ref()
+                .map(|default_lifetime| now + default_lifetime.duration),
+        };
+        Ok(match (expires_at, max) {
+            (Some(expires_at), Some(max)) => Some(expires_at.min(max)),
+            (None, max) => max,
+            (expires_at, None) => expires_at,
+        })
+    }
+}
 impl Default for PasteRules {
     fn default() -> Self {
         Self {
@@ -89,7 +337,46 @@ impl Default for PasteRules {
             show_without_login: true,
             allow_file_updates: true,
             allow_post_creation_without_file: true,
+            max_files_per_request: 20,
+            anonymous_lifetime: PasteLifetime::default(),
+            user_lifetime: PasteLifetime::default(),
+            admin_lifetime: PasteLifetime::default(),
+            expiration_check_interval: ConfigDuration {
+                duration: Duration::minutes(5),
+                unit: Unit::Minutes,
+            },
+            view_deduplication_window: ConfigDuration {
+                duration: Duration::minutes(30),
+                unit: Unit::Minutes,
+            },
+            trash_retention: ConfigDuration {
+                duration: Duration::days(30),
+                unit: Unit::Days,
+            },
+            paste_ids: PasteIdRules::default(),
             location: PathBuf::from("pastes"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::count_lines;
+
+    async fn lines_of(content: &[u8]) -> usize {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(content).unwrap();
+        count_lines(file.path()).await.unwrap()
+    }
+
+    #[actix_web::test]
+    async fn counts_lines() {
+        assert_eq!(lines_of(b"").await, 0);
+        assert_eq!(lines_of(b"one").await, 1);
+        assert_eq!(lines_of(b"one\n").await, 1);
+        assert_eq!(lines_of(b"one\ntwo").await, 2);
+        assert_eq!(lines_of(b"one\r\ntwo\r\n\n").await, 3);
+    }
+}
@@ -0,0 +1,413 @@
+//! Password protected pastes.
+//!
+//! The password is sent with each request as `Paste-Password`.
+//! Or exchanged at `/api/paste/{id}/unlock` for an access grant sent as `Paste-Access` or the `access` query parameter.
+//! Failed attempts are limited per client and paste
+use std::{
+    future::{ready, Ready},
+    net::IpAddr,
+};
+
+use actix_web::{dev::Payload, post, web, web::Data, FromRequest, HttpRequest};
+use chrono::{DateTime, Duration, Utc};
+use common::visibility::{HasVisibility, Visibility};
+use digestible::Digestible;
+use entities::paste::database_helpers::find_post_by_str_id;
+use helper_macros::Response;
+use parking_lot::Mutex;
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    config::client_address,
+    error::WebsiteError,
+    policy::{can_view, Actor, Resource, ViewAccess},
+    responses::JsonResponse,
+    user::OptionalAuthentication,
+    utils::password::{check_password, encrypt_password},
+    DatabaseConnection, Error,
+};
+
+pub const PASTE_PASSWORD_HEADER: &str = "paste-password";
+pub const PASTE_ACCESS_HEADER: &str = "paste-access";
+/// How long an access grant can be used
+const GRANT_LIFETIME_MINUTES: i64 = 60;
+/// The failed attempts allowed for one client on a paste within [ATTEMPT_WINDOW_MINUTES]
+const MAX_FAILED_ATTEMPTS: u32 = 5;
+/// The failed attempts allowed for a paste from all clients within [ATTEMPT_WINDOW_MINUTES].
+/// Well above [MAX_FAILED_ATTEMPTS]. So a single client can not lock everyone out
+const MAX_PASTE_FAILED_ATTEMPTS: u32 = 100;
+const ATTEMPT_WINDOW_MINUTES: i64 = 15;
+
+/// Replaces the password of [Visibility::Password] with its hash. Other visibilities are unchanged
+pub(crate) fn hash_visibility(visibility: Visibility) -> crate::Result<Visibility> {
+    let Visibility::Password { password } = visibility else {
+        return Ok(visibility);
+    };
+    if password.is_empty() {
+        return Err(WebsiteError::InvalidVisibility);
+    }
+    let password = encrypt_password(&password).ok_or(WebsiteError::InvalidVisibility)?;
+    Ok(Visibility::Password { password })
+}
+
+struct Grant {
+    post_id: i64,
+    expires_at: DateTime<Utc>,
+}
+struct FailedAttempts {
+    count: u32,
+    since: DateTime<Utc>,
+}
+impl FailedAttempts {
+    /// Counts an attempt. Unless the limit has been reached
+    fn count(&mut self, limit: u32) -> crate::Result<()> {
+        if self.count >= limit {
+            return Err(WebsiteError::TooManyAttempts);
+        }
+        self.count += 1;
+        Ok(())
+    }
+}
+/// Who is trying a password. Failed attempts are limited per client
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    User(i64),
+    Address(IpAddr),
+    /// The connection has no address. Such as a unix socket
+    Unknown,
+}
+impl Client {
+    fn new(auth: &OptionalAuthentication, address: Option<IpAddr>) -> Self {
+        match (auth.as_ref(), address) {
+            (Some(user), _) => Client::User(user.id),
+            (None, Some(address)) => Client::Address(address),
+            (None, None) => Client::Unknown,
+        }
+    }
+}
+/// The access grants and failed attempts. Kept in memory. So they are lost on restart
+#[derive(Default)]
+pub struct PasteUnlocks {
+    grants: Mutex<ahash::HashMap<String, Grant>>,
+    attempts: Mutex<ahash::HashMap<(i64, Client), FailedAttempts>>,
+    paste_attempts: Mutex<ahash::HashMap<i64, FailedAttempts>>,
+}
+impl PasteUnlocks {
+    /// Checks the password. Counting failures towards the limits of the client and the paste
+    async fn check_password(
+        &self,
+        client: Client,
+        post_id: i64,
+        password: &str,
+        hash: &str,
+    ) -> crate::Result<()> {
+        // Counted before the password is checked. So parallel requests can not exceed the limit
+        self.start_attempt(&client, post_id, Utc::now())?;
+        let password = password.to_owned();
+        let hash = hash.to_owned();
+        let correct = web::block(move || check_password(&password, &hash))
+            .await
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))??;
+        if !correct {
+            return Err(WebsiteError::Unauthorized);
+        }
+        self.attempt_succeeded(&client, post_id);
+        Ok(())
+    }
+    /// Counts an attempt of the client on the paste. Fails if either limit has been reached
+    fn start_attempt(
+        &self,
+        client: &Client,
+        post_id: i64,
+        now: DateTime<Utc>,
+    ) -> crate::Result<()> {
+        let window = Duration::minutes(ATTEMPT_WINDOW_MINUTES);
+        let new_attempts = || FailedAttempts {
+            count: 0,
+            since: now,
+        };
+        // Locked in the same order everywhere
+        let mut attempts = self.attempts.lock();
+        let mut paste_attempts = self.paste_attempts.lock();
+        attempts.retain(|_, attempt| attempt.since + window > now);
+        paste_attempts.retain(|_, attempt| attempt.since + window > now);
+
+        let attempt = attempts
+            .entry((post_id, client.clone()))
+            .or_insert_with(new_attempts);
+        if attempt.count >= MAX_FAILED_ATTEMPTS {
+            return Err(WebsiteError::TooManyAttempts);
+        }
+        paste_attempts
+            .entry(post_id)
+            .or_insert_with(new_attempts)
+            .count(MAX_PASTE_FAILED_ATTEMPTS)?;
+        attempt.count(MAX_FAILED_ATTEMPTS)
+    }
+    /// Forgets the failures of the client. The attempt is no longer counted for the paste
+    fn attempt_succeeded(&self, client: &Client, post_id: i64) {
+        let mut attempts = self.attempts.lock();
+        let mut paste_attempts = self.paste_attempts.lock();
+        attempts.remove(&(post_id, client.clone()));
+        if let Some(attempt) = paste_attempts.get_mut(&post_id) {
+            attempt.count = attempt.count.saturating_sub(1);
+        }
+    }
+    fn create_grant(&self, post_id: i64) -> (String, DateTime<Utc>) {
+        let now = Utc::now();
+        let expires_at = now + Duration::minutes(GRANT_LIFETIME_MINUTES);
+        let mut grants = self.grants.lock();
+        grants.retain(|_, grant| grant.expires_at > now);
+        let token = loop {
+            let token: String = (0..32)
+                .map(|_| OsRng.sample(Alphanumeric) as char)
+                .collect();
+            if !grants.contains_key(&token) {
+                break token;
+            }
+        };
+        grants.insert(
+            token.clone(),
+            Grant {
+                post_id,
+                expires_at,
+            },
+        );
+        (token, expires_at)
+    }
+    fn has_grant(&self, token: &str, post_id: i64) -> bool {
+        self.grants
+            .lock()
+            .get(token)
+            .is_some_and(|grant| grant.post_id == post_id && grant.expires_at > Utc::now())
+    }
+}
+
+/// The password or access grant sent with a request
+pub struct PasteAccess {
+    password: Option<String>,
+    grant: Option<String>,
+    address: Option<IpAddr>,
+    unlocks: Data<PasteUnlocks>,
+}
+#[derive(Deserialize)]
+struct AccessQuery {
+    access: Option<String>,
+}
+impl FromRequest for PasteAccess {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_owned())
+        };
+        // Links opened in a browser can not send headers
+        let grant = header(PASTE_ACCESS_HEADER).or_else(|| {
+            web::Query::<AccessQuery>::from_query(req.query_string())
+                .ok()
+                .and_then(|query| query.into_inner().access)
+        });
+        let unlocks = req
+            .app_data::<Data<PasteUnlocks>>()
+            .expect("Unable to get PasteUnlocks Ref")
+            .clone();
+        ready(Ok(Self {
+            password: header(PASTE_PASSWORD_HEADER),
+            grant,
+            address: client_address(req),
+            unlocks,
+        }))
+    }
+}
+impl PasteAccess {
+    /// Checks that the request can read the paste.
+    ///
+    /// Requests that [can_view] asks for a password need the password or a grant
+    pub async fn check(
+        &self,
+        post_id: i64,
+        item: &impl HasVisibility,
+        auth: &OptionalAuthentication,
+    ) -> crate::Result<()> {
+        match can_view(Resource::Paste, &Actor::from(auth), item) {
+            ViewAccess::Allowed => return Ok(()),
+            ViewAccess::Denied => return Err(WebsiteError::Unauthorized),
+            ViewAccess::RequiresPassword => {}
+        }
+        let Some(hash) = item.password_hash() else {
+            return Err(WebsiteError::Unauthorized);
+        };
+        if let Some(grant) = &self.grant {
+            if self.unlocks.has_grant(grant, post_id) {
+                return Ok(());
+            }
+        }
+        match &self.password {
+            Some(password) => {
+                let client = Client::new(auth, self.address);
+                self.unlocks
+                    .check_password(client, post_id, password, hash)
+                    .await
+            }
+            None => Err(WebsiteError::Unauthorized),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UnlockPaste {
+    pub password: String,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible, Response)]
+#[expires(Some(self.expires_at.into()))]
+#[private]
+pub struct PasteAccessGrant {
+    /// Sent as the `Paste-Access` header or the `access` query parameter
+    pub access: String,
+    #[schema(value_type = DateTime)]
+    #[digestible(digest_with = digest_with_hash)]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Exchanges the password of a paste for an access grant. So the password does not have to be sent with every request
+#[utoipa::path(post,
+    impl_for = unlock,
+    path = "/api/paste/{id}/unlock",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    request_body = UnlockPaste,
+    responses(
+        (status = 200, description = "The password is correct", body = PasteAccessGrant),
+        (status = 400, description = "The paste is not password protected"),
+        (status = 401, description = "The password is incorrect"),
+        (status = 404, description = "Paste Not Found"),
+        (status = 429, description = "Too many incorrect passwords. Try again later")
+    ),
+)]
+#[post("/{id}/unlock")]
+pub async fn unlock(
+    id: web::Path<String>,
+    request: web::Json<UnlockPaste>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    database: Data<DatabaseConnection>,
+    unlocks: Data<PasteUnlocks>,
+) -> crate::Result<JsonResponse<PasteAccessGrant>> {
+    let post = find_post_by_str_id(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    let Some(hash) = post.password_hash() else {
+        return Err(WebsiteError::InvalidVisibility);
+    };
+    let client = Client::new(&auth, access.address);
+    unlocks
+        .check_password(client, post.id, &request.password, hash)
+        .await?;
+    let (access, expires_at) = unlocks.create_grant(post.id);
+    Ok(JsonResponse::from(PasteAccessGrant { access, expires_at }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use chrono::{Duration, Utc};
+
+    use super::{
+        Client, Grant, PasteUnlocks, ATTEMPT_WINDOW_MINUTES, MAX_FAILED_ATTEMPTS,
+        MAX_PASTE_FAILED_ATTEMPTS,
+    };
+    use crate::error::WebsiteError;
+
+    fn address(last: u8) -> Client {
+        Client::Address(Ipv4Addr::new(10, 0, 0, last).into())
+    }
+
+    #[test]
+    fn attempts_are_limited_per_client() {
+        let unlocks = PasteUnlocks::default();
+        let now = Utc::now();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            unlocks.start_attempt(&address(1), 1, now).unwrap();
+        }
+        assert!(matches!(
+            unlocks.start_attempt(&address(1), 1, now),
+            Err(WebsiteError::TooManyAttempts)
+        ));
+        // Other clients and other pastes are not locked out
+        unlocks.start_attempt(&address(2), 1, now).unwrap();
+        unlocks.start_attempt(&Client::User(1), 1, now).unwrap();
+        unlocks.start_attempt(&address(1), 2, now).unwrap();
+    }
+
+    #[test]
+    fn attempts_are_limited_per_paste() {
+        let unlocks = PasteUnlocks::default();
+        let now = Utc::now();
+        for client in 0..MAX_PASTE_FAILED_ATTEMPTS {
+            unlocks
+                .start_attempt(&Client::User(client as i64), 1, now)
+                .unwrap();
+        }
+        assert!(matches!(
+            unlocks.start_attempt(&Client::User(-1), 1, now),
+            Err(WebsiteError::TooManyAttempts)
+        ));
+        unlocks.start_attempt(&Client::User(-1), 2, now).unwrap();
+    }
+
+    #[test]
+    fn attempts_expire_after_the_window() {
+        let unlocks = PasteUnlocks::default();
+        let now = Utc::now();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            unlocks.start_attempt(&address(1), 1, now).unwrap();
+        }
+        let later = now + Duration::minutes(ATTEMPT_WINDOW_MINUTES);
+        unlocks.start_attempt(&address(1), 1, later).unwrap();
+    }
+
+    #[test]
+    fn success_resets_the_client() {
+        let unlocks = PasteUnlocks::default();
+        let now = Utc::now();
+        for _ in 0..MAX_FAILED_ATTEMPTS {
+            unlocks.start_attempt(&address(1), 1, now).unwrap();
+        }
+        unlocks.attempt_succeeded(&address(1), 1);
+        unlocks.start_attempt(&address(1), 1, now).unwrap();
+        assert_eq!(unlocks.paste_attempts.lock()[&1].count, MAX_FAILED_ATTEMPTS);
+    }
+
+    #[test]
+    fn grants_are_for_one_paste() {
+        let unlocks = PasteUnlocks::default();
+        let (token, _) = unlocks.create_grant(1);
+        assert!(unlocks.has_grant(&token, 1));
+        assert!(!unlocks.has_grant(&token, 2));
+        assert!(!unlocks.has_grant("unknown", 1));
+    }
+
+    #[test]
+    fn expired_grants_are_rejected() {
+        let unlocks = PasteUnlocks::default();
+        unlocks.grants.lock().insert(
+            "expired".to_owned(),
+            Grant {
+                post_id: 1,
+                expires_at: Utc::now() - Duration::seconds(1),
+            },
+        );
+        assert!(!unlocks.has_grant("expired", 1));
+        // Creating a grant drops the expired ones
+        unlocks.create_grant(1);
+        assert!(!unlocks.grants.lock().contains_key("expired"));
+    }
+}
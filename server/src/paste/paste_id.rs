@@ -0,0 +1,193 @@
+//! The string ids of pastes.
+//!
+//! Ids are generated from [PasteIdRules]. Users with the custom slug permission can choose their own. Such as `deploy-runbook`
+use entities::paste::generate_post_paste_id;
+use sea_orm::{DbErr, SqlErr};
+use serde::{Deserialize, Serialize};
+
+use crate::error::WebsiteError;
+
+const ALPHANUMERIC: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+/// Without `0`, `1`, `i`, `l` and `o`
+const READABLE: &[u8] = b"23456789abcdefghjkmnpqrstuvwxyz";
+const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz0123456789";
+/// Shorter ids are too easy to guess
+const MIN_ID_LENGTH: usize = 4;
+const MIN_SLUG_LENGTH: usize = 3;
+const MAX_SLUG_LENGTH: usize = 64;
+/// Slugs that would be routed somewhere else
+const RESERVED_SLUGS: &[&str] = &["languages", "new", "trash"];
+/// The unique constraints on the string ids.
+/// Tables created from the entities name them `{table}_id_str_key`. Older image tables only have the index
+const ID_CONSTRAINTS: &[&str] = &[
+    "paste_posts_id_str_key",
+    "image_posts_id_str_key",
+    "idx-image_posts-id_str",
+];
+
+/// The characters of generated ids
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum PasteIdAlphabet {
+    /// Upper and lowercase letters and digits
+    #[default]
+    Alphanumeric,
+    /// Lowercase letters and digits without characters that are easily confused
+    Readable,
+    /// Lowercase letters and digits
+    Lowercase,
+}
+impl PasteIdAlphabet {
+    pub fn characters(&self) -> &'static [u8] {
+        match self {
+            PasteIdAlphabet::Alphanumeric => ALPHANUMERIC,
+            PasteIdAlphabet::Readable => READABLE,
+            PasteIdAlphabet::Lowercase => LOWERCASE,
+        }
+    }
+}
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PasteIdRules {
+    /// The length of generated ids. At least 4
+    pub length: usize,
+    pub alphabet: PasteIdAlphabet,
+    /// How many ids are generated before creating the paste fails. Each attempt is only made after the previous id was taken
+    pub max_attempts: usize,
+}
+impl Default for PasteIdRules {
+    fn default() -> Self {
+        Self {
+            length: 8,
+            alphabet: PasteIdAlphabet::default(),
+            max_attempts: 10,
+        }
+    }
+}
+impl PasteIdRules {
+    pub fn generate(&self) -> String {
+        generate_post_paste_id(self.length.max(MIN_ID_LENGTH), self.alphabet.characters())
+    }
+}
+
+/// Checks that a custom slug can be used as the id of a paste
+///
+/// Slugs are lowercase letters, digits and `-`. They start and end with a letter or digit
+pub fn validate_slug(slug: &str) -> crate::Result<()> {
+    let valid_length = (MIN_SLUG_LENGTH..=MAX_SLUG_LENGTH).contains(&slug.len());
+    let valid_characters = slug
+        .bytes()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == b'-');
+    let valid_ends = !slug.starts_with('-') && !slug.ends_with('-');
+    if !valid_length || !valid_characters || !valid_ends || RESERVED_SLUGS.contains(&slug) {
+        return Err(WebsiteError::InvalidSlug);
+    }
+    Ok(())
+}
+/// The insert failed because the id is already used by another paste
+///
+/// Other unique violations, such as a duplicate file name, are not retried with a new id
+pub fn is_id_taken(error: &DbErr) -> bool {
+    match error.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(message)) => names_id_constraint(&message),
+        _ => false,
+    }
+}
+/// Postgres reports the violated constraint in quotes
+fn names_id_constraint(message: &str) -> bool {
+    ID_CONSTRAINTS
+        .iter()
+        .any(|name| message.contains(&format!("\"{name}\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::DbErr;
+
+    use super::{
+        is_id_taken, names_id_constraint, validate_slug, PasteIdAlphabet, PasteIdRules,
+        MAX_SLUG_LENGTH, MIN_ID_LENGTH, RESERVED_SLUGS,
+    };
+
+    #[test]
+    fn valid_slugs() {
+        for slug in [
+            "deploy-runbook",
+            "abc",
+            "2023-notes",
+            "a".repeat(MAX_SLUG_LENGTH).as_str(),
+        ] {
+            assert!(validate_slug(slug).is_ok(), "{slug}");
+        }
+    }
+
+    #[test]
+    fn invalid_slugs() {
+        let too_long = "a".repeat(MAX_SLUG_LENGTH + 1);
+        for slug in [
+            "ab",
+            too_long.as_str(),
+            "Deploy",
+            "deploy_runbook",
+            "deploy runbook",
+            "-deploy",
+            "deploy-",
+            "caf\u{e9}",
+            "",
+        ] {
+            assert!(validate_slug(slug).is_err(), "{slug}");
+        }
+    }
+
+    #[test]
+    fn reserved_slugs_are_rejected() {
+        for slug in RESERVED_SLUGS {
+            assert!(validate_slug(slug).is_err(), "{slug}");
+        }
+    }
+
+    #[test]
+    fn generated_ids_use_the_alphabet() {
+        for alphabet in [
+            PasteIdAlphabet::Alphanumeric,
+            PasteIdAlphabet::Readable,
+            PasteIdAlphabet::Lowercase,
+        ] {
+            let rules = PasteIdRules {
+                length: 12,
+                alphabet,
+                ..Default::default()
+            };
+            let id = rules.generate();
+            assert_eq!(id.len(), 12);
+            assert!(
+                id.bytes().all(|c| alphabet.characters().contains(&c)),
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn generated_ids_have_a_minimum_length() {
+        let rules = PasteIdRules {
+            length: 1,
+            ..Default::default()
+        };
+        assert_eq!(rules.generate().len(), MIN_ID_LENGTH);
+    }
+
+    #[test]
+    fn only_id_constraints_are_taken_ids() {
+        assert!(names_id_constraint(
+            r#"duplicate key value violates unique constraint "paste_posts_id_str_key""#
+        ));
+        assert!(names_id_constraint(
+            r#"duplicate key value violates unique constraint "idx-image_posts-id_str""#
+        ));
+        assert!(!names_id_constraint(
+            r#"duplicate key value violates unique constraint "paste_files_post_id_name_key""#
+        ));
+        assert!(!is_id_taken(&DbErr::Custom(
+            "paste_posts_id_str_key".to_owned()
+        )));
+    }
+}
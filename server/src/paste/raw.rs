@@ -1,20 +1,61 @@
 use actix_web::{
-    body::BoxBody,
     get, head,
-    http::header::{CONTENT_LENGTH, DATE},
+    http::header::{
+        CacheControl, CacheDirective, ContentType, CONTENT_SECURITY_POLICY, X_CONTENT_TYPE_OPTIONS,
+    },
     web,
     web::Data,
-    CustomizeResponder, HttpRequest, HttpResponse, Responder,
+    HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use common::{
+    file_location::FileLocation,
+    paste::{encryption::EncryptionEnvelope, file_type::FileType},
+    visibility::HasVisibility,
+};
+use entities::paste::{
+    database_helpers::{find_revision, FileOwnerAndVisibility},
+    HasExpiration,
 };
-use common::{file_location::FileLocation, paste::file_type::FileType};
-use entities::paste::database_helpers::FileOwnerAndVisibility;
 use sea_orm::prelude::*;
+use serde::Deserialize;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    file_response::StoredFile,
+    paste::{
+        delete_post_files,
+        expiration::{burn, check_file},
+        password::PasteAccess,
+        render::{render_response, RenderView},
+        views::ViewTracker,
+        PasteRules,
+    },
+    user::OptionalAuthentication,
+};
+
+/// Nothing served from the raw route may run scripts on this origin. Even if the content type is missed by [ACTIVE_CONTENT_TYPES]
+const RAW_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'unsafe-inline'; sandbox";
+/// Content types that a browser renders as a document. Served as `text/plain` instead
+const ACTIVE_CONTENT_TYPES: &[&str] = &[
+    "text/html",
+    "application/xhtml+xml",
+    "image/svg+xml",
+    "application/xml",
+    "text/xml",
+];
+/// One year. Sent for revisions because their content never changes
+const IMMUTABLE_MAX_AGE: u32 = 31_536_000;
 
 #[utoipa::path(head,
     impl_for = head_file,
     path = "/raw/paste/{id}/file/{file_name}",
+    params(RawFileQuery),
     responses(
-        (status = 200, description = "File Details"),
+        (status = 200, description = "The headers of the file. The same as a GET request. Does not burn the paste"),
+        (status = 304, description = "The file has not changed since the version the client has"),
+        (status = 401, description = "You can not view the paste. Password protected pastes need the `Paste-Password` header or a grant from `/api/paste/{id}/unlock`"),
+        (status = 404, description = "File Not Found or the paste has expired")
     ),
 security(
 (),
@@ -23,36 +64,134 @@ security(
 )]
 #[head("/{id}/file/{file_name}")]
 pub async fn head_file(
-    request: HttpRequest,
     id: web::Path<(String, String)>,
+    query: web::Query<RawFileQuery>,
     database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    rules: Data<PasteRules>,
+    http_request: HttpRequest,
 ) -> crate::Result<HttpResponse> {
     let (id, file_name) = id.into_inner();
-    let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(
+    let resolved = find_file(
         database.as_ref(),
-        id,
+        id.clone(),
         file_name,
+        query.rev,
+        query.format,
+        &auth,
+        &access,
     )
-    .await?
-    .ok_or(crate::Error::NotFound)?;
-
-    let response = HttpResponse::NoContent()
-        .append_header((CONTENT_LENGTH, file.location.file_size()))
-        .finish();
-
-    Ok(prepare_response(
-        &request,
-        response.customize(),
-        file.created,
-        file.file_type,
-    ))
+    .await?;
+    if resolved.file.is_expired() {
+        return Err(crate::Error::NotFound);
+    }
+    if query.format == RawFormat::Html {
+        return render_response(
+            database.as_ref(),
+            id,
+            query.rev,
+            RenderView::Auto,
+            resolved,
+            rules.as_ref(),
+        )
+        .await;
+    }
+    let stored = StoredFile::new(&resolved.location, resolved.created.into()).await?;
+    let response = raw_response(&resolved, query.rev)?;
+    Ok(stored.respond(&http_request, response).await?)
+}
+/// A file that the user is allowed to read
+pub(crate) struct ResolvedFile {
+    pub file: FileOwnerAndVisibility,
+    /// The location of the requested revision
+    pub location: FileLocation,
+    /// How the requested revision was encrypted
+    pub encryption: Option<EncryptionEnvelope>,
+    /// When the requested revision was created. Sent as `Last-Modified`
+    pub created: DateTimeWithTimeZone,
+    /// Reading the file burns the paste. It must be burned with [burn] once the content is opened.
+    /// Then its files must be deleted
+    pub burned: bool,
+}
+/// Finds a file and checks that the user can read it. Including if reading it burns the paste
+///
+/// Encrypted files can not be served as [RawFormat::Html]. They are refused before the paste is burned
+pub(crate) async fn resolve_file(
+    database: &DatabaseConnection,
+    id: String,
+    file_name: String,
+    rev: Option<i32>,
+    format: RawFormat,
+    auth: &OptionalAuthentication,
+    access: &PasteAccess,
+) -> crate::Result<ResolvedFile> {
+    let mut resolved = find_file(database, id, file_name, rev, format, auth, access).await?;
+    resolved.burned = check_file(&resolved.file, auth)?;
+    Ok(resolved)
+}
+/// [resolve_file] without checking if the paste is burned
+async fn find_file(
+    database: &DatabaseConnection,
+    id: String,
+    file_name: String,
+    rev: Option<i32>,
+    format: RawFormat,
+    auth: &OptionalAuthentication,
+    access: &PasteAccess,
+) -> crate::Result<ResolvedFile> {
+    let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(database, id, file_name)
+        .await?
+        .ok_or(crate::Error::NotFound)?;
+    access.check(file.post_id, &file, auth).await?;
+    // Only the client can read the content
+    if format == RawFormat::Html && file.encryption.is_some() {
+        return Err(crate::Error::EncryptedPaste);
+    }
+    // The file row keeps the time the file was created. The latest revision has when the content last changed
+    let (location, encryption, created) = match find_revision(database, file.id, rev).await? {
+        Some(revision) => (revision.location, revision.encryption, revision.created),
+        None if rev.is_none() => (file.location.clone(), file.encryption.clone(), file.created),
+        None => return Err(crate::Error::NotFound),
+    };
+    Ok(ResolvedFile {
+        file,
+        location,
+        encryption,
+        created,
+        burned: false,
+    })
+}
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RawFormat {
+    /// The file as it was uploaded
+    #[default]
+    Raw,
+    /// An HTML page. The same as `/render/paste/{id}/file/{file_name}`. Markdown files are rendered
+    Html,
+}
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RawFileQuery {
+    /// The revision of the file. Defaults to the latest revision
+    pub rev: Option<i32>,
+    #[serde(default)]
+    pub format: RawFormat,
 }
 #[utoipa::path(get,
     impl_for = get_file,
-    path = "/raw/paste/{id}/file/{file_name}", 
+    path = "/raw/paste/{id}/file/{file_name}",
+    params(RawFileQuery),
     responses(
-        (status = 200, content_type = "text/plain", description = "File Contents"),
-        (status = 404, description = "File Not Found")
+        (status = 200, content_type = "text/plain", description = "File Contents. If the paste is burn after read it is deleted after this request"),
+        (status = 200, content_type = "text/html", description = "The file as a highlighted HTML page when `format=html`"),
+        (status = 200, content_type = "application/octet-stream", description = "The ciphertext of an encrypted file. The envelope is sent as `Encryption-Algorithm` and `Encryption-Nonce`"),
+        (status = 400, description = "`format=html` was requested for an encrypted file"),
+        (status = 206, description = "The byte range requested with `Range`"),
+        (status = 304, description = "The file matches `If-None-Match` or has not changed since `If-Modified-Since`"),
+        (status = 401, description = "You can not view the paste. Password protected pastes need the `Paste-Password` header or a grant from `/api/paste/{id}/unlock`"),
+        (status = 404, description = "File Not Found or the paste has expired"),
+        (status = 416, description = "The requested range is outside of the file")
     ),
 security(
 (),
@@ -62,33 +201,61 @@ security(
 #[get("/{id}/file/{file_name}")]
 pub async fn get_file(
     id: web::Path<(String, String)>,
+    query: web::Query<RawFileQuery>,
     database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    views: ViewTracker,
+    rules: Data<PasteRules>,
     http_request: HttpRequest,
 ) -> crate::Result<HttpResponse> {
     let (id, file_name) = id.into_inner();
-
-    let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(
+    let resolved = resolve_file(
         database.as_ref(),
-        id,
+        id.clone(),
         file_name,
+        query.rev,
+        query.format,
+        &auth,
+        &access,
     )
-    .await?
-    .ok_or(crate::Error::NotFound)?;
-    match file.location {
-        FileLocation::Local { location, size: _ } => {
-            let response = actix_files::NamedFile::open_async(location)
-                .await?
-                .customize();
-            Ok(prepare_response(
-                &http_request,
-                response,
-                file.created,
-                file.file_type,
-            ))
+    .await?;
+    // A burned paste no longer exists to have views
+    if !resolved.burned {
+        views.record(
+            resolved.file.post_id,
+            Some(resolved.file.id),
+            &resolved.file,
+            &auth,
+        );
+    }
+    if query.format == RawFormat::Html {
+        return render_response(
+            database.as_ref(),
+            id,
+            query.rev,
+            RenderView::Auto,
+            resolved,
+            rules.as_ref(),
+        )
+        .await;
+    }
+    let stored = StoredFile::new(&resolved.location, resolved.created.into()).await?;
+    let response = raw_response(&resolved, query.rev)?;
+    // The opened file can still be read after it has been removed
+    if resolved.burned {
+        burn(database.as_ref(), resolved.file.post_id).await?;
+    }
+    let response = stored.respond(&http_request, response).await;
+    if resolved.burned {
+        let post_id = resolved.file.post_id;
+        if let Err(e) = delete_post_files(rules.as_ref(), post_id).await {
+            warn!("Failed to delete files for burned paste {post_id}: {e}");
         }
     }
+    Ok(response?)
 }
-/// Prepares a response for a file
+/// The headers of a raw file
 ///
 /// # Headers Added
 /// `Programming-Language` header is added for the purpose of text highlighting. None if not provided. This is not apart of the HTTP standard
@@ -97,16 +264,72 @@ pub async fn get_file(
 ///
 /// `Content-Type` is added based on the type given during upload. Will be `text/plain` if not given
 ///
-/// `Date` is added based on the time the file was uploaded
-fn prepare_response<R: Responder>(
-    request: &HttpRequest,
-    customize: CustomizeResponder<R>,
-    created: DateTimeWithTimeZone,
-    file_type: FileType,
-) -> HttpResponse<BoxBody> {
-    let mut customize = customize.insert_header((DATE, created.to_rfc2822()));
-    for (name, value) in file_type.headers_owned_unchecked() {
-        customize = customize.insert_header((name.expect("Header Name"), value));
+/// `Cache-Control` depends on the visibility of the paste. Revisions never change so they can be cached forever.
+/// Burn after read pastes are never cached
+///
+/// HTML, SVG and XML files are `text/plain`. The `Programming-Language` header still describes them
+///
+/// Encrypted files are `application/octet-stream` with `Encryption-Algorithm` and `Encryption-Nonce` instead
+fn raw_response(resolved: &ResolvedFile, rev: Option<i32>) -> crate::Result<HttpResponseBuilder> {
+    let file = &resolved.file;
+    let mut response = HttpResponse::Ok();
+    response
+        .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+        .insert_header((CONTENT_SECURITY_POLICY, RAW_CONTENT_SECURITY_POLICY))
+        .insert_header(cache_control(file, rev, resolved.burned));
+    let headers = match &resolved.encryption {
+        Some(encryption) => {
+            response.insert_header(ContentType::octet_stream());
+            // The nonce is checked to be base64 when it is uploaded
+            encryption.headers()?
+        }
+        None if is_active_content(&file.file_type.mime_type) => FileType {
+            mime_type: "text/plain".to_owned(),
+            ..file.file_type.clone()
+        }
+        .headers_owned_unchecked(),
+        None => file.file_type.clone().headers_owned_unchecked(),
+    };
+    for (name, value) in headers.iter() {
+        response.insert_header((name.clone(), value.clone()));
+    }
+    Ok(response)
+}
+fn is_active_content(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    ACTIVE_CONTENT_TYPES
+        .iter()
+        .any(|active| active.eq_ignore_ascii_case(essence))
+}
+fn cache_control(file: &FileOwnerAndVisibility, rev: Option<i32>, burned: bool) -> CacheControl {
+    if burned || file.burn_after_read {
+        return CacheControl(vec![CacheDirective::NoStore]);
+    }
+    let scope = if file.requires_auth() {
+        CacheDirective::Private
+    } else {
+        CacheDirective::Public
+    };
+    match rev {
+        Some(_) => CacheControl(vec![
+            scope,
+            CacheDirective::MaxAge(IMMUTABLE_MAX_AGE),
+            CacheDirective::Extension("immutable".to_owned(), None),
+        ]),
+        // The latest revision can change. Clients must check the ETag before using their copy
+        None => CacheControl(vec![scope, CacheDirective::NoCache]),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::is_active_content;
+
+    #[test]
+    fn documents_are_active_content() {
+        assert!(is_active_content("text/html"));
+        assert!(is_active_content("Image/SVG+XML"));
+        assert!(is_active_content("application/xml; charset=utf-8"));
+        assert!(!is_active_content("text/plain"));
+        assert!(!is_active_content("text/javascript"));
     }
-    customize.respond_to(request).map_into_boxed_body()
 }
@@ -0,0 +1,193 @@
+//! Renders markdown files as sanitized HTML.
+//!
+//! Any HTML written in the markdown is passed through [ammonia] so it can not run scripts.
+use std::collections::HashMap;
+
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag};
+use syntect::{
+    highlighting::Theme,
+    html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator},
+    util::LinesWithEndings,
+};
+
+use super::{RenderError, Renderer};
+
+/// Added to every `id` by the sanitizer so user content can not clobber the ids of the page
+const ID_PREFIX: &str = "user-content-";
+const HIGHLIGHT_CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// The sanitizer for rendered markdown.
+///
+/// Starts from the ammonia defaults. Which remove scripts, event handlers, styles and `javascript:` urls
+pub(super) fn sanitizer() -> ammonia::Builder<'static> {
+    let mut builder = ammonia::Builder::default();
+    builder
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["type", "checked"])
+        // Task list items can not be changed
+        .set_tag_attribute_value("input", "disabled", "")
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("pre", &["class"])
+        .add_tag_attributes("a", &["class"])
+        .id_prefix(Some(ID_PREFIX))
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("input", "type") => Some("checkbox".into()),
+            _ => Some(value.into()),
+        });
+    for heading in ["h1", "h2", "h3", "h4", "h5", "h6"] {
+        builder.add_tag_attributes(heading, &["id"]);
+    }
+    builder
+}
+/// The styles for the classes of highlighted code blocks
+pub(super) fn highlight_css(theme: &Theme) -> Result<String, syntect::Error> {
+    css_for_theme_with_class_style(theme, HIGHLIGHT_CLASS_STYLE)
+}
+
+/// A heading that is being collected so its id can be generated from its text
+struct PendingHeading<'a> {
+    level: HeadingLevel,
+    text: String,
+    events: Vec<Event<'a>>,
+}
+/// A fenced or indented code block that is being collected so it can be highlighted
+struct PendingCodeBlock {
+    language: Option<String>,
+    code: String,
+}
+
+impl Renderer {
+    /// Renders markdown with tables, task lists, strikethrough, highlighted code blocks and heading anchors
+    pub(super) fn render_markdown(&self, content: &str) -> Result<String, RenderError> {
+        let options =
+            Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_STRIKETHROUGH;
+        let mut events = Vec::new();
+        let mut heading: Option<PendingHeading> = None;
+        let mut code_block: Option<PendingCodeBlock> = None;
+        let mut heading_ids = HashMap::new();
+        for event in Parser::new_ext(content, options) {
+            if let Some(pending) = code_block.as_mut() {
+                match event {
+                    Event::Text(text) => pending.code.push_str(&text),
+                    Event::End(Tag::CodeBlock(_)) => {
+                        let pending = code_block.take().expect("Code block is pending");
+                        let html = self.highlight_code_block(pending)?;
+                        push_event(&mut heading, &mut events, Event::Html(html.into()));
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            match event {
+                Event::Start(Tag::CodeBlock(kind)) => {
+                    let language = match kind {
+                        CodeBlockKind::Fenced(info) => info
+                            .split_whitespace()
+                            .next()
+                            .map(|language| language.to_owned()),
+                        CodeBlockKind::Indented => None,
+                    };
+                    code_block = Some(PendingCodeBlock {
+                        language,
+                        code: String::new(),
+                    });
+                }
+                Event::Start(Tag::Heading(level, _, _)) => {
+                    heading = Some(PendingHeading {
+                        level,
+                        text: String::new(),
+                        events: Vec::new(),
+                    });
+                }
+                Event::End(Tag::Heading(..)) => {
+                    if let Some(pending) = heading.take() {
+                        push_heading(pending, &mut heading_ids, &mut events);
+                    }
+                }
+                event => {
+                    if let (Some(pending), Event::Text(text) | Event::Code(text)) =
+                        (heading.as_mut(), &event)
+                    {
+                        pending.text.push_str(text);
+                    }
+                    push_event(&mut heading, &mut events, event);
+                }
+            }
+        }
+        let mut output = String::with_capacity(content.len() * 3 / 2);
+        html::push_html(&mut output, events.into_iter());
+        Ok(self.sanitizer.clean(&output).to_string())
+    }
+    fn highlight_code_block(&self, code_block: PendingCodeBlock) -> Result<String, RenderError> {
+        let syntax = code_block
+            .language
+            .as_deref()
+            .and_then(|language| self.find_language_syntax(language))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut generator = ClassedHTMLGenerator::new_with_class_style(
+            syntax,
+            &self.syntaxes,
+            HIGHLIGHT_CLASS_STYLE,
+        );
+        for line in LinesWithEndings::from(&code_block.code) {
+            generator.parse_html_for_line_which_includes_newline(line)?;
+        }
+        Ok(format!(
+            "<pre class=\"highlight\"><code>{}</code></pre>",
+            generator.finalize()
+        ))
+    }
+}
+/// Adds the event to the pending heading if there is one
+fn push_event<'a>(
+    heading: &mut Option<PendingHeading<'a>>,
+    events: &mut Vec<Event<'a>>,
+    event: Event<'a>,
+) {
+    match heading {
+        Some(heading) => heading.events.push(event),
+        None => events.push(event),
+    }
+}
+/// Writes the heading with an id and a link to itself
+fn push_heading<'a>(
+    heading: PendingHeading<'a>,
+    heading_ids: &mut HashMap<String, usize>,
+    events: &mut Vec<Event<'a>>,
+) {
+    let slug = slugify(&heading.text);
+    // Repeated headings get a number added. Such as `usage-1`
+    let id = match heading_ids.get_mut(&slug) {
+        Some(count) => {
+            *count += 1;
+            format!("{slug}-{count}")
+        }
+        None => {
+            heading_ids.insert(slug.clone(), 0);
+            slug
+        }
+    };
+    let level = heading.level;
+    events.push(Event::Html(CowStr::from(format!("<{level} id=\"{id}\">"))));
+    events.extend(heading.events);
+    events.push(Event::Html(CowStr::from(format!(
+        "<a class=\"anchor\" href=\"#{ID_PREFIX}{id}\">#</a></{level}>\n"
+    ))));
+}
+/// Lowercase letters and numbers separated by `-`
+fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
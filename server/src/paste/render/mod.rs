@@ -0,0 +1,357 @@
+//! Renders paste files as standalone HTML pages with syntax highlighting.
+//!
+//! Allows pastes to be viewed and linked to without the frontend
+use std::sync::OnceLock;
+
+use actix_web::{
+    error::BlockingError,
+    get,
+    http::header::{ContentType, CONTENT_SECURITY_POLICY},
+    web,
+    web::Data,
+    HttpResponse,
+};
+use common::paste::{file_type::FileType, language::Language};
+use handlebars::Handlebars;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, Theme, ThemeSet},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+use thiserror::Error;
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    paste::{
+        delete_post_files,
+        expiration::burn,
+        password::PasteAccess,
+        raw::{resolve_file, RawFormat, ResolvedFile},
+        read_to_string, PasteRules,
+    },
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+mod markdown;
+
+/// The syntect theme used for highlighting
+const THEME: &str = "InspiredGitHub";
+const PAGE_TEMPLATE: &str = "paste_file";
+/// Rendered pages never need to run scripts. Even if the sanitizer misses something the browser will not run it
+const CONTENT_SECURITY_POLICY_VALUE: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src * data:; sandbox";
+
+static RENDERER: OnceLock<Renderer> = OnceLock::new();
+
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Highlight(#[from] syntect::Error),
+    #[error(transparent)]
+    Template(#[from] handlebars::RenderError),
+    #[error(transparent)]
+    Blocking(#[from] BlockingError),
+}
+
+/// How a file is displayed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderView {
+    /// Markdown files are rendered. Everything else is shown as highlighted source
+    #[default]
+    Auto,
+    /// Highlighted source with line numbers
+    Source,
+    /// The file rendered as sanitized markdown
+    Markdown,
+}
+impl RenderView {
+    /// Resolves [RenderView::Auto] for the file
+    fn for_file(self, file_type: &FileType) -> Self {
+        match self {
+            RenderView::Auto if is_markdown(file_type) => RenderView::Markdown,
+            RenderView::Auto => RenderView::Source,
+            view => view,
+        }
+    }
+}
+fn is_markdown(file_type: &FileType) -> bool {
+    file_type.mime_type == "text/markdown"
+        || file_type
+            .programming_language
+            .as_deref()
+            .and_then(Language::find)
+            .is_some_and(|language| language.id == "markdown")
+}
+
+/// Holds the syntax definitions, theme and page template.
+///
+/// Loading them is expensive so it is only done once
+struct Renderer {
+    syntaxes: SyntaxSet,
+    theme: Theme,
+    templates: Handlebars<'static>,
+    sanitizer: ammonia::Builder<'static>,
+}
+impl Renderer {
+    fn get() -> &'static Self {
+        RENDERER.get_or_init(|| {
+            let mut templates = Handlebars::new();
+            templates.set_strict_mode(true);
+            templates
+                .register_template_string(
+                    PAGE_TEMPLATE,
+                    include_str!("../../../templates/paste_file.html.hbs"),
+                )
+                .expect("Invalid paste file template");
+            Self {
+                syntaxes: SyntaxSet::load_defaults_newlines(),
+                theme: ThemeSet::load_defaults()
+                    .themes
+                    .remove(THEME)
+                    .expect("Missing default theme"),
+                templates,
+                sanitizer: markdown::sanitizer(),
+            }
+        })
+    }
+    /// Finds the syntax for a programming language. By its name or by the extensions of the language
+    fn find_language_syntax(&self, language: &str) -> Option<&SyntaxReference> {
+        self.syntaxes.find_syntax_by_token(language).or_else(|| {
+            // The ids of detected languages do not always match the syntax names
+            Language::find(language)?
+                .extensions
+                .iter()
+                .find_map(|extension| self.syntaxes.find_syntax_by_extension(extension))
+        })
+    }
+    /// Picks the syntax by the stored programming language. Falling back to the file extension
+    fn find_syntax(&self, file_name: &str, file_type: &FileType) -> &SyntaxReference {
+        file_type
+            .programming_language
+            .as_deref()
+            .and_then(|language| self.find_language_syntax(language))
+            .or_else(|| {
+                file_name
+                    .rsplit_once('.')
+                    .and_then(|(_, extension)| self.syntaxes.find_syntax_by_extension(extension))
+            })
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text())
+    }
+    fn highlight_lines(
+        &self,
+        syntax: &SyntaxReference,
+        content: &str,
+    ) -> Result<Vec<RenderedLine>, RenderError> {
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+        let mut lines = Vec::new();
+        for (index, line) in LinesWithEndings::from(content).enumerate() {
+            let regions: Vec<_> = highlighter
+                .highlight_line(line, &self.syntaxes)?
+                .into_iter()
+                .map(|(style, text)| (style, text.trim_end_matches(['\r', '\n'])))
+                .collect();
+            lines.push(RenderedLine {
+                number: index + 1,
+                html: styled_line_to_highlighted_html(&regions, IncludeBackground::No)?,
+            });
+        }
+        Ok(lines)
+    }
+    fn render(&self, page: RenderFile) -> Result<String, RenderError> {
+        let syntax = self.find_syntax(&page.file_name, &page.file_type);
+        let (lines, markdown, highlight_css) = match page.view {
+            RenderView::Markdown => (
+                Vec::new(),
+                Some(self.render_markdown(&page.content)?),
+                Some(markdown::highlight_css(&self.theme)?),
+            ),
+            _ => (self.highlight_lines(syntax, &page.content)?, None, None),
+        };
+        let settings = &self.theme.settings;
+        let context = PageContext {
+            paste_id: &page.paste_id,
+            file_name: &page.file_name,
+            language: &syntax.name,
+            raw_url: page.raw_url.as_deref(),
+            alternate_view: page.alternate_view,
+            background: css_color(settings.background.unwrap_or(Color::WHITE)),
+            foreground: css_color(settings.foreground.unwrap_or(Color::BLACK)),
+            lines,
+            markdown,
+            highlight_css,
+        };
+        Ok(self.templates.render(PAGE_TEMPLATE, &context)?)
+    }
+}
+fn css_color(color: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+}
+
+/// A file waiting to be rendered
+struct RenderFile {
+    paste_id: String,
+    file_name: String,
+    file_type: FileType,
+    content: String,
+    /// Never [RenderView::Auto]
+    view: RenderView,
+    /// None if the file can no longer be downloaded
+    raw_url: Option<String>,
+    alternate_view: Option<AlternateView>,
+}
+/// A link to the other view of a markdown file
+#[derive(Serialize)]
+struct AlternateView {
+    label: &'static str,
+    url: String,
+}
+#[derive(Serialize)]
+struct RenderedLine {
+    number: usize,
+    /// The highlighted content of the line. Already escaped
+    html: String,
+}
+#[derive(Serialize)]
+struct PageContext<'a> {
+    paste_id: &'a str,
+    file_name: &'a str,
+    language: &'a str,
+    raw_url: Option<&'a str>,
+    alternate_view: Option<AlternateView>,
+    background: String,
+    foreground: String,
+    /// Empty when the file is rendered as markdown
+    lines: Vec<RenderedLine>,
+    /// The sanitized HTML of a markdown file
+    markdown: Option<String>,
+    /// Styles for the classes of highlighted code blocks in markdown
+    highlight_css: Option<String>,
+}
+
+/// Responds with the file rendered as an HTML page.
+///
+/// Source lines are anchored as `#L{line}`. Markdown headings as `#user-content-{heading}`.
+/// Highlighting is done on the blocking thread pool
+pub(crate) async fn render_response(
+    database: &DatabaseConnection,
+    paste_id: String,
+    rev: Option<i32>,
+    view: RenderView,
+    resolved: ResolvedFile,
+    rules: &PasteRules,
+) -> crate::Result<HttpResponse> {
+    let ResolvedFile {
+        file,
+        location,
+        burned,
+        ..
+    } = resolved;
+    // The paste is only burned once the content has been read
+    let content = read_to_string(&location).await?;
+    if burned {
+        burn(database, file.post_id).await?;
+        if let Err(e) = delete_post_files(rules, file.post_id).await {
+            warn!(
+                "Failed to delete files for burned paste {}: {e}",
+                file.post_id
+            );
+        }
+    }
+    let rev_query = rev.map(|rev| format!("rev={rev}"));
+    // File names can contain any character other than `/`
+    let encoded_name = utf8_percent_encode(&file.file_name, NON_ALPHANUMERIC).to_string();
+    let raw_url = (!burned).then(|| match &rev_query {
+        Some(rev_query) => format!("/raw/paste/{paste_id}/file/{encoded_name}?{rev_query}"),
+        None => format!("/raw/paste/{paste_id}/file/{encoded_name}"),
+    });
+    let view = view.for_file(&file.file_type);
+    let alternate_view = (!burned && is_markdown(&file.file_type)).then(|| {
+        let (label, view) = match view {
+            RenderView::Markdown => ("Source", "source"),
+            _ => ("Rendered", "markdown"),
+        };
+        let query = match &rev_query {
+            Some(rev_query) => format!("view={view}&{rev_query}"),
+            None => format!("view={view}"),
+        };
+        AlternateView {
+            label,
+            url: format!("/render/paste/{paste_id}/file/{encoded_name}?{query}"),
+        }
+    });
+    let page = RenderFile {
+        paste_id,
+        file_name: file.file_name,
+        file_type: file.file_type,
+        content,
+        view,
+        raw_url,
+        alternate_view,
+    };
+    let html = web::block(move || Renderer::get().render(page))
+        .await
+        .map_err(RenderError::from)??;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .insert_header((CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_VALUE))
+        .body(html))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct RenderFileQuery {
+    /// The revision of the file. Defaults to the latest revision
+    pub rev: Option<i32>,
+    #[serde(default)]
+    pub view: RenderView,
+}
+#[utoipa::path(get,
+    impl_for = render_file,
+    path = "/render/paste/{id}/file/{file_name}",
+    params(RenderFileQuery),
+    responses(
+        (status = 200, content_type = "text/html", description = "The file as a highlighted HTML page. Markdown files are rendered unless `view=source`. If the paste is burn after read it is deleted after this request"),
+        (status = 400, description = "The paste is encrypted. Only the client can read it"),
+        (status = 404, description = "File Not Found or the paste has expired")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/file/{file_name}")]
+pub async fn render_file(
+    id: web::Path<(String, String)>,
+    query: web::Query<RenderFileQuery>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+    rules: Data<PasteRules>,
+) -> crate::Result<HttpResponse> {
+    let (id, file_name) = id.into_inner();
+    let resolved = resolve_file(
+        database.as_ref(),
+        id.clone(),
+        file_name,
+        query.rev,
+        RawFormat::Html,
+        &auth,
+        &access,
+    )
+    .await?;
+    render_response(
+        database.as_ref(),
+        id,
+        query.rev,
+        query.view,
+        resolved,
+        rules.as_ref(),
+    )
+    .await
+}
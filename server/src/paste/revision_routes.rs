@@ -0,0 +1,192 @@
+use std::io::ErrorKind;
+
+use actix_web::{get, web, web::Data, HttpResponse};
+use common::paste::encryption::EncryptionEnvelope;
+use digestible::Digestible;
+use entities::{
+    paste::database_helpers::{
+        find_revision, get_revisions as find_revisions, FileOwnerAndVisibility,
+    },
+    PasteFileRevisionModel,
+};
+use helper_macros::Response;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    paste::{password::PasteAccess, read_to_string},
+    responses::JsonResponse,
+    user::OptionalAuthentication,
+    DatabaseConnection,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible)]
+pub struct PasteFileRevision {
+    pub revision: i32,
+    pub size: i64,
+    /// The user that uploaded the revision. None if the user has been deleted
+    pub user_id: Option<i64>,
+    /// How the revision was encrypted. None if the paste is not encrypted
+    #[schema(nullable)]
+    pub encryption: Option<EncryptionEnvelope>,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub created: DateTimeWithTimeZone,
+}
+impl From<PasteFileRevisionModel> for PasteFileRevision {
+    fn from(model: PasteFileRevisionModel) -> Self {
+        Self {
+            revision: model.revision,
+            size: model.size,
+            user_id: model.user_id,
+            encryption: model.encryption,
+            created: model.created,
+        }
+    }
+}
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+pub struct PasteFileRevisions {
+    pub file_name: String,
+    /// Oldest revision first
+    pub revisions: Vec<PasteFileRevision>,
+}
+
+#[utoipa::path(get,
+    impl_for = get_revisions,
+    path = "/api/paste/{id}/file/{file_name}/revisions",
+    params(
+        ("id", description = "The id of the paste"),
+        ("file_name", description = "The name of the file")
+    ),
+    responses(
+        (status = 200, description = "The revisions of the file", body = PasteFileRevisions),
+        (status = 404, description = "File Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/file/{file_name}/revisions")]
+pub async fn get_revisions(
+    path: web::Path<(String, String)>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<JsonResponse<PasteFileRevisions>> {
+    let (id, file_name) = path.into_inner();
+    let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(
+        database.as_ref(),
+        id,
+        file_name,
+    )
+    .await?
+    .ok_or(crate::Error::NotFound)?;
+    access.check(file.post_id, &file, &auth).await?;
+
+    let revisions = find_revisions(database.as_ref(), file.id)
+        .await?
+        .into_iter()
+        .map(PasteFileRevision::from)
+        .collect();
+    Ok(JsonResponse::from(PasteFileRevisions {
+        file_name: file.file_name,
+        revisions,
+    }))
+}
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DiffQuery {
+    /// The old revision. Defaults to the revision before `to`.
+    /// The first revision is compared against an empty file
+    pub from: Option<i32>,
+    /// The new revision. Defaults to the latest revision
+    pub to: Option<i32>,
+}
+
+#[utoipa::path(get,
+    impl_for = diff,
+    path = "/api/paste/{id}/file/{file_name}/diff",
+    params(
+        ("id", description = "The id of the paste"),
+        ("file_name", description = "The name of the file"),
+        DiffQuery
+    ),
+    responses(
+        (status = 200, content_type = "text/x-diff", description = "Unified diff between the two revisions"),
+        (status = 400, description = "The paste is encrypted or a revision is not valid UTF-8"),
+        (status = 404, description = "File or Revision Not Found")
+    ),
+security(
+(),
+("api_key" = [])
+)
+)]
+#[get("/{id}/file/{file_name}/diff")]
+pub async fn diff(
+    path: web::Path<(String, String)>,
+    query: web::Query<DiffQuery>,
+    database: Data<DatabaseConnection>,
+    auth: OptionalAuthentication,
+    access: PasteAccess,
+) -> crate::Result<HttpResponse> {
+    let (id, file_name) = path.into_inner();
+    let file = FileOwnerAndVisibility::get_file_by_string_id_and_file_name(
+        database.as_ref(),
+        id,
+        file_name,
+    )
+    .await?
+    .ok_or(crate::Error::NotFound)?;
+    access.check(file.post_id, &file, &auth).await?;
+    if file.encryption.is_some() {
+        return Err(crate::Error::EncryptedPaste);
+    }
+    let DiffQuery { from, to } = query.into_inner();
+
+    let to = find_revision(database.as_ref(), file.id, to)
+        .await?
+        .ok_or(crate::Error::NotFound)?;
+    let from = match from {
+        Some(from) => Some(from),
+        None if to.revision > 1 => Some(to.revision - 1),
+        None => None,
+    };
+    let from = match from {
+        Some(from) => Some(
+            find_revision(database.as_ref(), file.id, Some(from))
+                .await?
+                .ok_or(crate::Error::NotFound)?,
+        ),
+        None => None,
+    };
+
+    let old = match &from {
+        Some(from) => read_revision(from).await?,
+        None => String::new(),
+    };
+    let new = read_revision(&to).await?;
+    let old_header = match &from {
+        Some(from) => format!("{}@{}", file.file_name, from.revision),
+        None => "/dev/null".to_owned(),
+    };
+    let diff = TextDiff::from_lines(&old, &new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&old_header, &format!("{}@{}", file.file_name, to.revision))
+        .to_string();
+    Ok(HttpResponse::Ok()
+        .content_type("text/x-diff; charset=utf-8")
+        .body(diff))
+}
+/// Reads a revision as text. Binary revisions can not be diffed
+async fn read_revision(revision: &PasteFileRevisionModel) -> crate::Result<String> {
+    read_to_string(&revision.location)
+        .await
+        .map_err(|error| match error.kind() {
+            ErrorKind::InvalidData => crate::Error::RevisionNotUtf8(revision.revision),
+            _ => crate::Error::IoError(error),
+        })
+}
@@ -0,0 +1,141 @@
+//! Deleted pastes.
+//!
+//! Deleting a paste moves it to the trash. The owner can restore it until it is purged [PasteRules::trash_retention] after it was deleted
+use actix_web::{get, post, web, web::Data};
+use chrono::Utc;
+use digestible::Digestible;
+use entities::{
+    paste::{
+        database_helpers::{
+            find_deleted_post, find_posts_deleted_before, get_trash, set_post_deleted,
+        },
+        Paste, PostColumn,
+    },
+    PastePostEntity,
+};
+use helper_macros::Response;
+use sea_orm::prelude::*;
+use serde::Serialize;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::{
+    error::WebsiteError,
+    paste::{delete_post_files, PasteRules},
+    policy::{can_delete, Actor, Resource},
+    responses::JsonResponse,
+    user::Authentication,
+    DatabaseConnection,
+};
+
+#[derive(Debug, Serialize, ToSchema, Digestible)]
+pub struct TrashedPaste {
+    pub id: i64,
+    pub id_str: String,
+    pub name: String,
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub deleted_at: DateTimeWithTimeZone,
+    /// When the paste will be deleted permanently
+    #[schema(value_type = DateTime)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub purged_at: DateTimeWithTimeZone,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible, Response)]
+#[private]
+pub struct PasteTrash {
+    /// Most recently deleted first
+    pub pastes: Vec<TrashedPaste>,
+}
+
+/// The deleted pastes of the user
+#[utoipa::path(get,
+    impl_for = get_paste_trash,
+    path = "/api/paste/trash",
+    responses(
+        (status = 200, description = "The pastes in the trash", body = PasteTrash),
+        (status = 401, description = "You are not logged in")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[get("/trash")]
+pub async fn get_paste_trash(
+    auth: Authentication,
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+) -> crate::Result<JsonResponse<PasteTrash>> {
+    let retention = rules.trash_retention.duration;
+    let pastes = get_trash(database.as_ref(), auth.id())
+        .await?
+        .into_iter()
+        .filter_map(|post| {
+            let deleted_at = post.deleted_at?;
+            Some(TrashedPaste {
+                id: post.id,
+                id_str: post.id_str,
+                name: post.name,
+                deleted_at,
+                purged_at: deleted_at + retention,
+            })
+        })
+        .collect();
+    Ok(JsonResponse::from(PasteTrash { pastes }))
+}
+
+/// Moves a paste out of the trash
+#[utoipa::path(post,
+    impl_for = restore,
+    path = "/api/paste/{id}/restore",
+    params(
+        ("id", description = "The numeric id of the paste")
+    ),
+    responses(
+        (status = 200, description = "The restored paste", body = Paste),
+        (status = 403, description = "You can not restore the paste"),
+        (status = 404, description = "The paste is not in the trash")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[post("/{id}/restore")]
+pub async fn restore(
+    auth: Authentication,
+    id: web::Path<i64>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<Paste>> {
+    let post = find_deleted_post(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if !can_delete(Resource::Paste, &Actor::from(&auth), &post) {
+        return Err(WebsiteError::Forbidden);
+    }
+    set_post_deleted(database.as_ref(), post.id, None).await?;
+    Paste::get_by_id(database.as_ref(), post.id, true)
+        .await?
+        .ok_or(WebsiteError::NotFound)
+        .map(JsonResponse::from)
+}
+
+/// Permanently deletes the pastes that have been in the trash longer than [PasteRules::trash_retention]. Along with their files
+pub async fn purge_trash(database: &DatabaseConnection, rules: &PasteRules) -> Result<u64, DbErr> {
+    let deleted_before = Utc::now() - rules.trash_retention.duration;
+    let purged = find_posts_deleted_before(database, deleted_before.into()).await?;
+    if purged.is_empty() {
+        return Ok(0);
+    }
+    let result = PastePostEntity::delete_many()
+        .filter(PostColumn::Id.is_in(purged.clone()))
+        .exec(database)
+        .await?;
+    for post_id in purged {
+        if let Err(e) = delete_post_files(rules, post_id).await {
+            warn!("Failed to delete files for purged paste {post_id}: {e}");
+        }
+    }
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,754 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{patch, put, web, web::Data};
+use common::{
+    paste::{encryption::EncryptionEnvelope, file_name::normalize_file_name, file_type::FileType},
+    visibility::Visibility,
+};
+use entities::{
+    paste::{
+        database_helpers::{
+            create_revision, find_file_by_name, find_post_by_id, get_files, get_paste_size,
+            lock_file, next_revision, update_last_updated,
+        },
+        Paste,
+    },
+    user::{database_helpers::get_storage_usage, user_responses::User},
+    PasteFileActiveModel, PasteFileModel, PastePostActiveModel, PastePostModel,
+};
+use sea_orm::{prelude::*, ActiveValue::Set, SqlErr, TransactionTrait};
+use serde::{Deserialize, Serialize};
+use utoipa::{
+    openapi::{
+        AllOfBuilder, KnownFormat, ObjectBuilder, Ref, RefOr, Schema, SchemaFormat, SchemaType,
+    },
+    ToSchema,
+};
+
+use crate::{
+    blob::{BlobStore, StagedBlob},
+    config::SiteRules,
+    error::WebsiteError,
+    paste::{check_encryption, check_paste_name, password::hash_visibility, PasteRules},
+    policy::{can_edit, Actor, Resource},
+    responses::JsonResponse,
+    upload::{MultipartLimits, MultipartUpload},
+    user::{quota::UploadBudget, Authentication},
+    DatabaseConnection,
+};
+
+/// Changes to the metadata of a paste. Fields that are not set are left unchanged
+#[derive(Deserialize, Serialize, Default, Debug, ToSchema)]
+#[serde(default)]
+pub struct UpdatePaste {
+    #[schema(nullable)]
+    pub name: Option<String>,
+    #[schema(nullable)]
+    pub description: Option<String>,
+    #[schema(nullable)]
+    pub tags: Option<Vec<String>>,
+    #[schema(nullable)]
+    pub visibility: Option<Visibility>,
+}
+/// Changes to the metadata of a file. Fields that are not set are left unchanged
+#[derive(Deserialize, Serialize, Default, Debug, ToSchema)]
+#[serde(default)]
+pub struct UpdateFile {
+    #[schema(nullable)]
+    pub file_name: Option<String>,
+    #[schema(nullable)]
+    pub file_type: Option<FileType>,
+    /// The index the file is moved to. The other files keep their order around it.
+    /// Negative positions are the start and positions past the last file are the end
+    #[schema(nullable)]
+    pub position: Option<i32>,
+}
+/// The new order of the files in a paste.
+///
+/// Files that are not listed are placed after the listed files keeping their current order
+#[derive(Deserialize, Serialize, Default, Debug, ToSchema)]
+pub struct ReorderFiles {
+    pub files: Vec<String>,
+}
+/// The multipart request used to replace a file. Only used for the OpenAPI schema
+///
+/// Files in encrypted pastes must send `encryption` before `file`.
+/// The envelope is limited to [SiteRules::max_payload]
+pub struct ReplaceFile;
+impl<'a> ToSchema<'a> for ReplaceFile {
+    fn schema() -> (&'a str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .property(
+                "encryption",
+                AllOfBuilder::new()
+                    .nullable(true)
+                    .item(Ref::from_schema_name("EncryptionEnvelope")),
+            )
+            .property(
+                "file",
+                ObjectBuilder::new()
+                    .schema_type(SchemaType::String)
+                    .format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary))),
+            )
+            .into();
+        ("ReplaceFile", RefOr::T(schema))
+    }
+}
+/// Finds the post and checks that the user can edit it
+async fn find_owned_post(
+    database: &DatabaseConnection,
+    id: i64,
+    auth: &Authentication,
+) -> crate::Result<PastePostModel> {
+    let post = find_post_by_id(database, id)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if !can_edit(Resource::Paste, &Actor::from(auth), &post) {
+        return Err(WebsiteError::Forbidden);
+    }
+    Ok(post)
+}
+async fn paste_response(
+    database: &DatabaseConnection,
+    id: i64,
+) -> crate::Result<JsonResponse<Paste>> {
+    Paste::get_by_id(database, id, true)
+        .await?
+        .ok_or(WebsiteError::NotFound)
+        .map(JsonResponse::from)
+}
+
+#[utoipa::path(patch,
+    impl_for = update,
+    path = "/api/paste/{id}",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    request_body (content = UpdatePaste, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated paste", body = Paste),
+        (status = 400, description = "The name is empty or the visibility is not valid"),
+        (status = 403, description = "You do not own this paste"),
+        (status = 404, description = "Paste Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[patch("/{id}")]
+pub async fn update(
+    auth: Authentication,
+    path: web::Path<i64>,
+    update: web::Json<UpdatePaste>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<Paste>> {
+    let post = find_owned_post(database.as_ref(), path.into_inner(), &auth).await?;
+    let UpdatePaste {
+        name,
+        description,
+        tags,
+        visibility,
+    } = update.into_inner();
+
+    let id = post.id;
+    let mut post: PastePostActiveModel = post.into();
+    if let Some(name) = name {
+        check_paste_name(&name)?;
+        post.name = Set(name);
+    }
+    if let Some(description) = description {
+        post.description = Set(description);
+    }
+    if let Some(tags) = tags {
+        post.tags = Set(tags);
+    }
+    if let Some(visibility) = visibility {
+        post.visibility = Set(hash_visibility(visibility)?);
+    }
+    post.last_updated = Set(chrono::Utc::now().into());
+    post.update(database.as_ref()).await?;
+
+    paste_response(database.as_ref(), id).await
+}
+
+/// Moves the item at `index` to `position`. Negative positions are the start and positions past the end are the end
+fn move_to<T>(items: &mut Vec<T>, index: usize, position: i32) {
+    let item = items.remove(index);
+    let position = (position.max(0) as usize).min(items.len());
+    items.insert(position, item);
+}
+/// Numbers the files from 0 in the order given. Only the files whose position changed are updated
+async fn write_positions(
+    connection: &impl ConnectionTrait,
+    files: Vec<PasteFileModel>,
+) -> Result<(), DbErr> {
+    for (position, file) in files.into_iter().enumerate() {
+        let position = position as i32;
+        if file.position == position {
+            continue;
+        }
+        let mut file: PasteFileActiveModel = file.into();
+        file.position = Set(position);
+        file.update(connection).await?;
+    }
+    Ok(())
+}
+/// Maps the unique index on the names of the files in a paste to [WebsiteError::FileAlreadyExists]
+fn file_name_taken(error: DbErr) -> WebsiteError {
+    if matches!(error.sql_err(), Some(SqlErr::UniqueConstraintViolation(_))) {
+        WebsiteError::FileAlreadyExists
+    } else {
+        error.into()
+    }
+}
+
+#[utoipa::path(patch,
+    impl_for = update_file,
+    path = "/api/paste/{id}/file/{file_name}",
+    params(
+        ("id", description = "The id of the paste"),
+        ("file_name", description = "The current name of the file")
+    ),
+    request_body (content = UpdateFile, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated paste", body = Paste),
+        (status = 400, description = "Invalid File Type"),
+        (status = 403, description = "You do not own this paste"),
+        (status = 404, description = "File Not Found"),
+        (status = 409, description = "A file with the new name already exists")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[patch("/{id}/file/{file_name}")]
+pub async fn update_file(
+    auth: Authentication,
+    path: web::Path<(i64, String)>,
+    update: web::Json<UpdateFile>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<Paste>> {
+    let (id, file_name) = path.into_inner();
+    let post = find_owned_post(database.as_ref(), id, &auth).await?;
+    let file = find_file_by_name(database.as_ref(), post.id, &file_name)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    let UpdateFile {
+        file_name: new_file_name,
+        file_type,
+        position,
+    } = update.into_inner();
+
+    let file_id = file.id;
+    let mut file: PasteFileActiveModel = file.into();
+    if let Some(new_file_name) = new_file_name {
+        let new_file_name = normalize_file_name(&new_file_name)?;
+        if new_file_name != file_name {
+            if find_file_by_name(database.as_ref(), post.id, &new_file_name)
+                .await?
+                .is_some()
+            {
+                return Err(WebsiteError::FileAlreadyExists);
+            }
+            file.file_name = Set(new_file_name);
+        }
+    }
+    if let Some(file_type) = file_type {
+        if !file_type.check() {
+            return Err(WebsiteError::InvalidFileType);
+        }
+        file.file_type = Set(file_type);
+    }
+    let transaction = database.begin().await?;
+    if file.is_changed() {
+        // Another file could have been renamed to the same name since the check
+        file.update(&transaction).await.map_err(file_name_taken)?;
+    }
+    if let Some(position) = position {
+        let mut files = get_files(&transaction, post.id).await?;
+        let index = files
+            .iter()
+            .position(|file| file.id == file_id)
+            .ok_or(WebsiteError::NotFound)?;
+        move_to(&mut files, index, position);
+        write_positions(&transaction, files).await?;
+    }
+    update_last_updated(&transaction, post.id).await?;
+    transaction.commit().await?;
+
+    paste_response(database.as_ref(), post.id).await
+}
+
+/// The limits of a file replacement. One file and an encryption envelope no larger than [SiteRules::max_payload]
+fn replace_limits(rules: &PasteRules, site_rules: &SiteRules) -> MultipartLimits {
+    let mut limits = rules.multipart_limits(site_rules.max_payload.get_as_bytes());
+    limits.max_files = 1;
+    limits.max_request_size = limits.max_file_size.saturating_add(limits.max_field_size);
+    limits
+}
+/// Reads the fields of a [ReplaceFile] request up to the file. The content of the file is left unread
+async fn read_replacement(
+    upload: &mut MultipartUpload,
+) -> crate::Result<(Option<EncryptionEnvelope>, Field)> {
+    let mut envelope = None;
+    loop {
+        match upload.next_field().await? {
+            Some(mut field) if field.name() == "encryption" && envelope.is_none() => {
+                envelope = Some(upload.read_json(&mut field).await?);
+            }
+            Some(field) if field.name() == "file" => return Ok((envelope, field)),
+            Some(field) => return Err(WebsiteError::UnexpectedField(field.name().to_owned())),
+            None => return Err(WebsiteError::MissingField("file")),
+        }
+    }
+}
+/// Points the file at the staged content and records it as a new revision. Then moves the content into the blob store
+///
+/// The file is locked while the revision number is chosen. So concurrent replacements get consecutive revisions
+async fn store_replacement(
+    database: &DatabaseConnection,
+    post_id: i64,
+    file: PasteFileModel,
+    content: StagedBlob,
+    encryption: Option<EncryptionEnvelope>,
+    author: i64,
+    budget: &UploadBudget,
+) -> crate::Result<()> {
+    let location = content.location();
+    let result: crate::Result<()> = async {
+        let transaction = database.begin().await?;
+        let file_id = file.id;
+        lock_file(&transaction, file_id).await?;
+        let revision = next_revision(&transaction, file_id).await?;
+        let mut file: PasteFileActiveModel = file.into();
+        file.location = Set(location.clone());
+        file.encryption = Set(encryption.clone());
+        file.update(&transaction).await?;
+        create_revision(
+            &transaction,
+            file_id,
+            revision,
+            location,
+            encryption,
+            author,
+        )
+        .await?;
+        update_last_updated(&transaction, post_id).await?;
+        budget.recheck(&transaction).await?;
+        transaction.commit().await?;
+        Ok(())
+    }
+    .await;
+    match result {
+        Ok(()) => Ok(content.commit().await?),
+        Err(e) => {
+            content.discard().await;
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(put,
+    impl_for = replace_file,
+    path = "/api/paste/{id}/file/{file_name}",
+    params(
+        ("id", description = "The id of the paste"),
+        ("file_name", description = "The name of the file")
+    ),
+    request_body (content = ReplaceFile, content_type = "multipart/form-data"),
+    responses(
+        (status = 200, description = "The updated paste. The new content is stored as a new revision", body = Paste),
+        (status = 400, description = "The new content is not valid or the paste would exceed the maximum paste size"),
+        (status = 403, description = "You do not own this paste, file updates are disabled or your storage quota is exceeded"),
+        (status = 404, description = "File Not Found"),
+        (status = 413, description = "The new content exceeds the maximum file size")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[put("/{id}/file/{file_name}")]
+pub async fn replace_file(
+    auth: Authentication,
+    path: web::Path<(i64, String)>,
+    payload: Multipart,
+    database: Data<DatabaseConnection>,
+    rules: Data<PasteRules>,
+    site_rules: Data<SiteRules>,
+    blobs: Data<BlobStore>,
+) -> crate::Result<JsonResponse<Paste>> {
+    if !rules.allow_file_updates {
+        return Err(WebsiteError::Forbidden);
+    }
+    let (id, file_name) = path.into_inner();
+    let post = find_owned_post(database.as_ref(), id, &auth).await?;
+    let file = find_file_by_name(database.as_ref(), post.id, &file_name)
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+
+    // The replaced content no longer counts towards the paste size. It still counts towards the quota
+    let user: &User = auth.as_ref();
+    let usage = get_storage_usage(database.as_ref(), user.id).await?;
+    let paste_size = get_paste_size(database.as_ref(), post.id)
+        .await?
+        .saturating_sub(file.location.file_size() as u64);
+    let budget = UploadBudget::for_paste(
+        rules.as_ref(),
+        site_rules.quota_for(&user.permissions),
+        user.id,
+        &usage,
+        paste_size,
+    );
+    let mut upload =
+        MultipartUpload::new(payload, replace_limits(rules.as_ref(), site_rules.as_ref()));
+    let (envelope, mut field) = read_replacement(&mut upload).await?;
+    // A new nonce is required for every revision
+    let encryption = check_encryption(post.encrypted, envelope)?;
+
+    // Previous revisions keep their content. So the new content is a new blob
+    let content = upload
+        .save_file(&mut field, blobs.as_ref(), post.encrypted, |size| {
+            budget.check(size as u64)
+        })
+        .await?;
+
+    store_replacement(
+        database.as_ref(),
+        post.id,
+        file,
+        content,
+        encryption,
+        auth.id(),
+        &budget,
+    )
+    .await?;
+
+    paste_response(database.as_ref(), post.id).await
+}
+
+#[utoipa::path(put,
+    impl_for = reorder_files,
+    path = "/api/paste/{id}/order",
+    params(
+        ("id", description = "The id of the paste")
+    ),
+    request_body (content = ReorderFiles, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated paste", body = Paste),
+        (status = 403, description = "You do not own this paste"),
+        (status = 404, description = "Paste or one of the files was not found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[put("/{id}/order")]
+pub async fn reorder_files(
+    auth: Authentication,
+    path: web::Path<i64>,
+    order: web::Json<ReorderFiles>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<Paste>> {
+    let post = find_owned_post(database.as_ref(), path.into_inner(), &auth).await?;
+    let ReorderFiles { files: order } = order.into_inner();
+
+    let mut files = get_files(database.as_ref(), post.id).await?;
+    let mut ordered = Vec::with_capacity(files.len());
+    for file_name in order {
+        let index = files
+            .iter()
+            .position(|file| file.file_name == file_name)
+            .ok_or(WebsiteError::NotFound)?;
+        ordered.push(files.remove(index));
+    }
+    // Any files not listed keep their current order after the listed files
+    ordered.append(&mut files);
+
+    let transaction = database.begin().await?;
+    write_positions(&transaction, ordered).await?;
+    update_last_updated(&transaction, post.id).await?;
+    transaction.commit().await?;
+
+    paste_response(database.as_ref(), post.id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_multipart::Multipart;
+    use actix_web::{
+        error::PayloadError,
+        http::header::{HeaderMap, HeaderValue, CONTENT_TYPE},
+        web::Bytes,
+    };
+    use common::{
+        file_location::FileLocation,
+        paste::encryption::{EncryptionAlgorithm, EncryptionEnvelope, NONCE_LENGTH},
+    };
+    use config_types::size_config::ConfigSize;
+    use entities::{
+        paste::database_helpers::{find_file_by_name, find_revision, get_revisions},
+        user::ANONYMOUS_USER_ID,
+        PasteFileActiveModel, PastePostActiveModel, PastePostEntity,
+    };
+    use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait};
+
+    use super::{read_replacement, replace_limits, store_replacement};
+    use crate::{
+        blob::BlobStore,
+        config::SiteRules,
+        error::WebsiteError,
+        paste::{check_encryption, check_paste_name, PasteRules},
+        test_utils,
+        upload::MultipartUpload,
+        user::quota::UploadBudget,
+        utils::base64_utils,
+    };
+
+    const BOUNDARY: &str = "replace-file-boundary";
+
+    /// A multipart request with the fields in order. Each field is a name, a file name and its content
+    fn multipart(fields: &[(&str, Option<&str>, &[u8])]) -> Multipart {
+        let mut body = Vec::new();
+        for (name, file_name, content) in fields {
+            let disposition = match file_name {
+                Some(file_name) => format!("form-data; name=\"{name}\"; filename=\"{file_name}\""),
+                None => format!("form-data; name=\"{name}\""),
+            };
+            body.extend_from_slice(
+                format!("--{BOUNDARY}\r\nContent-Disposition: {disposition}\r\n\r\n").as_bytes(),
+            );
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{BOUNDARY}--\r\n").as_bytes());
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(&format!("multipart/form-data; boundary={BOUNDARY}")).unwrap(),
+        );
+        Multipart::new(
+            &headers,
+            futures_util::stream::iter([Ok::<_, PayloadError>(Bytes::from(body))]),
+        )
+    }
+    fn envelope() -> EncryptionEnvelope {
+        EncryptionEnvelope {
+            algorithm: EncryptionAlgorithm::Aes256Gcm,
+            nonce: base64_utils::encode([7u8; NONCE_LENGTH]),
+        }
+    }
+    fn upload(fields: &[(&str, Option<&str>, &[u8])]) -> MultipartUpload {
+        MultipartUpload::new(
+            multipart(fields),
+            replace_limits(&PasteRules::default(), &SiteRules::default()),
+        )
+    }
+
+    #[actix_web::test]
+    async fn envelope_is_read_before_the_file() {
+        let envelope_json = serde_json::to_vec(&envelope()).unwrap();
+        let mut upload = upload(&[
+            ("encryption", None, &envelope_json),
+            ("file", Some("secret.txt"), &[0xFF, 0x00, 0x13]),
+        ]);
+        let (envelope, field) = read_replacement(&mut upload).await.unwrap();
+        assert_eq!(field.name(), "file");
+        assert_eq!(
+            check_encryption(true, envelope).unwrap(),
+            Some(self::envelope())
+        );
+    }
+
+    #[actix_web::test]
+    async fn file_is_required() {
+        let envelope_json = serde_json::to_vec(&envelope()).unwrap();
+        let mut upload = upload(&[("encryption", None, &envelope_json)]);
+        assert!(matches!(
+            read_replacement(&mut upload).await,
+            Err(WebsiteError::MissingField("file"))
+        ));
+    }
+
+    #[actix_web::test]
+    async fn envelope_larger_than_the_payload_limit_is_rejected() {
+        let mut site_rules = SiteRules::default();
+        site_rules.max_payload = ConfigSize::new_from_kibibytes(1);
+        let padding = vec![b' '; 2048];
+        let mut upload = MultipartUpload::new(
+            multipart(&[("encryption", None, &padding)]),
+            replace_limits(&PasteRules::default(), &site_rules),
+        );
+        assert!(matches!(
+            read_replacement(&mut upload).await,
+            Err(WebsiteError::ExceedsMaxLength)
+        ));
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn replaces_a_file_in_an_encrypted_paste() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let blobs = BlobStore {
+            location: directory.path().to_path_buf(),
+            ..Default::default()
+        };
+        let post_id = test_utils::create_paste(&database).await;
+        PastePostEntity::update(PastePostActiveModel {
+            id: Set(post_id),
+            encrypted: Set(true),
+            ..Default::default()
+        })
+        .exec(&database)
+        .await
+        .unwrap();
+        let original = blobs.write(b"first ciphertext").await.unwrap();
+        let location = original.location();
+        original.commit().await.unwrap();
+        test_utils::create_file(&database, post_id, "secret.txt", location).await;
+
+        let envelope_json = serde_json::to_vec(&envelope()).unwrap();
+        let ciphertext = [0xFF, 0x00, 0x13, 0x37];
+        let mut upload = upload(&[
+            ("encryption", None, &envelope_json),
+            ("file", Some("secret.txt"), &ciphertext),
+        ]);
+        let (envelope, mut field) = read_replacement(&mut upload).await.unwrap();
+        let encryption = check_encryption(true, envelope).unwrap();
+        let content = upload
+            .save_file(&mut field, &blobs, true, |_| Ok(()))
+            .await
+            .unwrap();
+        let file = find_file_by_name(&database, post_id, "secret.txt")
+            .await
+            .unwrap()
+            .unwrap();
+        let file_id = file.id;
+        store_replacement(
+            &database,
+            post_id,
+            file,
+            content,
+            encryption,
+            ANONYMOUS_USER_ID,
+            &UploadBudget::default(),
+        )
+        .await
+        .unwrap();
+
+        let file = find_file_by_name(&database, post_id, "secret.txt")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(file.encryption, Some(self::envelope()));
+        assert_eq!(std::fs::read(file.location.path()).unwrap(), ciphertext);
+        let revision = find_revision(&database, file_id, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(revision.revision, 2);
+        assert_eq!(revision.encryption, Some(self::envelope()));
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn concurrent_replacements_get_their_own_revisions() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let blobs = BlobStore {
+            location: directory.path().to_path_buf(),
+            ..Default::default()
+        };
+        let post_id = test_utils::create_paste(&database).await;
+        let original = blobs.write(b"first").await.unwrap();
+        let location = original.location();
+        original.commit().await.unwrap();
+        let file_id = test_utils::create_file(&database, post_id, "a.txt", location).await;
+        let file = find_file_by_name(&database, post_id, "a.txt")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let budget = UploadBudget::default();
+        let replace = |content: &'static [u8]| {
+            let (database, blobs, file, budget) = (&database, &blobs, file.clone(), &budget);
+            async move {
+                let content = blobs.write(content).await.unwrap();
+                store_replacement(
+                    database,
+                    post_id,
+                    file,
+                    content,
+                    None,
+                    ANONYMOUS_USER_ID,
+                    budget,
+                )
+                .await
+            }
+        };
+        let (second, third) = futures_util::join!(replace(b"second"), replace(b"third"));
+        second.unwrap();
+        third.unwrap();
+
+        let revisions: Vec<i32> = get_revisions(&database, file_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|revision| revision.revision)
+            .collect();
+        assert_eq!(revisions, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn moving_keeps_the_order_of_the_other_files() {
+        let mut files = vec!["a", "b", "c", "d"];
+        super::move_to(&mut files, 0, 2);
+        assert_eq!(files, ["b", "c", "a", "d"]);
+        super::move_to(&mut files, 3, 0);
+        assert_eq!(files, ["d", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn positions_outside_the_files_are_clamped() {
+        let mut files = vec!["a", "b", "c"];
+        super::move_to(&mut files, 1, -5);
+        assert_eq!(files, ["b", "a", "c"]);
+        super::move_to(&mut files, 0, 100);
+        assert_eq!(files, ["a", "c", "b"]);
+    }
+
+    #[test]
+    fn paste_names_can_not_be_empty() {
+        assert!(check_paste_name("Notes").is_ok());
+        for name in ["", "   ", "\t\n"] {
+            assert!(matches!(
+                check_paste_name(name),
+                Err(WebsiteError::EmptyPasteName)
+            ));
+        }
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn renaming_to_a_taken_name_is_a_conflict() {
+        let database = test_utils::database().await;
+        let post_id = test_utils::create_paste(&database).await;
+        let location = FileLocation::Local {
+            location: "missing.txt".into(),
+            size: 0,
+            hash: None,
+        };
+        test_utils::create_file(&database, post_id, "a.txt", location.clone()).await;
+        let b = test_utils::create_file(&database, post_id, "b.txt", location).await;
+
+        let rename = PasteFileActiveModel {
+            id: Set(b),
+            file_name: Set("a.txt".to_owned()),
+            ..Default::default()
+        };
+        let error = rename.update(&database).await.unwrap_err();
+        assert!(matches!(
+            super::file_name_taken(error),
+            WebsiteError::FileAlreadyExists
+        ));
+    }
+}
@@ -0,0 +1,373 @@
+//! View counts of pastes.
+//!
+//! Views are deduplicated per viewer in memory and written to the database by a background task.
+//! So recording a view does not slow down the request
+use std::{
+    future::{ready, Ready},
+    net::IpAddr,
+};
+
+use actix_web::{dev::Payload, get, web, web::Data, FromRequest, HttpRequest};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use common::visibility::HasVisibility;
+use digestible::Digestible;
+use entities::{
+    paste::database_helpers::{
+        find_post_by_str_id, get_daily_views, get_file_views, get_view_totals,
+    },
+    PasteViewActiveModel, PasteViewEntity,
+};
+use helper_macros::Response;
+use parking_lot::Mutex;
+use sea_orm::{prelude::DateTimeWithTimeZone, ActiveValue::Set, EntityTrait};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    config::client_address,
+    error::WebsiteError,
+    paste::PasteRules,
+    policy::{can_moderate, Actor, Resource},
+    responses::JsonResponse,
+    user::{Authentication, OptionalAuthentication},
+    DatabaseConnection, Error,
+};
+
+/// Views waiting to be written. Views are dropped if the database falls this far behind
+const QUEUE_SIZE: usize = 1024;
+/// The most views written in a single insert
+const MAX_BATCH_SIZE: usize = 128;
+/// The most tracked viewers. Views by more viewers are counted without deduplication until old entries are removed
+const MAX_TRACKED_VIEWERS: usize = 100_000;
+/// How often viewers outside the deduplication window are removed
+const EVICTION_INTERVAL_SECONDS: i64 = 60;
+const DEFAULT_STATS_DAYS: u32 = 30;
+const MAX_STATS_DAYS: u32 = 365;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Viewer {
+    User(i64),
+    Address(IpAddr),
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ViewKey {
+    post_id: i64,
+    file_id: Option<i64>,
+    viewer: Viewer,
+}
+#[derive(Debug)]
+struct View {
+    post_id: i64,
+    file_id: Option<i64>,
+    viewed_at: DateTime<Utc>,
+}
+/// When each viewer last had a view recorded
+struct SeenViewers {
+    viewers: ahash::HashMap<ViewKey, DateTime<Utc>>,
+    next_eviction: DateTime<Utc>,
+}
+/// Records the views of pastes
+pub struct PasteViews {
+    window: Duration,
+    seen: Mutex<SeenViewers>,
+    sender: mpsc::Sender<View>,
+}
+impl PasteViews {
+    fn new(window: Duration) -> (Self, mpsc::Receiver<View>) {
+        let (sender, receiver) = mpsc::channel::<View>(QUEUE_SIZE);
+        let views = Self {
+            window,
+            seen: Mutex::new(SeenViewers {
+                viewers: ahash::HashMap::default(),
+                next_eviction: Utc::now(),
+            }),
+            sender,
+        };
+        (views, receiver)
+    }
+    /// Starts the background task that writes views to the database
+    pub fn start(database: Data<DatabaseConnection>, rules: &PasteRules) -> Self {
+        let (views, mut receiver) = Self::new(rules.view_deduplication_window.duration);
+        actix_web::rt::spawn(async move {
+            while let Some(view) = receiver.recv().await {
+                let mut batch = vec![view];
+                while batch.len() < MAX_BATCH_SIZE {
+                    match receiver.try_recv() {
+                        Ok(view) => batch.push(view),
+                        Err(_) => break,
+                    }
+                }
+                write_views(database.as_ref(), batch).await;
+            }
+        });
+        views
+    }
+    /// Returns false if the viewer already had a view recorded within the window
+    fn should_record(&self, key: ViewKey, now: DateTime<Utc>) -> bool {
+        let mut seen = self.seen.lock();
+        // Removing old viewers walks the whole map. So it is only done once per interval
+        if seen.next_eviction <= now {
+            seen.viewers
+                .retain(|_, last_viewed| *last_viewed + self.window > now);
+            seen.next_eviction = now + Duration::seconds(EVICTION_INTERVAL_SECONDS);
+        }
+        if let Some(last_viewed) = seen.viewers.get(&key) {
+            if *last_viewed + self.window > now {
+                return false;
+            }
+        }
+        if seen.viewers.len() < MAX_TRACKED_VIEWERS || seen.viewers.contains_key(&key) {
+            seen.viewers.insert(key, now);
+        }
+        true
+    }
+    fn record(&self, post_id: i64, file_id: Option<i64>, viewer: Viewer, now: DateTime<Utc>) {
+        let key = ViewKey {
+            post_id,
+            file_id,
+            viewer,
+        };
+        if !self.should_record(key, now) {
+            return;
+        }
+        let view = View {
+            post_id,
+            file_id,
+            viewed_at: now,
+        };
+        if let Err(e) = self.sender.try_send(view) {
+            warn!("Dropped a view of paste {post_id}: {e}");
+        }
+    }
+}
+/// Writes a batch of views. If the batch fails each view is written on its own.
+/// So a paste deleted while its views were queued does not lose the views of other pastes
+async fn write_views(database: &DatabaseConnection, views: Vec<View>) {
+    let models: Vec<_> = views
+        .into_iter()
+        .map(|view| PasteViewActiveModel {
+            post_id: Set(view.post_id),
+            file_id: Set(view.file_id),
+            created: Set(view.viewed_at.into()),
+            ..Default::default()
+        })
+        .collect();
+    if PasteViewEntity::insert_many(models.clone())
+        .exec(database)
+        .await
+        .is_ok()
+    {
+        return;
+    }
+    for model in models {
+        if let Err(e) = PasteViewEntity::insert(model).exec(database).await {
+            error!("Failed to record a paste view: {e}");
+        }
+    }
+}
+
+/// Records views from a request
+pub struct ViewTracker {
+    address: Option<IpAddr>,
+    views: Data<PasteViews>,
+}
+impl FromRequest for ViewTracker {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let views = req
+            .app_data::<Data<PasteViews>>()
+            .expect("Unable to get PasteViews Ref")
+            .clone();
+        ready(Ok(Self {
+            address: client_address(req),
+            views,
+        }))
+    }
+}
+impl ViewTracker {
+    /// Records a view of the paste or one of its files. Views by the owner are not counted
+    pub fn record(
+        &self,
+        post_id: i64,
+        file_id: Option<i64>,
+        item: &impl HasVisibility,
+        auth: &OptionalAuthentication,
+    ) {
+        let viewer = match auth.as_ref() {
+            Some(user) if item.is_owner(user.id) => return,
+            Some(user) => Viewer::User(user.id),
+            None => match self.address {
+                Some(address) => Viewer::Address(address),
+                None => return,
+            },
+        };
+        self.views.record(post_id, file_id, viewer, Utc::now());
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StatsQuery {
+    /// The number of days included in `daily`. Defaults to 30. At most 365
+    pub days: Option<u32>,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible)]
+pub struct FileViewCount {
+    pub file_name: String,
+    pub views: u64,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible)]
+pub struct DailyViewCount {
+    /// In UTC
+    #[schema(value_type = String, format = Date)]
+    #[digestible(digest_with = digest_with_hash)]
+    pub day: NaiveDate,
+    pub views: u64,
+}
+#[derive(Debug, Serialize, ToSchema, Digestible, Response)]
+#[private]
+pub struct PasteStats {
+    /// Views of the paste and its files
+    pub total_views: u64,
+    /// Views of the paste itself
+    pub paste_views: u64,
+    #[schema(value_type = DateTime, nullable)]
+    #[serde(serialize_with = "common::serde_chrono::serialize_date_time_optional")]
+    #[digestible(digest_with = digest_with_hash)]
+    pub last_viewed: Option<DateTimeWithTimeZone>,
+    /// Sorted by the position of the file within the paste
+    pub files: Vec<FileViewCount>,
+    /// Days without views are not included
+    pub daily: Vec<DailyViewCount>,
+}
+
+/// The views of a paste. Only available to the owner and paste admins
+#[utoipa::path(get,
+    impl_for = stats,
+    path = "/api/paste/{id}/stats",
+    params(
+        ("id", description = "The id of the paste"),
+        StatsQuery
+    ),
+    responses(
+        (status = 200, description = "The views of the paste", body = PasteStats),
+        (status = 401, description = "You are not logged in"),
+        (status = 403, description = "You do not own the paste"),
+        (status = 404, description = "Paste Not Found")
+    ),
+security(
+("api_key" = [])
+)
+)]
+#[get("/{id}/stats")]
+pub async fn stats(
+    auth: Authentication,
+    id: web::Path<String>,
+    query: web::Query<StatsQuery>,
+    database: Data<DatabaseConnection>,
+) -> crate::Result<JsonResponse<PasteStats>> {
+    let post = find_post_by_str_id(database.as_ref(), id.into_inner())
+        .await?
+        .ok_or(WebsiteError::NotFound)?;
+    if !can_moderate(Resource::Paste, &Actor::from(&auth), &post) {
+        return Err(WebsiteError::Forbidden);
+    }
+    let days = query
+        .days
+        .unwrap_or(DEFAULT_STATS_DAYS)
+        .clamp(1, MAX_STATS_DAYS);
+    let since = (Utc::now() - Duration::days(days as i64)).into();
+
+    let totals = get_view_totals(database.as_ref(), post.id).await?;
+    let files = get_file_views(database.as_ref(), post.id).await?;
+    let daily = get_daily_views(database.as_ref(), post.id, since).await?;
+    Ok(JsonResponse::from(PasteStats {
+        total_views: totals.total_views as u64,
+        paste_views: totals.paste_views as u64,
+        last_viewed: totals.last_viewed,
+        files: files
+            .into_iter()
+            .map(|file| FileViewCount {
+                file_name: file.file_name,
+                views: file.views as u64,
+            })
+            .collect(),
+        daily: daily
+            .into_iter()
+            .map(|day| DailyViewCount {
+                day: day.day,
+                views: day.views as u64,
+            })
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use chrono::{Duration, Utc};
+
+    use super::{PasteViews, ViewKey, Viewer, EVICTION_INTERVAL_SECONDS, MAX_TRACKED_VIEWERS};
+
+    fn key(post_id: i64, viewer: Viewer) -> ViewKey {
+        ViewKey {
+            post_id,
+            file_id: None,
+            viewer,
+        }
+    }
+
+    #[test]
+    fn views_are_deduplicated_within_the_window() {
+        let (views, mut receiver) = PasteViews::new(Duration::minutes(30));
+        let viewer = Viewer::Address(Ipv4Addr::new(10, 0, 0, 1).into());
+        let now = Utc::now();
+        views.record(1, None, viewer.clone(), now);
+        views.record(1, None, viewer.clone(), now + Duration::minutes(29));
+        views.record(1, Some(2), viewer.clone(), now);
+        views.record(1, None, Viewer::User(1), now);
+        views.record(1, None, viewer, now + Duration::minutes(30));
+
+        let mut recorded = Vec::new();
+        while let Ok(view) = receiver.try_recv() {
+            recorded.push((view.post_id, view.file_id));
+        }
+        assert_eq!(
+            recorded,
+            vec![(1, None), (1, Some(2)), (1, None), (1, None)]
+        );
+    }
+
+    #[test]
+    fn old_viewers_are_evicted_periodically() {
+        let window = Duration::seconds(45);
+        let (views, _receiver) = PasteViews::new(window);
+        let now = Utc::now();
+        assert!(views.should_record(key(1, Viewer::User(1)), now));
+        // Not evicted until the interval has passed
+        let expired = now + window;
+        assert!(views.should_record(key(1, Viewer::User(2)), expired));
+        assert_eq!(views.seen.lock().viewers.len(), 2);
+
+        let next = now + Duration::seconds(EVICTION_INTERVAL_SECONDS);
+        assert!(views.should_record(key(1, Viewer::User(3)), next));
+        assert_eq!(views.seen.lock().viewers.len(), 2);
+    }
+
+    #[test]
+    fn viewers_past_the_limit_are_not_tracked() {
+        let (views, _receiver) = PasteViews::new(Duration::minutes(30));
+        let now = Utc::now();
+        for user in 0..MAX_TRACKED_VIEWERS {
+            assert!(views.should_record(key(1, Viewer::User(user as i64)), now));
+        }
+        let untracked = key(1, Viewer::User(-1));
+        assert!(views.should_record(untracked.clone(), now));
+        assert!(views.should_record(untracked, now));
+        assert_eq!(views.seen.lock().viewers.len(), MAX_TRACKED_VIEWERS);
+    }
+}
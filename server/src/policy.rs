@@ -0,0 +1,412 @@
+//! Who can view, edit and delete pastes, images and users.
+//!
+//! Routes ask this module instead of comparing ids and permissions themselves.
+//! Admin permissions only apply to logged in users. [SiteRules::anonymous_permissions](crate::config::SiteRules) can not make a request an admin
+use common::visibility::{HasVisibility, Visibility};
+use entities::user::{permissions::Permissions, user_responses::User};
+
+use crate::{
+    images::ImageRules,
+    user::{Authentication, OptionalAuthentication},
+};
+
+/// The kinds of items that are owned by a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    Paste,
+    Image,
+}
+impl Resource {
+    fn is_admin(self, permissions: &Permissions) -> bool {
+        match self {
+            Resource::Paste => permissions.is_paste_admin(),
+            Resource::Image => permissions.is_image_admin(),
+        }
+    }
+    fn view_public(self, permissions: &Permissions) -> bool {
+        match self {
+            Resource::Paste => permissions.paste_permissions.view_public,
+            Resource::Image => permissions.image_permissions.view_public,
+        }
+    }
+    fn create(self, permissions: &Permissions) -> bool {
+        match self {
+            Resource::Paste => permissions.paste_permissions.create,
+            Resource::Image => permissions.image_permissions.create,
+        }
+    }
+}
+/// The user making a request. Or an anonymous request with the anonymous permissions
+#[derive(Debug, Clone, Copy)]
+pub struct Actor<'a> {
+    user_id: Option<i64>,
+    permissions: &'a Permissions,
+}
+impl<'a> Actor<'a> {
+    pub fn new(user_id: Option<i64>, permissions: &'a Permissions) -> Self {
+        Self {
+            user_id,
+            permissions,
+        }
+    }
+    pub fn user_id(&self) -> Option<i64> {
+        self.user_id
+    }
+    pub fn is_anonymous(&self) -> bool {
+        self.user_id.is_none()
+    }
+    pub fn permissions(&self) -> &'a Permissions {
+        self.permissions
+    }
+    pub fn is_owner(&self, item: &impl HasVisibility) -> bool {
+        self.user_id.is_some_and(|user_id| item.is_owner(user_id))
+    }
+    pub fn is_admin(&self, resource: Resource) -> bool {
+        !self.is_anonymous() && resource.is_admin(self.permissions)
+    }
+    pub fn is_site_admin(&self) -> bool {
+        !self.is_anonymous() && self.permissions.admin
+    }
+}
+impl<'a> From<&'a User> for Actor<'a> {
+    fn from(user: &'a User) -> Self {
+        Self::new(Some(user.id), &user.permissions)
+    }
+}
+impl<'a> From<&'a Authentication> for Actor<'a> {
+    fn from(auth: &'a Authentication) -> Self {
+        Self::from(auth.as_ref())
+    }
+}
+impl<'a> From<&'a OptionalAuthentication> for Actor<'a> {
+    fn from(auth: &'a OptionalAuthentication) -> Self {
+        match auth.as_ref() {
+            Some(user) => Self::from(user),
+            None => Self::new(None, AsRef::<Permissions>::as_ref(auth)),
+        }
+    }
+}
+
+/// The result of [can_view]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewAccess {
+    Allowed,
+    Denied,
+    /// The item is password protected. The request needs the password or an access grant
+    RequiresPassword,
+}
+
+/// Owners and admins can view everything.
+/// Public and unlisted items need the view public permission. Private items are only visible to the users they are shared with
+pub fn can_view(resource: Resource, actor: &Actor, item: &impl HasVisibility) -> ViewAccess {
+    if actor.is_owner(item) || actor.is_admin(resource) {
+        return ViewAccess::Allowed;
+    }
+    let allowed = match item.visibility() {
+        Visibility::Public | Visibility::Unlisted => resource.view_public(actor.permissions),
+        Visibility::Private { .. } => actor
+            .user_id
+            .is_some_and(|user_id| item.is_visible_to(user_id)),
+        Visibility::Password { .. } => return ViewAccess::RequiresPassword,
+    };
+    if allowed {
+        ViewAccess::Allowed
+    } else {
+        ViewAccess::Denied
+    }
+}
+/// Only the owner can change an item. Admins can delete it but not change it
+pub fn can_edit(_resource: Resource, actor: &Actor, item: &impl HasVisibility) -> bool {
+    actor.is_owner(item)
+}
+/// The owner and admins can delete an item
+pub fn can_delete(resource: Resource, actor: &Actor, item: &impl HasVisibility) -> bool {
+    actor.is_owner(item) || actor.is_admin(resource)
+}
+/// The owner and admins can moderate the comments of an item and see its views
+pub fn can_moderate(resource: Resource, actor: &Actor, item: &impl HasVisibility) -> bool {
+    actor.is_owner(item) || actor.is_admin(resource)
+}
+pub fn can_create(resource: Resource, actor: &Actor) -> bool {
+    resource.create(actor.permissions)
+}
+
+/// Images follow [can_view]. Anonymous requests also need [ImageRules::show_without_login]
+pub fn can_view_image(actor: &Actor, rules: &ImageRules, item: &impl HasVisibility) -> bool {
+    if actor.is_anonymous() && !rules.show_without_login {
+        return false;
+    }
+    can_view(Resource::Image, actor, item) == ViewAccess::Allowed
+}
+/// Users can edit themselves. Other users can only be edited by admins and users with the edit user permission
+pub fn can_edit_user(actor: &Actor, user_id: i64) -> bool {
+    match actor.user_id {
+        Some(id) if id == user_id => true,
+        Some(_) => actor.permissions.admin || actor.permissions.user_permissions.edit_user,
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWNER: i64 = 1;
+    const SHARED: i64 = 2;
+    const OTHER: i64 = 3;
+    const ADMIN: i64 = 4;
+    const PASTE_ADMIN: i64 = 5;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Who {
+        Owner,
+        Shared,
+        Other,
+        Admin,
+        PasteAdmin,
+        Anonymous,
+    }
+    const EVERYONE: [Who; 6] = [
+        Who::Owner,
+        Who::Shared,
+        Who::Other,
+        Who::Admin,
+        Who::PasteAdmin,
+        Who::Anonymous,
+    ];
+
+    struct Actors {
+        user: Permissions,
+        admin: Permissions,
+        paste_admin: Permissions,
+        anonymous: Permissions,
+    }
+    impl Default for Actors {
+        fn default() -> Self {
+            let mut paste_admin = Permissions::default();
+            paste_admin.paste_permissions.admin = true;
+            Self {
+                user: Permissions::default(),
+                admin: Permissions::new_admin(),
+                paste_admin,
+                anonymous: Permissions::new_anonymous(),
+            }
+        }
+    }
+    impl Actors {
+        fn actor(&self, who: Who) -> Actor {
+            match who {
+                Who::Owner => Actor::new(Some(OWNER), &self.user),
+                Who::Shared => Actor::new(Some(SHARED), &self.user),
+                Who::Other => Actor::new(Some(OTHER), &self.user),
+                Who::Admin => Actor::new(Some(ADMIN), &self.admin),
+                Who::PasteAdmin => Actor::new(Some(PASTE_ADMIN), &self.paste_admin),
+                Who::Anonymous => Actor::new(None, &self.anonymous),
+            }
+        }
+    }
+
+    fn public() -> (i64, Visibility) {
+        (OWNER, Visibility::Public)
+    }
+    fn unlisted() -> (i64, Visibility) {
+        (OWNER, Visibility::Unlisted)
+    }
+    fn private() -> (i64, Visibility) {
+        (
+            OWNER,
+            Visibility::Private {
+                visible_to: vec![SHARED],
+            },
+        )
+    }
+    fn password() -> (i64, Visibility) {
+        (
+            OWNER,
+            Visibility::Password {
+                password: "hash".to_owned(),
+            },
+        )
+    }
+
+    #[test]
+    fn view_matrix() {
+        use ViewAccess::*;
+        let actors = Actors::default();
+        let matrix = [
+            (public(), [Allowed; 6]),
+            (unlisted(), [Allowed; 6]),
+            (
+                private(),
+                [Allowed, Allowed, Denied, Allowed, Allowed, Denied],
+            ),
+            (
+                password(),
+                [
+                    Allowed,
+                    RequiresPassword,
+                    RequiresPassword,
+                    Allowed,
+                    Allowed,
+                    RequiresPassword,
+                ],
+            ),
+        ];
+        for (item, expected) in matrix {
+            for (who, expected) in EVERYONE.into_iter().zip(expected) {
+                let actor = actors.actor(who);
+                assert_eq!(
+                    can_view(Resource::Paste, &actor, &item),
+                    expected,
+                    "{who:?} viewing {:?}",
+                    item.1
+                );
+            }
+        }
+    }
+    #[test]
+    fn view_public_permission() {
+        let mut actors = Actors::default();
+        actors.anonymous.paste_permissions.view_public = false;
+        actors.user.paste_permissions.view_public = false;
+        let anonymous = actors.actor(Who::Anonymous);
+        let other = actors.actor(Who::Other);
+        let owner = actors.actor(Who::Owner);
+        assert_eq!(
+            can_view(Resource::Paste, &anonymous, &public()),
+            ViewAccess::Denied
+        );
+        assert_eq!(
+            can_view(Resource::Paste, &other, &unlisted()),
+            ViewAccess::Denied
+        );
+        assert_eq!(
+            can_view(Resource::Paste, &owner, &public()),
+            ViewAccess::Allowed
+        );
+        // Images have their own permission
+        assert_eq!(
+            can_view(Resource::Image, &anonymous, &public()),
+            ViewAccess::Allowed
+        );
+    }
+    #[test]
+    fn edit_matrix() {
+        let actors = Actors::default();
+        for item in [public(), unlisted(), private(), password()] {
+            for who in EVERYONE {
+                let actor = actors.actor(who);
+                assert_eq!(
+                    can_edit(Resource::Paste, &actor, &item),
+                    who == Who::Owner,
+                    "{who:?} editing {:?}",
+                    item.1
+                );
+            }
+        }
+    }
+    #[test]
+    fn delete_matrix() {
+        let actors = Actors::default();
+        for item in [public(), unlisted(), private(), password()] {
+            for who in EVERYONE {
+                let actor = actors.actor(who);
+                let expected = matches!(who, Who::Owner | Who::Admin | Who::PasteAdmin);
+                assert_eq!(
+                    can_delete(Resource::Paste, &actor, &item),
+                    expected,
+                    "{who:?} deleting {:?}",
+                    item.1
+                );
+            }
+        }
+    }
+    #[test]
+    fn paste_admins_are_not_image_admins() {
+        let actors = Actors::default();
+        let paste_admin = actors.actor(Who::PasteAdmin);
+        let admin = actors.actor(Who::Admin);
+        assert!(!can_delete(Resource::Image, &paste_admin, &private()));
+        assert_eq!(
+            can_view(Resource::Image, &paste_admin, &private()),
+            ViewAccess::Denied
+        );
+        assert!(can_delete(Resource::Image, &admin, &private()));
+    }
+    #[test]
+    fn anonymous_is_never_an_admin() {
+        let mut actors = Actors::default();
+        actors.anonymous = Permissions::new_admin();
+        let anonymous = actors.actor(Who::Anonymous);
+        assert!(!can_delete(Resource::Paste, &anonymous, &public()));
+        assert_eq!(
+            can_view(Resource::Paste, &anonymous, &private()),
+            ViewAccess::Denied
+        );
+        assert!(!can_edit_user(&anonymous, OWNER));
+    }
+    #[test]
+    fn anonymous_does_not_own_anonymous_pastes() {
+        let actors = Actors::default();
+        let anonymous = actors.actor(Who::Anonymous);
+        let item = (entities::user::ANONYMOUS_USER_ID, Visibility::Public);
+        assert!(!can_edit(Resource::Paste, &anonymous, &item));
+        assert!(!can_delete(Resource::Paste, &anonymous, &item));
+    }
+    #[test]
+    fn create() {
+        let actors = Actors::default();
+        assert!(can_create(Resource::Paste, &actors.actor(Who::Other)));
+        assert!(!can_create(Resource::Paste, &actors.actor(Who::Anonymous)));
+    }
+    #[test]
+    fn images_without_login() {
+        let actors = Actors::default();
+        let hidden = ImageRules {
+            show_without_login: false,
+            ..ImageRules::default()
+        };
+        let anonymous = actors.actor(Who::Anonymous);
+        assert!(can_view_image(
+            &anonymous,
+            &ImageRules::default(),
+            &public()
+        ));
+        assert!(!can_view_image(&anonymous, &hidden, &public()));
+        assert!(can_view_image(
+            &actors.actor(Who::Other),
+            &hidden,
+            &public()
+        ));
+    }
+    #[test]
+    fn moderate_matrix() {
+        let actors = Actors::default();
+        for item in [public(), unlisted(), private(), password()] {
+            for who in EVERYONE {
+                let actor = actors.actor(who);
+                let expected = matches!(who, Who::Owner | Who::Admin | Who::PasteAdmin);
+                assert_eq!(
+                    can_moderate(Resource::Paste, &actor, &item),
+                    expected,
+                    "{who:?} moderating {:?}",
+                    item.1
+                );
+            }
+        }
+        assert!(!can_moderate(
+            Resource::Image,
+            &actors.actor(Who::PasteAdmin),
+            &public()
+        ));
+    }
+    #[test]
+    fn edit_users() {
+        let actors = Actors::default();
+        let other = actors.actor(Who::Other);
+        assert!(can_edit_user(&other, OTHER));
+        assert!(!can_edit_user(&other, OWNER));
+        assert!(can_edit_user(&actors.actor(Who::Admin), OWNER));
+        assert!(!can_edit_user(&actors.actor(Who::PasteAdmin), OWNER));
+    }
+}
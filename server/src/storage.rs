@@ -0,0 +1,838 @@
+//! Finds differences between the database and the files on disk. And repairs them.
+//!
+//! - Orphans. Files in the storage directories that no row references. Such as the files of deleted pastes
+//! - Missing files. Rows whose file does not exist
+//! - Size mismatches. Rows whose recorded size is not the size of the file
+//! - Empty directories. Such as the directory of a paste whose files were orphans
+//! - Unreadable files. Such as files the server does not have permission to read
+//!
+//! The server checks every [StorageCheckConfig::interval]. It can also be run with `nitro_share_admin check-storage`
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use actix_web::web::Data;
+use chrono::Duration;
+use common::file_location::FileLocation;
+use config_types::chrono_types::duration::{ConfigDuration, Unit};
+use entities::{
+    BlobEntity, ImageFileActiveModel, ImageFileEntity, ImageVariantActiveModel, ImageVariantEntity,
+    PasteFileActiveModel, PasteFileEntity, PasteFileRevisionActiveModel, PasteFileRevisionEntity,
+};
+use sea_orm::{prelude::*, ActiveValue::Set, QuerySelect};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+use crate::{blob::BlobStore, images::ImageRules, paste::PasteRules, DatabaseConnection};
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StorageCheckConfig {
+    /// How often the server checks storage. None disables the check
+    pub interval: Option<ConfigDuration>,
+    /// Delete orphans and correct sizes. Otherwise problems are only logged
+    pub repair: bool,
+    /// Files modified more recently are never orphans. Uploads write the file before the row that references it
+    ///
+    /// Empty directories modified more recently are also kept. An upload may be about to write into them
+    pub min_orphan_age: ConfigDuration,
+}
+impl Default for StorageCheckConfig {
+    fn default() -> Self {
+        Self {
+            interval: Some(ConfigDuration {
+                duration: Duration::days(1),
+                unit: Unit::Days,
+            }),
+            repair: false,
+            min_orphan_age: ConfigDuration {
+                duration: Duration::minutes(60),
+                unit: Unit::Minutes,
+            },
+        }
+    }
+}
+
+/// The table of a row that references a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FileTable {
+    PasteFile,
+    PasteRevision,
+    Image,
+    ImageVariant,
+}
+impl Display for FileTable {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileTable::PasteFile => write!(f, "paste file"),
+            FileTable::PasteRevision => write!(f, "paste file revision"),
+            FileTable::Image => write!(f, "image"),
+            FileTable::ImageVariant => write!(f, "image variant"),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum StorageProblem {
+    /// A file that nothing references
+    Orphan { path: PathBuf, size: u64 },
+    /// A row references a file that does not exist. Can not be repaired
+    MissingFile {
+        table: FileTable,
+        id: i64,
+        path: PathBuf,
+    },
+    /// The recorded size is not the size of the file
+    SizeMismatch {
+        table: FileTable,
+        id: i64,
+        path: PathBuf,
+        recorded: u64,
+        actual: u64,
+    },
+    /// A directory without any files. Left behind after deleting orphans
+    EmptyDirectory { path: PathBuf },
+    /// The file or directory could not be read. Can not be repaired
+    Unreadable { path: PathBuf, error: String },
+}
+impl Display for StorageProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageProblem::Orphan { path, size } => {
+                write!(f, "Orphaned file {path:?} ({size} bytes)")
+            }
+            StorageProblem::MissingFile { table, id, path } => {
+                write!(f, "The file of {table} {id} is missing. Expected at {path:?}")
+            }
+            StorageProblem::SizeMismatch {
+                table,
+                id,
+                path,
+                recorded,
+                actual,
+            } => write!(
+                f,
+                "The size of {table} {id} is recorded as {recorded} bytes. {path:?} is {actual} bytes"
+            ),
+            StorageProblem::EmptyDirectory { path } => write!(f, "Empty directory {path:?}"),
+            StorageProblem::Unreadable { path, error } => {
+                write!(f, "Failed to read {path:?}: {error}")
+            }
+        }
+    }
+}
+#[derive(Debug, Default, Serialize)]
+pub struct StorageReport {
+    /// The number of rows whose file was checked
+    pub files_checked: u64,
+    pub problems: Vec<StorageProblem>,
+}
+impl StorageReport {
+    /// The space that deleting the orphans frees
+    pub fn orphaned_bytes(&self) -> u64 {
+        self.problems
+            .iter()
+            .map(|problem| match problem {
+                StorageProblem::Orphan { size, .. } => *size,
+                _ => 0,
+            })
+            .sum()
+    }
+}
+#[derive(Debug, Default, Serialize)]
+pub struct RepairReport {
+    pub repaired: u64,
+    pub failed: u64,
+}
+#[derive(Debug, Error)]
+pub enum StorageCheckError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("Failed to read {path:?}: {error}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        error: std::io::Error,
+    },
+}
+fn io_error(path: &Path, error: std::io::Error) -> StorageCheckError {
+    StorageCheckError::Io {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+fn unreadable(path: &Path, error: std::io::Error) -> StorageProblem {
+    StorageProblem::Unreadable {
+        path: path.to_path_buf(),
+        error: error.to_string(),
+    }
+}
+
+struct FileRow {
+    table: FileTable,
+    id: i64,
+    location: FileLocation,
+    /// Revisions also record the size in their own column
+    size: Option<i64>,
+}
+pub struct StorageChecker<'a> {
+    database: &'a DatabaseConnection,
+    blobs: &'a BlobStore,
+    /// Every file in these directories should be referenced by a row
+    directories: Vec<PathBuf>,
+    min_orphan_age: Duration,
+}
+impl<'a> StorageChecker<'a> {
+    pub fn new(
+        database: &'a DatabaseConnection,
+        paste_rules: &PasteRules,
+        image_rules: &ImageRules,
+        blobs: &'a BlobStore,
+        config: &StorageCheckConfig,
+    ) -> Self {
+        Self {
+            database,
+            blobs,
+            directories: vec![
+                paste_rules.location.clone(),
+                image_rules.location.clone(),
+                blobs.location.clone(),
+            ],
+            min_orphan_age: config.min_orphan_age.duration,
+        }
+    }
+    /// Compares every row that references a file with the file. Then looks for files that nothing references
+    ///
+    /// Files that can not be read are reported as [StorageProblem::Unreadable]. The rest are still checked
+    pub async fn check(&self) -> Result<StorageReport, StorageCheckError> {
+        let mut report = StorageReport::default();
+        let rows = self.file_rows().await?;
+        let mut referenced = check_rows(rows, &mut report).await;
+        // Blobs without references are deleted by BlobStore::collect_garbage. Not here
+        let hashes: Vec<String> = BlobEntity::find()
+            .select_only()
+            .column(entities::blob::Column::Hash)
+            .into_tuple()
+            .all(self.database)
+            .await?;
+        for hash in hashes {
+            if let Ok(path) = tokio::fs::canonicalize(self.blobs.blob_path(&hash)).await {
+                referenced.insert(path);
+            }
+        }
+        self.find_orphans(&referenced, &mut report).await;
+        Ok(report)
+    }
+    /// Reports the files and empty directories that are not referenced and older than the minimum orphan age
+    async fn find_orphans(&self, referenced: &HashSet<PathBuf>, report: &mut StorageReport) {
+        let orphaned_before = SystemTime::now()
+            - self
+                .min_orphan_age
+                .to_std()
+                .unwrap_or(std::time::Duration::ZERO);
+        let is_old = |path: &Path, metadata: &std::fs::Metadata| match metadata.modified() {
+            Ok(modified) => Ok(modified <= orphaned_before),
+            Err(e) => Err(unreadable(path, e)),
+        };
+        let mut seen = HashSet::new();
+        for directory in &self.directories {
+            let walked = walk(directory).await;
+            report.problems.extend(walked.unreadable);
+            for (path, metadata) in walked.files {
+                if referenced.contains(&path) || !seen.insert(path.clone()) {
+                    continue;
+                }
+                match is_old(&path, &metadata) {
+                    Ok(true) => report.problems.push(StorageProblem::Orphan {
+                        size: metadata.len(),
+                        path,
+                    }),
+                    Ok(false) => {}
+                    Err(problem) => report.problems.push(problem),
+                }
+            }
+            for (path, metadata) in walked.empty_directories {
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+                match is_old(&path, &metadata) {
+                    Ok(true) => report
+                        .problems
+                        .push(StorageProblem::EmptyDirectory { path }),
+                    Ok(false) => {}
+                    Err(problem) => report.problems.push(problem),
+                }
+            }
+        }
+    }
+    /// Deletes the orphans and empty directories and corrects the sizes in the report.
+    /// Missing and unreadable files are left alone
+    pub async fn repair(&self, report: &StorageReport) -> RepairReport {
+        let mut result = RepairReport::default();
+        for problem in &report.problems {
+            let repaired = match problem {
+                StorageProblem::Orphan { path, .. } => self.delete_orphan(path).await,
+                StorageProblem::SizeMismatch {
+                    table, id, actual, ..
+                } => self.correct_size(*table, *id, *actual).await,
+                StorageProblem::EmptyDirectory { path } => self.delete_empty_directory(path).await,
+                StorageProblem::MissingFile { .. } | StorageProblem::Unreadable { .. } => continue,
+            };
+            match repaired {
+                Ok(()) => result.repaired += 1,
+                Err(e) => {
+                    warn!("Failed to repair. {problem}: {e}");
+                    result.failed += 1;
+                }
+            }
+        }
+        result
+    }
+    async fn file_rows(&self) -> Result<Vec<FileRow>, DbErr> {
+        let files: Vec<(i64, FileLocation)> = PasteFileEntity::find()
+            .select_only()
+            .column(entities::paste::FileColumn::Id)
+            .column(entities::paste::FileColumn::Location)
+            .into_tuple()
+            .all(self.database)
+            .await?;
+        let revisions: Vec<(i64, FileLocation, i64)> = PasteFileRevisionEntity::find()
+            .select_only()
+            .column(entities::paste::RevisionColumn::Id)
+            .column(entities::paste::RevisionColumn::Location)
+            .column(entities::paste::RevisionColumn::Size)
+            .into_tuple()
+            .all(self.database)
+            .await?;
+        let images: Vec<(i64, FileLocation)> = ImageFileEntity::find()
+            .select_only()
+            .column(entities::image::image::Column::Id)
+            .column(entities::image::image::Column::File)
+            .into_tuple()
+            .all(self.database)
+            .await?;
+        let variants: Vec<(i64, FileLocation)> = ImageVariantEntity::find()
+            .select_only()
+            .column(entities::image::VariantColumn::Id)
+            .column(entities::image::VariantColumn::File)
+            .into_tuple()
+            .all(self.database)
+            .await?;
+
+        let files = files.into_iter().map(|(id, location)| FileRow {
+            table: FileTable::PasteFile,
+            id,
+            location,
+            size: None,
+        });
+        let revisions = revisions.into_iter().map(|(id, location, size)| FileRow {
+            table: FileTable::PasteRevision,
+            id,
+            location,
+            size: Some(size),
+        });
+        let images = images.into_iter().map(|(id, location)| FileRow {
+            table: FileTable::Image,
+            id,
+            location,
+            size: None,
+        });
+        let variants = variants.into_iter().map(|(id, location)| FileRow {
+            table: FileTable::ImageVariant,
+            id,
+            location,
+            size: None,
+        });
+        Ok(files
+            .chain(revisions)
+            .chain(images)
+            .chain(variants)
+            .collect())
+    }
+    /// Deletes the file. The directories it leaves empty are found by the next check.
+    /// Once they are older than the minimum orphan age
+    async fn delete_orphan(&self, path: &Path) -> Result<(), StorageCheckError> {
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(path, e)),
+            _ => Ok(()),
+        }
+    }
+    /// Fails if a file was written into the directory since the check. The storage directories are never removed
+    async fn delete_empty_directory(&self, path: &Path) -> Result<(), StorageCheckError> {
+        for directory in &self.directories {
+            if tokio::fs::canonicalize(directory)
+                .await
+                .is_ok_and(|root| root == path)
+            {
+                return Ok(());
+            }
+        }
+        match tokio::fs::remove_dir(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(path, e)),
+            _ => Ok(()),
+        }
+    }
+    async fn correct_size(
+        &self,
+        table: FileTable,
+        id: i64,
+        size: u64,
+    ) -> Result<(), StorageCheckError> {
+        match table {
+            FileTable::PasteFile => {
+                let Some(file) = PasteFileEntity::find_by_id(id).one(self.database).await? else {
+                    return Ok(());
+                };
+                let location = with_size(&file.location, size);
+                let mut file: PasteFileActiveModel = file.into();
+                file.location = Set(location);
+                file.update(self.database).await?;
+            }
+            FileTable::PasteRevision => {
+                let Some(revision) = PasteFileRevisionEntity::find_by_id(id)
+                    .one(self.database)
+                    .await?
+                else {
+                    return Ok(());
+                };
+                let location = with_size(&revision.location, size);
+                let mut revision: PasteFileRevisionActiveModel = revision.into();
+                revision.location = Set(location);
+                revision.size = Set(size as i64);
+                revision.update(self.database).await?;
+            }
+            FileTable::Image => {
+                let Some(image) = ImageFileEntity::find_by_id(id).one(self.database).await? else {
+                    return Ok(());
+                };
+                let location = with_size(&image.file, size);
+                let mut image: ImageFileActiveModel = image.into();
+                image.file = Set(location);
+                image.update(self.database).await?;
+            }
+            FileTable::ImageVariant => {
+                let Some(variant) = ImageVariantEntity::find_by_id(id)
+                    .one(self.database)
+                    .await?
+                else {
+                    return Ok(());
+                };
+                let location = with_size(&variant.file, size);
+                let mut variant: ImageVariantActiveModel = variant.into();
+                variant.file = Set(location);
+                variant.update(self.database).await?;
+            }
+        }
+        Ok(())
+    }
+}
+fn with_size(location: &FileLocation, size: u64) -> FileLocation {
+    let mut location = location.clone();
+    location.set_file_size(size as usize);
+    location
+}
+/// Compares the rows with their files. Adding the size mismatches, missing and unreadable files to the report
+///
+/// # Returns
+/// The canonical paths of the files. Relative and absolute paths to the same file match
+async fn check_rows(rows: Vec<FileRow>, report: &mut StorageReport) -> HashSet<PathBuf> {
+    let mut referenced = HashSet::new();
+    for row in rows {
+        report.files_checked += 1;
+        let path = row.location.path();
+        let metadata = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                report.problems.push(StorageProblem::MissingFile {
+                    table: row.table,
+                    id: row.id,
+                    path: path.to_path_buf(),
+                });
+                continue;
+            }
+            Err(e) => {
+                report.problems.push(unreadable(path, e));
+                continue;
+            }
+        };
+        let actual = metadata.len();
+        let recorded = [
+            Some(row.location.file_size() as u64),
+            row.size.map(|size| size as u64),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|recorded| *recorded != actual);
+        if let Some(recorded) = recorded {
+            report.problems.push(StorageProblem::SizeMismatch {
+                table: row.table,
+                id: row.id,
+                path: path.to_path_buf(),
+                recorded,
+                actual,
+            });
+        }
+        match tokio::fs::canonicalize(path).await {
+            Ok(canonical) => {
+                referenced.insert(canonical);
+            }
+            Err(e) => report.problems.push(unreadable(path, e)),
+        }
+    }
+    referenced
+}
+#[derive(Default)]
+struct WalkedDirectory {
+    files: Vec<(PathBuf, std::fs::Metadata)>,
+    /// Directories without any entries. Not including the directory that was walked
+    empty_directories: Vec<(PathBuf, std::fs::Metadata)>,
+    unreadable: Vec<StorageProblem>,
+}
+/// Every file in the directory and its subdirectories. Nothing if the directory does not exist
+///
+/// Symbolic links are not followed. Entries that can not be read are skipped and reported
+async fn walk(directory: &Path) -> WalkedDirectory {
+    let mut walked = WalkedDirectory::default();
+    let root = match tokio::fs::canonicalize(directory).await {
+        Ok(root) => root,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return walked,
+        Err(e) => {
+            walked.unreadable.push(unreadable(directory, e));
+            return walked;
+        }
+    };
+    let mut directories = vec![root.clone()];
+    while let Some(directory) = directories.pop() {
+        let mut entries = match tokio::fs::read_dir(&directory).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                walked.unreadable.push(unreadable(&directory, e));
+                continue;
+            }
+        };
+        let mut is_empty = true;
+        loop {
+            let entry = match entries.next_entry().await {
+                Ok(Some(entry)) => entry,
+                Ok(None) => break,
+                Err(e) => {
+                    is_empty = false;
+                    walked.unreadable.push(unreadable(&directory, e));
+                    break;
+                }
+            };
+            is_empty = false;
+            let path = entry.path();
+            let metadata = match entry.metadata().await {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    walked.unreadable.push(unreadable(&path, e));
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                directories.push(path);
+            } else if metadata.is_file() {
+                walked.files.push((path, metadata));
+            }
+        }
+        if is_empty && directory != root {
+            match tokio::fs::symlink_metadata(&directory).await {
+                Ok(metadata) => walked.empty_directories.push((directory, metadata)),
+                Err(e) => walked.unreadable.push(unreadable(&directory, e)),
+            }
+        }
+    }
+    walked
+}
+
+/// Starts a background task that checks storage every [StorageCheckConfig::interval].
+///
+/// Problems are repaired if [StorageCheckConfig::repair] is set. Otherwise they are logged
+pub fn start_checker(
+    database: Data<DatabaseConnection>,
+    config: StorageCheckConfig,
+    paste_rules: Data<PasteRules>,
+    image_rules: Data<ImageRules>,
+    blobs: Data<BlobStore>,
+) {
+    let Some(period) = config
+        .interval
+        .as_ref()
+        .and_then(|interval| interval.duration.to_std().ok())
+    else {
+        return;
+    };
+    actix_web::rt::spawn(async move {
+        let mut interval = actix_web::rt::time::interval(period);
+        // The first tick is immediate. Storage is checked one period after starting
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let checker = StorageChecker::new(
+                database.as_ref(),
+                paste_rules.as_ref(),
+                image_rules.as_ref(),
+                blobs.as_ref(),
+                &config,
+            );
+            let report = match checker.check().await {
+                Ok(report) => report,
+                Err(e) => {
+                    error!("Failed to check storage: {e}");
+                    continue;
+                }
+            };
+            if report.problems.is_empty() {
+                info!("Checked {} files. No problems found", report.files_checked);
+                continue;
+            }
+            if !config.repair {
+                for problem in &report.problems {
+                    warn!("{problem}");
+                }
+                warn!(
+                    "Found {} storage problems. Run `nitro_share_admin check-storage --repair` or enable repair to fix them",
+                    report.problems.len()
+                );
+                continue;
+            }
+            let repaired = checker.repair(&report).await;
+            info!(
+                "Repaired {} of {} storage problems. Orphans used {} bytes",
+                repaired.repaired,
+                report.problems.len(),
+                report.orphaned_bytes()
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+    };
+
+    use chrono::Duration;
+    use common::file_location::FileLocation;
+    use entities::PasteFileEntity;
+    use sea_orm::EntityTrait;
+
+    use super::{check_rows, FileRow, FileTable, StorageChecker, StorageProblem, StorageReport};
+    use crate::{blob::BlobStore, test_utils, DatabaseConnection};
+
+    /// A checker of a single directory. Only checks that read the database need a connection
+    fn checker<'a>(
+        database: &'a DatabaseConnection,
+        blobs: &'a BlobStore,
+        directory: &Path,
+        min_orphan_age: Duration,
+    ) -> StorageChecker<'a> {
+        StorageChecker {
+            database,
+            blobs,
+            directories: vec![directory.to_path_buf()],
+            min_orphan_age,
+        }
+    }
+    async fn orphans(checker: &StorageChecker<'_>, referenced: &HashSet<PathBuf>) -> StorageReport {
+        let mut report = StorageReport::default();
+        checker.find_orphans(referenced, &mut report).await;
+        report
+    }
+
+    #[actix_web::test]
+    async fn unreferenced_files_are_orphans() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("1")).unwrap();
+        std::fs::write(root.join("1/referenced.txt"), "referenced").unwrap();
+        std::fs::write(root.join("1/orphan.txt"), "orphan").unwrap();
+        std::fs::create_dir_all(root.join("2/nested")).unwrap();
+        std::fs::write(root.join("2/nested/orphan.txt"), "nested").unwrap();
+
+        let database = DatabaseConnection::Disconnected;
+        let blobs = BlobStore::default();
+        let checker = checker(&database, &blobs, &root, Duration::zero());
+        let referenced = HashSet::from([root.join("1/referenced.txt")]);
+        let report = orphans(&checker, &referenced).await;
+
+        let mut found: Vec<_> = report.problems.clone();
+        found.sort_by_key(|problem| format!("{problem}"));
+        assert_eq!(
+            found,
+            vec![
+                StorageProblem::Orphan {
+                    path: root.join("1/orphan.txt"),
+                    size: 6,
+                },
+                StorageProblem::Orphan {
+                    path: root.join("2/nested/orphan.txt"),
+                    size: 6,
+                },
+            ]
+        );
+        assert_eq!(report.orphaned_bytes(), 12);
+    }
+
+    #[actix_web::test]
+    async fn recent_files_are_not_orphans() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        std::fs::write(root.join("uploading.txt"), "new").unwrap();
+        std::fs::create_dir_all(root.join("empty")).unwrap();
+
+        let database = DatabaseConnection::Disconnected;
+        let blobs = BlobStore::default();
+        let checker = checker(&database, &blobs, &root, Duration::minutes(60));
+        let report = orphans(&checker, &HashSet::new()).await;
+        assert!(report.problems.is_empty(), "{:?}", report.problems);
+    }
+
+    #[actix_web::test]
+    async fn empty_directories_are_removed_after_their_orphans() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("1")).unwrap();
+        std::fs::write(root.join("1/orphan.txt"), "orphan").unwrap();
+
+        let database = DatabaseConnection::Disconnected;
+        let blobs = BlobStore::default();
+        let checker = checker(&database, &blobs, &root, Duration::zero());
+        let report = orphans(&checker, &HashSet::new()).await;
+        assert_eq!(report.problems.len(), 1);
+        let repaired = checker.repair(&report).await;
+        assert_eq!(repaired.repaired, 1);
+        // Deleting the orphan does not remove its directory
+        assert!(root.join("1").exists());
+
+        let report = orphans(&checker, &HashSet::new()).await;
+        assert_eq!(
+            report.problems,
+            vec![StorageProblem::EmptyDirectory {
+                path: root.join("1")
+            }]
+        );
+        checker.repair(&report).await;
+        assert!(!root.join("1").exists());
+        // The storage directory itself is kept
+        assert!(root.exists());
+        assert!(orphans(&checker, &HashSet::new()).await.problems.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn directories_written_to_after_the_check_are_kept() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("1")).unwrap();
+
+        let database = DatabaseConnection::Disconnected;
+        let blobs = BlobStore::default();
+        let checker = checker(&database, &blobs, &root, Duration::zero());
+        let report = orphans(&checker, &HashSet::new()).await;
+        std::fs::write(root.join("1/upload.txt"), "upload").unwrap();
+        let repaired = checker.repair(&report).await;
+        assert_eq!(repaired.failed, 1);
+        assert!(root.join("1/upload.txt").exists());
+    }
+
+    #[actix_web::test]
+    async fn rows_are_compared_with_their_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        std::fs::write(root.join("correct.txt"), "12345").unwrap();
+        std::fs::write(root.join("changed.txt"), "12345").unwrap();
+        let row = |id: i64, name: &str, size: usize| FileRow {
+            table: FileTable::PasteFile,
+            id,
+            location: FileLocation::new_local(root.join(name), size),
+            size: None,
+        };
+        let rows = vec![
+            row(1, "correct.txt", 5),
+            row(2, "changed.txt", 3),
+            row(3, "missing.txt", 5),
+        ];
+        let mut report = StorageReport::default();
+        let referenced = check_rows(rows, &mut report).await;
+
+        assert_eq!(report.files_checked, 3);
+        assert_eq!(
+            report.problems,
+            vec![
+                StorageProblem::SizeMismatch {
+                    table: FileTable::PasteFile,
+                    id: 2,
+                    path: root.join("changed.txt"),
+                    recorded: 3,
+                    actual: 5,
+                },
+                StorageProblem::MissingFile {
+                    table: FileTable::PasteFile,
+                    id: 3,
+                    path: root.join("missing.txt"),
+                },
+            ]
+        );
+        assert_eq!(
+            referenced,
+            HashSet::from([root.join("correct.txt"), root.join("changed.txt")])
+        );
+    }
+
+    #[actix_web::test]
+    #[ignore = "Needs a PostgreSQL database. See test_utils"]
+    async fn size_mismatches_are_repaired() {
+        let database = test_utils::database().await;
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path().canonicalize().unwrap();
+        let path = root.join("changed.txt");
+        std::fs::write(&path, "12345").unwrap();
+        let post_id = test_utils::create_paste(&database).await;
+        let file_id = test_utils::create_file(
+            &database,
+            post_id,
+            "changed.txt",
+            FileLocation::new_local(path.clone(), 3),
+        )
+        .await;
+
+        let blobs = BlobStore::default();
+        let checker = checker(&database, &blobs, &root, Duration::zero());
+        let report = checker.check().await.unwrap();
+        let mismatches: Vec<_> = report
+            .problems
+            .iter()
+            .filter(|problem| {
+                matches!(problem, StorageProblem::SizeMismatch { path: mismatched, .. } if *mismatched == path)
+            })
+            .cloned()
+            .collect();
+        // The file and its revision
+        assert_eq!(mismatches.len(), 2);
+        let repaired = checker
+            .repair(&StorageReport {
+                files_checked: 0,
+                problems: mismatches,
+            })
+            .await;
+        assert_eq!(repaired.repaired, 2);
+
+        let file = PasteFileEntity::find_by_id(file_id)
+            .one(&database)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(file.location.file_size(), 5);
+        let report = checker.check().await.unwrap();
+        assert!(!report.problems.iter().any(|problem| matches!(
+            problem,
+            StorageProblem::SizeMismatch { path: mismatched, .. } if *mismatched == path
+        )));
+    }
+}
@@ -0,0 +1,108 @@
+//! Helpers for tests that need a database.
+//!
+//! Those tests are ignored by default. Run them with `cargo test -- --ignored`
+//! and the URL of an empty PostgreSQL database in [DATABASE_ENV]
+use common::{file_location::FileLocation, visibility::Visibility};
+use entities::{
+    paste::database_helpers::create_revision, user::ANONYMOUS_USER_ID, ImagePostActiveModel,
+    ImagePostEntity, PasteFileActiveModel, PasteFileEntity, PastePostActiveModel, PastePostEntity,
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{ActiveValue::Set, Database, EntityTrait, NotSet};
+use uuid::Uuid;
+
+use crate::DatabaseConnection;
+
+pub const DATABASE_ENV: &str = "NITRO_SHARE_TEST_DATABASE";
+
+/// Connects to the test database and runs the migrations
+pub async fn database() -> DatabaseConnection {
+    let url = std::env::var(DATABASE_ENV).unwrap_or_else(|_| panic!("{DATABASE_ENV} is not set"));
+    let database = Database::connect(url)
+        .await
+        .expect("Failed to connect to the test database");
+    Migrator::up(&database, None)
+        .await
+        .expect("Failed to run the migrations");
+    database
+}
+/// Creates an anonymous paste without any files
+///
+/// # Returns
+/// The id of the paste
+pub async fn create_paste(database: &DatabaseConnection) -> i64 {
+    let post = PastePostActiveModel {
+        id: NotSet,
+        id_str: Set(Uuid::new_v4().simple().to_string()),
+        user_id: Set(ANONYMOUS_USER_ID),
+        name: Set("Test Paste".to_owned()),
+        tags: Set(vec![]),
+        description: Set(String::new()),
+        visibility: Set(Visibility::Public),
+        expires_at: Set(None),
+        burn_after_read: Set(false),
+        encrypted: Set(false),
+        delete_token: Set(None),
+        forked_from: Set(None),
+        deleted_at: Set(None),
+        last_updated: NotSet,
+        created: NotSet,
+    };
+    PastePostEntity::insert(post)
+        .exec(database)
+        .await
+        .expect("Failed to create the paste")
+        .last_insert_id
+}
+/// Adds a file with a single revision to a paste
+///
+/// # Returns
+/// The id of the file
+pub async fn create_file(
+    database: &DatabaseConnection,
+    post_id: i64,
+    file_name: &str,
+    location: FileLocation,
+) -> i64 {
+    let file = PasteFileActiveModel {
+        id: NotSet,
+        post_id: Set(post_id),
+        file_name: Set(file_name.to_owned()),
+        file_type: Set(Default::default()),
+        location: Set(location.clone()),
+        encryption: Set(None),
+        position: Set(0),
+        created: NotSet,
+    };
+    let file_id = PasteFileEntity::insert(file)
+        .exec(database)
+        .await
+        .expect("Failed to create the file")
+        .last_insert_id;
+    create_revision(database, file_id, 1, location, None, ANONYMOUS_USER_ID)
+        .await
+        .expect("Failed to create the revision");
+    file_id
+}
+/// Creates an anonymous image post without any images
+///
+/// # Returns
+/// The id of the post
+pub async fn create_image_post(database: &DatabaseConnection) -> i64 {
+    let post = ImagePostActiveModel {
+        id: NotSet,
+        id_str: Set(Uuid::new_v4().simple().to_string()),
+        user_id: Set(ANONYMOUS_USER_ID),
+        name: Set("Test Images".to_owned()),
+        tags: Set(vec![]),
+        description: Set(String::new()),
+        deleted_at: Set(None),
+        last_updated: NotSet,
+        created: NotSet,
+    };
+    ImagePostEntity::insert(post)
+        .exec(database)
+        .await
+        .expect("Failed to create the image post")
+        .last_insert_id
+}
@@ -0,0 +1,279 @@
+//! Streams uploads directly to their storage location.
+//!
+//! Limits are checked as each chunk arrives so an upload is aborted as soon as it exceeds them.
+use std::path::Path;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::web::Payload;
+use bytes::{Bytes, BytesMut};
+use futures_util::{Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use simdutf8::compat::{from_utf8, Utf8Error};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+use crate::{
+    blob::{BlobStore, StagedBlob},
+    error::WebsiteError,
+};
+
+/// The limits of a multipart request. Sizes are in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultipartLimits {
+    /// The total size of all fields in the request
+    pub max_request_size: usize,
+    /// The size of a single file
+    pub max_file_size: usize,
+    /// The number of files in the request
+    pub max_files: usize,
+    /// The size of a field that is not a file. Such as the details of the upload
+    pub max_field_size: usize,
+    /// Files must be valid UTF-8
+    pub require_utf8: bool,
+}
+/// A multipart request that enforces [MultipartLimits] while it is read
+pub struct MultipartUpload {
+    multipart: Multipart,
+    limits: MultipartLimits,
+    received: usize,
+    files: usize,
+}
+impl MultipartUpload {
+    pub fn new(multipart: Multipart, limits: MultipartLimits) -> Self {
+        Self {
+            multipart,
+            limits,
+            received: 0,
+            files: 0,
+        }
+    }
+    /// The next field of the request. Any unread content of the previous field is skipped
+    pub async fn next_field(&mut self) -> crate::Result<Option<Field>> {
+        self.multipart.try_next().await.map_err(WebsiteError::from)
+    }
+    fn receive(&mut self, size: usize) -> crate::Result<()> {
+        self.received += size;
+        if self.received > self.limits.max_request_size {
+            return Err(WebsiteError::RequestTooLarge);
+        }
+        Ok(())
+    }
+    /// Reads a JSON field
+    pub async fn read_json<T: DeserializeOwned>(&mut self, field: &mut Field) -> crate::Result<T> {
+        let mut content = Vec::new();
+        while let Some(chunk) = field.try_next().await? {
+            self.receive(chunk.len())?;
+            if content.len() + chunk.len() > self.limits.max_field_size {
+                return Err(WebsiteError::ExceedsMaxLength);
+            }
+            content.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice(&content).map_err(WebsiteError::InvalidJson)
+    }
+    /// Writes a file field to a temporary file in the blob store.
+    ///
+    /// `check` is called with the number of bytes written so far after every chunk.
+    /// If it returns an error the upload is aborted.
+    ///
+    /// `binary` files such as ciphertext are never checked for UTF-8.
+    ///
+    /// Nothing is left behind if the upload fails
+    pub async fn save_file(
+        &mut self,
+        field: &mut Field,
+        blobs: &BlobStore,
+        binary: bool,
+        check: impl Fn(usize) -> crate::Result<()>,
+    ) -> crate::Result<StagedBlob> {
+        self.files += 1;
+        if self.files > self.limits.max_files {
+            return Err(WebsiteError::TooManyFiles);
+        }
+        let limits = self.limits;
+        let received = &mut self.received;
+        let require_utf8 = limits.require_utf8 && !binary;
+        save_stream(field, blobs, require_utf8, |chunk, size| {
+            *received += chunk;
+            if *received > limits.max_request_size {
+                return Err(WebsiteError::RequestTooLarge);
+            }
+            if size > limits.max_file_size {
+                return Err(WebsiteError::ExceedsMaxLength);
+            }
+            check(size)
+        })
+        .await
+    }
+}
+/// Writes a stream of chunks to a temporary file in the blob store. Hashing the content as it arrives
+///
+/// `on_chunk` is called with the size of the chunk and the number of bytes written so far.
+/// Before the chunk is written. If it returns an error the upload is aborted.
+///
+/// Nothing is left behind if the upload fails
+pub async fn save_stream<S, E>(
+    stream: S,
+    blobs: &BlobStore,
+    require_utf8: bool,
+    on_chunk: impl FnMut(usize, usize) -> crate::Result<()>,
+) -> crate::Result<StagedBlob>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    WebsiteError: From<E>,
+{
+    let location = blobs.temp_path();
+    if let Some(parent) = location.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let mut hasher = Sha256::new();
+    match write_stream(stream, &location, &mut hasher, require_utf8, on_chunk).await {
+        Ok(size) => Ok(blobs.staged(location, hasher.finalize(), size)),
+        Err(e) => {
+            if let Err(remove_error) = tokio::fs::remove_file(&location).await {
+                if remove_error.kind() != std::io::ErrorKind::NotFound {
+                    tracing::warn!("Failed to remove failed upload {location:?}: {remove_error}");
+                }
+            }
+            Err(e)
+        }
+    }
+}
+async fn write_stream<S, E>(
+    mut stream: S,
+    location: &Path,
+    hasher: &mut Sha256,
+    require_utf8: bool,
+    mut on_chunk: impl FnMut(usize, usize) -> crate::Result<()>,
+) -> crate::Result<usize>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    WebsiteError: From<E>,
+{
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(location)
+        .await?;
+    let mut validator = require_utf8.then(Utf8Validator::default);
+    let mut size = 0;
+    while let Some(chunk) = stream.try_next().await? {
+        size += chunk.len();
+        on_chunk(chunk.len(), size)?;
+        if let Some(validator) = validator.as_mut() {
+            validator.update(&chunk)?;
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await?;
+    }
+    if let Some(validator) = validator {
+        validator.finish()?;
+    }
+    file.flush().await?;
+    Ok(size)
+}
+/// Reads a whole request body into memory. Failing if it is larger than `limit`
+pub async fn read_body(mut payload: Payload, limit: usize) -> crate::Result<BytesMut> {
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.try_next().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(WebsiteError::RequestTooLarge);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+/// Errors that leave the request in a state where the remaining fields can not be read
+pub fn aborts_request(error: &WebsiteError) -> bool {
+    matches!(
+        error,
+        WebsiteError::MultipartError(_)
+            | WebsiteError::RequestTooLarge
+            | WebsiteError::TooManyFiles
+    )
+}
+
+/// Validates UTF-8 that arrives in chunks.
+///
+/// A character split between two chunks is held until the next chunk arrives
+#[derive(Debug, Default)]
+pub struct Utf8Validator {
+    incomplete: Vec<u8>,
+}
+impl Utf8Validator {
+    pub fn update(&mut self, mut chunk: &[u8]) -> Result<(), Utf8Error> {
+        if !self.incomplete.is_empty() {
+            while let Some((byte, rest)) = chunk.split_first() {
+                self.incomplete.push(*byte);
+                chunk = rest;
+                match from_utf8(&self.incomplete) {
+                    Ok(_) => {
+                        self.incomplete.clear();
+                        break;
+                    }
+                    Err(e) if e.error_len().is_none() => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+            if !self.incomplete.is_empty() {
+                return Ok(());
+            }
+        }
+        match from_utf8(chunk) {
+            Ok(_) => Ok(()),
+            Err(e) if e.error_len().is_none() => {
+                self.incomplete.extend_from_slice(&chunk[e.valid_up_to()..]);
+                Ok(())
+            }
+            Err(e) => Err(e),
+        }
+    }
+    /// Fails if the content ended in the middle of a character
+    pub fn finish(self) -> Result<(), Utf8Error> {
+        from_utf8(&self.incomplete).map(|_| ())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::Utf8Validator;
+
+    fn validate(chunks: &[&[u8]]) -> bool {
+        let mut validator = Utf8Validator::default();
+        chunks.iter().all(|chunk| validator.update(chunk).is_ok()) && validator.finish().is_ok()
+    }
+
+    #[test]
+    fn characters_split_between_chunks() {
+        // Two, three and four byte characters
+        let content = "é€😀 ok".as_bytes();
+        for split in 0..=content.len() {
+            let (first, second) = content.split_at(split);
+            assert!(validate(&[first, second]), "split at {split}");
+        }
+    }
+    #[test]
+    fn one_byte_per_chunk() {
+        let content = "😀€é".as_bytes();
+        let chunks: Vec<&[u8]> = content.chunks(1).collect();
+        assert!(validate(&chunks));
+    }
+    #[test]
+    fn empty_chunks() {
+        let emoji = "😀".as_bytes();
+        assert!(validate(&[&[], &emoji[..2], &[], &emoji[2..], &[]]));
+    }
+    #[test]
+    fn invalid_continuation_in_next_chunk() {
+        let emoji = "😀".as_bytes();
+        assert!(!validate(&[&emoji[..2], b"a"]));
+    }
+    #[test]
+    fn invalid_byte() {
+        assert!(!validate(&[b"valid", &[0xFF], b"valid"]));
+    }
+    #[test]
+    fn ends_in_the_middle_of_a_character() {
+        let emoji = "😀".as_bytes();
+        assert!(!validate(&[b"text", &emoji[..3]]));
+    }
+}
@@ -1,12 +1,21 @@
 use actix_web::{get, http::StatusCode, web, web::Data, HttpResponse};
-use entities::{user::user_responses::User, AuthTokenActiveModel, AuthTokenEntity, AuthTokenModel};
+use digestible::Digestible;
+use entities::{
+    user::{database_helpers::get_storage_usage, user_responses::User},
+    AuthTokenActiveModel, AuthTokenEntity, AuthTokenModel,
+};
+use helper_macros::Response;
 use sea_orm::{ActiveValue, ActiveValue::Set, EntityTrait, InsertResult};
 use serde::{Deserialize, Serialize};
 use tracing::{error, info, warn};
+use utoipa::ToSchema;
 
 use crate::{
+    config::SiteRules,
+    policy::{can_edit_user, Actor},
     responses::{JsonOrError, JsonResponse},
     user::{
+        quota::{StorageLimits, UserStorage},
         session::{DynSessionManager, Session, SessionManager},
         Authentication,
     },
@@ -25,7 +34,7 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     impl_for=me,
     path = "/api/me",
     responses(
-        (status = 200, description = "You are Logged In", body = User),
+        (status = 200, description = "You are Logged In", body = MeResponse),
         (status = 401, description = "You are not logged in")
     ),
     security(
@@ -33,8 +42,26 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     )
 )]
 #[get("")]
-pub async fn me(auth: Authentication) -> JsonResponse<User> {
-    JsonResponse::from(Into::<User>::into(auth))
+pub async fn me(
+    auth: Authentication,
+    database: Data<DatabaseConnection>,
+    site_rules: Data<SiteRules>,
+) -> crate::Result<JsonResponse<MeResponse>> {
+    let user: User = auth.into();
+    let usage = get_storage_usage(database.as_ref(), user.id).await?;
+    let limits = StorageLimits::from(site_rules.quota_for(&user.permissions));
+    Ok(JsonResponse::from(MeResponse {
+        user,
+        storage: UserStorage { usage, limits },
+    }))
+}
+/// The logged in user and their storage usage
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema, Digestible, Response)]
+#[private]
+pub struct MeResponse {
+    #[serde(flatten)]
+    pub user: User,
+    pub storage: UserStorage,
 }
 #[get("/session")]
 pub async fn get_session(auth: Authentication) -> JsonOrError<Session> {
@@ -117,7 +144,7 @@ pub async fn revoke_token(
         .one(database.as_ref())
         .await?;
     if let Some(token) = token {
-        if token.user_id != auth.as_ref().id {
+        if !can_edit_user(&Actor::from(&auth), token.user_id) {
             warn!("User tried to revoke token that doesn't belong to them");
             return Ok(HttpResponse::BadRequest().finish());
         }
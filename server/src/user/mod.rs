@@ -2,6 +2,7 @@ pub mod me;
 pub mod middleware;
 pub mod profile;
 pub mod public;
+pub mod quota;
 pub mod session;
 
 use std::fmt::Debug;
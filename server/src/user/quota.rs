@@ -0,0 +1,163 @@
+use digestible::Digestible;
+use entities::user::{
+    database_helpers::{get_storage_usage, lock_user},
+    user_responses::StorageUsage,
+};
+use sea_orm::DatabaseTransaction;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{config::StorageQuota, error::WebsiteError, paste::PasteRules};
+
+/// The storage limits of a user in bytes. None is unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema, Digestible)]
+pub struct StorageLimits {
+    pub max_paste_storage: Option<u64>,
+    pub max_pastes: Option<u64>,
+    pub max_image_storage: Option<u64>,
+    pub max_images: Option<u64>,
+}
+impl From<&StorageQuota> for StorageLimits {
+    fn from(quota: &StorageQuota) -> Self {
+        Self {
+            max_paste_storage: quota
+                .max_paste_storage
+                .as_ref()
+                .map(|size| size.get_as_bytes() as u64),
+            max_pastes: quota.max_pastes,
+            max_image_storage: quota
+                .max_image_storage
+                .as_ref()
+                .map(|size| size.get_as_bytes() as u64),
+            max_images: quota.max_images,
+        }
+    }
+}
+/// The storage used by a user and their limits
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema, Digestible)]
+pub struct UserStorage {
+    pub usage: StorageUsage,
+    pub limits: StorageLimits,
+}
+
+/// Checks that the user can create another paste
+pub fn check_paste_count(quota: &StorageQuota, usage: &StorageUsage) -> crate::Result<()> {
+    match quota.max_pastes {
+        Some(max_pastes) if usage.pastes as u64 >= max_pastes => Err(WebsiteError::QuotaExceeded),
+        _ => Ok(()),
+    }
+}
+/// Checks that the user can create another image post
+pub fn check_image_count(quota: &StorageQuota, usage: &StorageUsage) -> crate::Result<()> {
+    match quota.max_images {
+        Some(max_images) if usage.images as u64 >= max_images => Err(WebsiteError::QuotaExceeded),
+        _ => Ok(()),
+    }
+}
+
+/// Tracks how many bytes can still be uploaded during a request
+///
+/// The budget is based on the usage from before the request.
+/// So concurrent uploads by the same user can each fit in it. [UploadBudget::recheck] catches that when the upload is stored
+#[derive(Debug, Clone, Default)]
+pub struct UploadBudget {
+    /// Remaining bytes before the paste exceeds `max_paste_size`
+    paste_remaining: Option<u64>,
+    /// Remaining bytes before the user exceeds their quota
+    quota_remaining: Option<u64>,
+    /// The user the upload counts towards
+    user_id: i64,
+    /// The storage quota of the user
+    max_storage: Option<u64>,
+    /// Image storage is counted separately from paste storage
+    images: bool,
+}
+impl UploadBudget {
+    /// # Parameters
+    /// - `user_id` - The user that `usage` belongs to
+    /// - `paste_size` - The size of the files already in the paste
+    pub fn for_paste(
+        rules: &PasteRules,
+        quota: &StorageQuota,
+        user_id: i64,
+        usage: &StorageUsage,
+        paste_size: u64,
+    ) -> Self {
+        let max_storage = quota
+            .max_paste_storage
+            .as_ref()
+            .map(|max| max.get_as_bytes() as u64);
+        Self {
+            paste_remaining: rules
+                .max_paste_size
+                .as_ref()
+                .map(|max| (max.get_as_bytes() as u64).saturating_sub(paste_size)),
+            quota_remaining: max_storage.map(|max| max.saturating_sub(usage.paste_bytes as u64)),
+            user_id,
+            max_storage,
+            images: false,
+        }
+    }
+    /// Images are only limited by the user's quota. The size of each image is limited by the upload
+    pub fn for_images(quota: &StorageQuota, user_id: i64, usage: &StorageUsage) -> Self {
+        let max_storage = quota
+            .max_image_storage
+            .as_ref()
+            .map(|max| max.get_as_bytes() as u64);
+        Self {
+            paste_remaining: None,
+            quota_remaining: max_storage.map(|max| max.saturating_sub(usage.image_bytes as u64)),
+            user_id,
+            max_storage,
+            images: true,
+        }
+    }
+    /// Checks that `size` bytes fit in the budget without reserving them
+    pub fn check(&self, size: u64) -> crate::Result<()> {
+        if self
+            .paste_remaining
+            .is_some_and(|remaining| size > remaining)
+        {
+            return Err(WebsiteError::ExceedsMaxPasteSize);
+        }
+        if self
+            .quota_remaining
+            .is_some_and(|remaining| size > remaining)
+        {
+            return Err(WebsiteError::QuotaExceeded);
+        }
+        Ok(())
+    }
+    /// Reserves `size` bytes. Nothing is reserved if either limit would be exceeded
+    pub fn reserve(&mut self, size: u64) -> crate::Result<()> {
+        self.check(size)?;
+        for remaining in [&mut self.paste_remaining, &mut self.quota_remaining]
+            .into_iter()
+            .flatten()
+        {
+            *remaining -= size;
+        }
+        Ok(())
+    }
+    /// Checks the quota again from inside the transaction that stores the upload.
+    ///
+    /// The user's row is locked until the transaction ends. So concurrent uploads are checked one at a time.
+    /// The usage includes the rows inserted by the transaction.
+    /// The number of pastes and images is only checked before a post is created
+    pub async fn recheck(&self, transaction: &DatabaseTransaction) -> crate::Result<()> {
+        let Some(max_storage) = self.max_storage else {
+            return Ok(());
+        };
+        lock_user(transaction, self.user_id).await?;
+        let usage = get_storage_usage(transaction, self.user_id).await?;
+        let used = if self.images {
+            usage.image_bytes
+        } else {
+            usage.paste_bytes
+        };
+        if used.max(0) as u64 > max_storage {
+            return Err(WebsiteError::QuotaExceeded);
+        }
+        Ok(())
+    }
+}